@@ -0,0 +1,254 @@
+use crate::error::BuildError;
+use crate::substates::SubstateData;
+use crate::syscall::Syscall;
+use crate::transaction::Instruction;
+use crate::transaction::Transaction;
+use crate::types::*;
+
+/// Transaction builder, the counterpart of `TxLowLevelBuilder` in the engine
+///
+/// Instructions are appended in order. Substate updates have to be grouped and each group
+/// terminated with `end()`, which is checked by `build()`.
+#[derive(Default)]
+pub struct TransactionBuilder {
+    instructions: Vec<Instruction>,
+    up_count: usize,
+    group_size: usize,
+    group_count: usize,
+    error: Option<BuildError>,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an instruction, tracking `UP` substates and instruction groups.
+    pub fn instruction(&mut self, inst: Instruction) -> &mut Self {
+        if let Instruction::UP(_) = inst {
+            self.up_count += 1;
+            if self.up_count > SubstateIndex::MAX as usize + 1 {
+                self.error.get_or_insert(BuildError::TooManySubstates);
+            }
+        }
+        if inst.is_substate_update() {
+            self.group_size += 1;
+        } else if let Instruction::END = inst {
+            if self.group_size == 0 {
+                self.error.get_or_insert(BuildError::EmptyGroup);
+            }
+            self.group_size = 0;
+            self.group_count += 1;
+        }
+        self.instructions.push(inst);
+        self
+    }
+
+    pub fn header(&mut self, flags: TxFlags) -> &mut Self {
+        self.instruction(Instruction::HEADER(Header {
+            version: Header::VERSION,
+            flags,
+        }))
+    }
+
+    pub fn syscall(&mut self, syscall: Syscall) -> &mut Self {
+        self.instruction(Instruction::SYSCALL(syscall))
+    }
+
+    pub fn syscall_fee_put(&mut self, amount: U256) -> &mut Self {
+        self.syscall(Syscall::FeeReservePut(amount))
+    }
+
+    pub fn syscall_fee_take(&mut self, amount: U256) -> &mut Self {
+        self.syscall(Syscall::FeeReserveTake(amount))
+    }
+
+    pub fn syscall_readdr_claim(&mut self, symbol: &[u8]) -> &mut Self {
+        self.syscall(Syscall::ReaddrClaim(Bytes::from_slice(symbol)))
+    }
+
+    /// Boots up a substate, returning its index for `local_read` and `local_down`.
+    pub fn up(&mut self, substate: SubstateData) -> SubstateIndex {
+        let index = self.up_count as SubstateIndex;
+        self.instruction(Instruction::UP(substate));
+        index
+    }
+
+    pub fn read(&mut self, substate_id: SubstateId) -> &mut Self {
+        self.instruction(Instruction::READ(substate_id))
+    }
+
+    pub fn local_read(&mut self, index: SubstateIndex) -> &mut Self {
+        self.instruction(Instruction::LREAD(index))
+    }
+
+    pub fn virtual_read(&mut self, parent: SubstateId, key: VirtualKey) -> &mut Self {
+        self.instruction(Instruction::VREAD(VirtualSubstateID {
+            parent,
+            key: key.to_bytes(),
+        }))
+    }
+
+    pub fn local_virtual_read(&mut self, parent: SubstateIndex, key: VirtualKey) -> &mut Self {
+        self.instruction(Instruction::LVREAD(LocalVirtualSubstateID {
+            parent,
+            key: key.to_bytes(),
+        }))
+    }
+
+    pub fn down(&mut self, substate_id: SubstateId) -> &mut Self {
+        self.instruction(Instruction::DOWN(substate_id))
+    }
+
+    pub fn local_down(&mut self, index: SubstateIndex) -> &mut Self {
+        self.instruction(Instruction::LDOWN(index))
+    }
+
+    pub fn virtual_down(&mut self, parent: SubstateId, key: VirtualKey) -> &mut Self {
+        self.instruction(Instruction::VDOWN(VirtualSubstateID {
+            parent,
+            key: key.to_bytes(),
+        }))
+    }
+
+    pub fn local_virtual_down(&mut self, parent: SubstateIndex, key: VirtualKey) -> &mut Self {
+        self.instruction(Instruction::LVDOWN(LocalVirtualSubstateID {
+            parent,
+            key: key.to_bytes(),
+        }))
+    }
+
+    pub fn read_index(&mut self, prefix: IndexPrefix) -> &mut Self {
+        self.instruction(Instruction::READINDEX(prefix))
+    }
+
+    pub fn down_index(&mut self, prefix: IndexPrefix) -> &mut Self {
+        self.instruction(Instruction::DOWNINDEX(prefix))
+    }
+
+    pub fn end(&mut self) -> &mut Self {
+        self.instruction(Instruction::END)
+    }
+
+    pub fn message(&mut self, msg: &[u8]) -> &mut Self {
+        self.instruction(Instruction::MSG(Bytes::from_slice(msg)))
+    }
+
+    pub fn sig(&mut self, signature: Signature) -> &mut Self {
+        self.instruction(Instruction::SIG(signature))
+    }
+
+    /// Returns the number of substates booted up so far.
+    pub fn up_count(&self) -> usize {
+        self.up_count
+    }
+
+    /// Builds the transaction, leaving the builder empty on success and untouched on failure.
+    pub fn build(&mut self) -> Result<Transaction, BuildError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        if self.group_size != 0 {
+            return Err(BuildError::MissingEnd);
+        }
+        if self.group_count == 0 {
+            return Err(BuildError::NoStateUpdates);
+        }
+        Ok(Transaction::new(std::mem::take(self).instructions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::TransactionBuilder;
+    use crate::error::BuildError;
+    use crate::reader::Reader;
+    use crate::samples::samples;
+    use crate::substates::SubstateData;
+    use crate::transaction::Instruction;
+    use crate::transaction::Transaction;
+    use crate::types::*;
+
+    const TOKENS: &str = concat!(
+        "0600",
+        "0402c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        "01",
+        "00000000000000000000000000000000000000000000000000000000000003e8"
+    );
+
+    fn tokens() -> SubstateData {
+        let raw = hex::decode(TOKENS).unwrap();
+        SubstateData::from_buffer(&mut Reader::new(&raw)).unwrap()
+    }
+
+    fn substate_id(index: u32) -> SubstateId {
+        SubstateId {
+            hash: Hash { raw: [7; 32] },
+            index,
+        }
+    }
+
+    #[test]
+    fn replays_samples() {
+        for (name, raw, tx) in samples() {
+            let mut builder = TransactionBuilder::new();
+            for inst in tx.into_instructions() {
+                builder.instruction(inst);
+            }
+            assert_eq!(builder.build().unwrap().to_bytes(), raw, "{}", name);
+        }
+    }
+
+    #[test]
+    fn tracks_local_indices() {
+        let mut builder = TransactionBuilder::new();
+        builder
+            .header(TxFlags::empty())
+            .down(substate_id(0))
+            .syscall_fee_put(U256 { raw: 100u64.into() });
+        assert_eq!(builder.up(tokens()), 0);
+        builder.end();
+        assert_eq!(builder.up(tokens()), 1);
+        builder.local_down(0).end().message(b"hi");
+        assert_eq!(builder.up_count(), 2);
+
+        let tx = builder.build().unwrap();
+        assert_eq!(tx.instructions().len(), 9);
+        assert!(matches!(tx.instructions()[6], Instruction::LDOWN(0)));
+        let parsed = Transaction::from_bytes(tx.to_bytes()).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", tx));
+    }
+
+    #[test]
+    fn builds_fluently() {
+        let tx = TransactionBuilder::new()
+            .header(TxFlags::empty())
+            .down(substate_id(0))
+            .end()
+            .build()
+            .unwrap();
+        assert_eq!(tx.instructions().len(), 3);
+
+        let mut builder = TransactionBuilder::new();
+        builder.down(substate_id(0)).end().build().unwrap();
+        assert_eq!(builder.up_count(), 0);
+        assert_eq!(builder.build().unwrap_err(), BuildError::NoStateUpdates);
+    }
+
+    #[test]
+    fn rejects_invalid_groups() {
+        let mut builder = TransactionBuilder::new();
+        builder.down(substate_id(0));
+        assert_eq!(builder.build().unwrap_err(), BuildError::MissingEnd);
+        let tx = builder.end().build().unwrap();
+        assert_eq!(tx.instructions().len(), 2);
+
+        let mut builder = TransactionBuilder::new();
+        builder.down(substate_id(0)).end().end();
+        assert_eq!(builder.build().unwrap_err(), BuildError::EmptyGroup);
+
+        let mut builder = TransactionBuilder::new();
+        builder.header(TxFlags::empty()).message(b"hi");
+        assert_eq!(builder.build().unwrap_err(), BuildError::NoStateUpdates);
+    }
+}
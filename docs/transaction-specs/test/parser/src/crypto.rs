@@ -0,0 +1,288 @@
+use k256::ecdsa;
+use k256::ecdsa::signature::hazmat::PrehashVerifier;
+use k256::ecdsa::RecoveryId;
+use k256::ecdsa::SigningKey;
+use k256::ecdsa::VerifyingKey;
+
+use crate::error::SignatureError;
+use crate::transaction::Instruction;
+use crate::transaction::Transaction;
+use crate::types::Hash;
+use crate::types::PublicKey;
+use crate::types::Signature;
+
+/// ECDSA private key
+pub struct PrivateKey {
+    key: SigningKey,
+}
+
+impl PrivateKey {
+    pub fn from_bytes(raw: &[u8; 32]) -> Result<Self, SignatureError> {
+        let key =
+            SigningKey::from_bytes(raw.into()).map_err(|_| SignatureError::InvalidPrivateKey)?;
+        Ok(Self { key })
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_verifying_key(self.key.verifying_key())
+    }
+
+    /// Signs a hash with a deterministic nonce (RFC 6979), normalised to low-S like the engine.
+    pub fn sign(&self, hash: &Hash) -> Signature {
+        let (signature, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(&hash.raw)
+            .expect("Signing a 32-byte hash should not fail");
+        let (r, s) = signature.split_bytes();
+        Signature {
+            v: recovery_id.to_byte(),
+            r: r.into(),
+            s: s.into(),
+        }
+    }
+}
+
+impl Signature {
+    /// Verifies the signature of a hash against a public key.
+    ///
+    /// Like the engine, signatures with a high `s` value are accepted.
+    pub fn verify(&self, key: &PublicKey, hash: &Hash) -> bool {
+        let verifying_key = match VerifyingKey::from_sec1_bytes(&key.raw) {
+            Ok(verifying_key) => verifying_key,
+            Err(_) => return false,
+        };
+        match self.to_ecdsa() {
+            Ok((signature, _)) => verifying_key.verify_prehash(&hash.raw, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Recovers the public key which signed a hash, using the recovery byte `v`.
+    pub fn recover(&self, hash: &Hash) -> Result<PublicKey, SignatureError> {
+        let (signature, recovery_id) = self.to_ecdsa()?;
+        let verifying_key = VerifyingKey::recover_from_prehash(&hash.raw, &signature, recovery_id)
+            .map_err(|_| SignatureError::InvalidSignature)?;
+        Ok(PublicKey::from_verifying_key(&verifying_key))
+    }
+
+    /// Converts to a low-S signature, adjusting the recovery ID accordingly.
+    fn to_ecdsa(&self) -> Result<(ecdsa::Signature, RecoveryId), SignatureError> {
+        let signature = ecdsa::Signature::from_scalars(self.r, self.s)
+            .map_err(|_| SignatureError::InvalidSignature)?;
+        let recovery_id =
+            RecoveryId::from_byte(self.v).ok_or(SignatureError::InvalidRecoveryId(self.v))?;
+        Ok(match signature.normalize_s() {
+            Some(normalized) => {
+                let recovery_id =
+                    RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced());
+                (normalized, recovery_id)
+            }
+            None => (signature, recovery_id),
+        })
+    }
+}
+
+impl PublicKey {
+    pub(crate) fn from_verifying_key(key: &VerifyingKey) -> Self {
+        let point = key.to_encoded_point(true);
+        let mut raw = [0u8; 33];
+        raw.copy_from_slice(point.as_bytes());
+        Self { raw }
+    }
+}
+
+impl Transaction {
+    /// Signs the transaction and appends the `SIG` instruction.
+    pub fn sign(self, key: &PrivateKey) -> Result<Transaction, SignatureError> {
+        if self.signature().is_some() {
+            return Err(SignatureError::AlreadySigned);
+        }
+        let mut bytes = match self.bytes() {
+            Some(bytes) => bytes.to_vec(),
+            None => self.to_bytes(),
+        };
+        let sig = Instruction::SIG(key.sign(&Hash::sha256_twice(&bytes)));
+        sig.write_to(&mut bytes);
+        let mut instructions = self.into_instructions();
+        instructions.push(sig);
+        Ok(Transaction::with_bytes(instructions, bytes))
+    }
+
+    /// Recovers the public key which signed the transaction.
+    pub fn recover_signer(&self) -> Result<PublicKey, SignatureError> {
+        let signature = self.signature().ok_or(SignatureError::MissingSignature)?;
+        let hash = self
+            .signed_payload_hash()
+            .ok_or(SignatureError::MissingSignature)?;
+        let key = signature.recover(&hash)?;
+        if !signature.verify(&key, &hash) {
+            return Err(SignatureError::InvalidSignature);
+        }
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use k256::ecdsa;
+
+    use crate::crypto::PrivateKey;
+    use crate::error::SignatureError;
+    use crate::samples::samples;
+    use crate::transaction::Instruction;
+    use crate::transaction::Transaction;
+    use crate::types::Hash;
+    use crate::types::PublicKey;
+    use crate::types::Signature;
+
+    fn load(name: &str) -> Transaction {
+        let contents = fs::read_to_string(format!("../samples/{}.txt", name)).unwrap();
+        Transaction::from_bytes(hex::decode(contents).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn recovers_sample_signers() {
+        for (name, _, tx) in samples() {
+            if tx.signature().is_none() {
+                continue;
+            }
+            let key = tx.recover_signer().unwrap();
+            let hash = tx.signed_payload_hash().unwrap();
+            assert!(tx.signature().unwrap().verify(&key, &hash), "{}", name);
+        }
+    }
+
+    #[test]
+    fn xrd_transfer_signer() {
+        let tx = load("xrd_transfer");
+        assert_eq!(
+            hex::encode(tx.recover_signer().unwrap().raw),
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"
+        );
+    }
+
+    #[test]
+    fn rejects_other_hash() {
+        let tx = load("xrd_transfer");
+        let key = tx.recover_signer().unwrap();
+        let signature = tx.signature().unwrap();
+        let hash = Hash::sha256_twice(b"other");
+        assert!(!signature.verify(&key, &hash));
+        let other = PublicKey { raw: [2; 33] };
+        assert!(!signature.verify(&other, &tx.signed_payload_hash().unwrap()));
+    }
+
+    #[test]
+    fn accepts_high_s() {
+        let tx = load("xrd_transfer");
+        let key = tx.recover_signer().unwrap();
+        let hash = tx.signed_payload_hash().unwrap();
+        let signature = tx.signature().unwrap();
+
+        // (r, n - s) with the opposite parity is the same signature
+        let n = primitive_types::U256::from_str_radix(
+            "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141",
+            16,
+        )
+        .unwrap();
+        let mut s = [0u8; 32];
+        (n - primitive_types::U256::from_big_endian(&signature.s)).to_big_endian(&mut s);
+        let high = Signature {
+            v: signature.v ^ 1,
+            r: signature.r,
+            s,
+        };
+        assert!(!is_low_s(&high));
+        assert!(high.verify(&key, &hash));
+        assert_eq!(high.recover(&hash).unwrap().raw, key.raw);
+
+        let invalid = Signature {
+            v: 4,
+            r: signature.r,
+            s: signature.s,
+        };
+        assert_eq!(
+            invalid.recover(&hash).unwrap_err(),
+            SignatureError::InvalidRecoveryId(4)
+        );
+    }
+
+    fn unsigned(tx: Transaction) -> Transaction {
+        Transaction::new(
+            tx.into_instructions()
+                .into_iter()
+                .filter(|i| !matches!(i, Instruction::SIG(_)))
+                .collect(),
+        )
+    }
+
+    /// Checks that `s` is at most half the curve order, over all of its bytes.
+    fn is_low_s(signature: &Signature) -> bool {
+        ecdsa::Signature::from_scalars(signature.r, signature.s)
+            .unwrap()
+            .normalize_s()
+            .is_none()
+    }
+
+    fn small_key(n: u8) -> PrivateKey {
+        let mut raw = [0u8; 32];
+        raw[31] = n;
+        PrivateKey::from_bytes(&raw).unwrap()
+    }
+
+    #[test]
+    fn signs_samples() {
+        for (name, raw, tx) in samples() {
+            let signer = tx.recover_signer().unwrap();
+            // The samples are signed with small private keys, and low-S like ours
+            let key = (1..=16)
+                .map(small_key)
+                .find(|key| key.public_key().raw == signer.raw)
+                .unwrap();
+            assert!(is_low_s(tx.signature().unwrap()));
+
+            // The engine signs with a random nonce, so only the signer can be compared
+            let hash = tx.signed_payload_hash().unwrap();
+            let signed = unsigned(tx).sign(&key).unwrap();
+            assert_eq!(signed.signed_payload_hash().unwrap(), hash);
+            assert_eq!(signed.recover_signer().unwrap().raw, signer.raw, "{}", name);
+            assert_eq!(signed.bytes().unwrap().len(), raw.len());
+            assert_eq!(signed.to_bytes(), signed.bytes().unwrap());
+        }
+    }
+
+    #[test]
+    fn signs_low_s() {
+        let key = small_key(2);
+        for i in 0..32u8 {
+            let hash = Hash::sha256_twice(&[i]);
+            let signature = key.sign(&hash);
+            assert!(is_low_s(&signature));
+            assert_eq!(signature.recover(&hash).unwrap().raw, key.public_key().raw);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_private_key() {
+        assert_eq!(
+            PrivateKey::from_bytes(&[0; 32]).err(),
+            Some(SignatureError::InvalidPrivateKey)
+        );
+        let tx = load("xrd_transfer");
+        assert_eq!(
+            tx.sign(&small_key(2)).err(),
+            Some(SignatureError::AlreadySigned)
+        );
+    }
+
+    #[test]
+    fn missing_signature() {
+        let tx = unsigned(load("token_mint"));
+        assert_eq!(
+            tx.recover_signer().unwrap_err(),
+            SignatureError::MissingSignature
+        );
+    }
+}
@@ -0,0 +1,2174 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+use primitive_types::U256;
+use primitive_types::U512;
+
+use crate::error::ConstraintMachineError;
+use crate::error::ConstraintMachineErrorKind;
+use crate::reader::Reader;
+use crate::store::SubstateStore;
+use crate::substates::*;
+use crate::syscall::Syscall;
+use crate::transaction::Instruction;
+use crate::transaction::Transaction;
+use crate::types::Address;
+use crate::types::Boolean;
+use crate::types::Bytes;
+use crate::types::Hash;
+use crate::types::IndexPrefix;
+use crate::types::PublicKey;
+use crate::types::SubstateId;
+use crate::types::SubstateIndex;
+use crate::types::VirtualKey;
+use crate::types::VirtualSubstate;
+use crate::types::UTF8;
+
+/// Operation of a procedure, i.e. the instruction kind with local and virtual variants merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Up,
+
+    Down,
+
+    Read,
+
+    DownIndex,
+
+    ReadIndex,
+
+    End,
+
+    Syscall,
+}
+
+/// Kind of a current state, which procedures are registered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StateKind {
+    /// No current state, i.e. `VoidReducerState`
+    Void,
+
+    TokenHoldingBucket,
+
+    REAddrClaimStart,
+
+    REAddrClaim,
+
+    NeedFixedTokenSupply,
+
+    NeedMetadata,
+
+    AllocatingSystem,
+
+    AllocatingVirtualState,
+
+    OwnerStakePrepare,
+
+    StakePrepare,
+
+    StakeOwnershipHoldingBucket,
+
+    UpdatingValidatorInfo,
+
+    UpdatingDelegationFlag,
+
+    UpdatingRegisteredNeedToReadEpoch,
+
+    UpdatingRegistered,
+
+    UpdatingRakeNeedToReadEpoch,
+
+    UpdatingRakeNeedToReadCurrentRake,
+
+    UpdatingRakeReady,
+
+    UpdatingOwnerNeedToReadEpoch,
+
+    UpdatingValidatorOwner,
+
+    EndPrevRound,
+
+    StartValidatorBFTUpdate,
+
+    UpdatingValidatorBFTData,
+
+    StartNextRound,
+
+    UpdatingEpoch,
+
+    ProcessExittingStake,
+
+    RewardingValidators,
+
+    LoadingStake,
+
+    PreparingUnstake,
+
+    Unstaking,
+
+    PreparingStake,
+
+    Staking,
+
+    PreparingRakeUpdate,
+
+    ResetRakeUpdate,
+
+    PreparingOwnerUpdate,
+
+    ResetOwnerUpdate,
+
+    PreparingRegisteredUpdate,
+
+    ResetRegisteredUpdate,
+
+    UpdatingValidatorStakes,
+
+    CreatingNextValidatorSet,
+
+    BootupValidator,
+
+    StartingNextEpoch,
+
+    StartingEpochRound,
+}
+
+/// Key a procedure is registered to, i.e. `<current_state, operation, substate type>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcedureKey {
+    pub current_state: StateKind,
+    pub operation: Operation,
+    /// Substate type of the instruction, or `None` for `END` and `SYSCALL`
+    pub substate_type: Option<SubstateTypeId>,
+}
+
+/// Current state of the constraint machine, carried across the instructions of an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReducerState {
+    TokenHoldingBucket(TokenHoldingBucket),
+
+    REAddrClaimStart {
+        symbol: Vec<u8>,
+    },
+
+    REAddrClaim {
+        symbol: Vec<u8>,
+        address: Address,
+    },
+
+    NeedFixedTokenSupply {
+        symbol: Vec<u8>,
+        resource: Address,
+    },
+
+    NeedMetadata {
+        symbol: Vec<u8>,
+        resource: Address,
+    },
+
+    AllocatingSystem,
+
+    /// Child types of the virtual parents left to boot up at genesis
+    AllocatingVirtualState(Vec<SubstateTypeId>),
+
+    /// Tokens to stake to a validator which doesn't allow delegation, awaiting its owner
+    OwnerStakePrepare {
+        bucket: TokenHoldingBucket,
+        validator: PublicKey,
+    },
+
+    /// Tokens to stake to a validator, on behalf of `owner` only if set
+    StakePrepare {
+        bucket: TokenHoldingBucket,
+        validator: PublicKey,
+        owner: Option<Address>,
+    },
+
+    StakeOwnershipHoldingBucket(StakeOwnershipHoldingBucket),
+
+    /// Metadata of a validator spun down, awaiting its update
+    UpdatingValidatorInfo(PublicKey),
+
+    UpdatingDelegationFlag(PublicKey),
+
+    UpdatingRegisteredNeedToReadEpoch(PublicKey),
+
+    UpdatingRegistered {
+        validator: PublicKey,
+        epoch: u64,
+    },
+
+    UpdatingRakeNeedToReadEpoch(PublicKey),
+
+    UpdatingRakeNeedToReadCurrentRake {
+        validator: PublicKey,
+        epoch: u64,
+    },
+
+    UpdatingRakeReady {
+        validator: PublicKey,
+        epoch: u64,
+        current_rake: u32,
+    },
+
+    UpdatingOwnerNeedToReadEpoch(PublicKey),
+
+    UpdatingValidatorOwner {
+        validator: PublicKey,
+        epoch: u64,
+    },
+
+    /// Round spun down, awaiting the BFT data of the validators or the epoch
+    EndPrevRound {
+        view: u64,
+    },
+
+    /// BFT data of validators spun down so far, awaiting their updates in key order
+    StartValidatorBFTUpdate {
+        view: u64,
+        validators: BTreeSet<PublicKey>,
+    },
+
+    UpdatingValidatorBFTData {
+        view: u64,
+        validators: BTreeSet<PublicKey>,
+    },
+
+    StartNextRound {
+        view: u64,
+    },
+
+    UpdatingEpoch(Box<EpochUpdate>),
+
+    ProcessExittingStake(Box<EpochUpdate>),
+
+    RewardingValidators(Box<EpochUpdate>),
+
+    /// Stake data of a validator to spin down, before resuming the epoch update at `then`
+    LoadingStake {
+        update: Box<EpochUpdate>,
+        validator: PublicKey,
+        then: StateKind,
+    },
+
+    PreparingUnstake(Box<EpochUpdate>),
+
+    Unstaking(Box<EpochUpdate>),
+
+    PreparingStake(Box<EpochUpdate>),
+
+    Staking(Box<EpochUpdate>),
+
+    PreparingRakeUpdate(Box<EpochUpdate>),
+
+    ResetRakeUpdate(Box<EpochUpdate>),
+
+    PreparingOwnerUpdate(Box<EpochUpdate>),
+
+    ResetOwnerUpdate(Box<EpochUpdate>),
+
+    PreparingRegisteredUpdate(Box<EpochUpdate>),
+
+    ResetRegisteredUpdate(Box<EpochUpdate>),
+
+    UpdatingValidatorStakes(Box<EpochUpdate>),
+
+    CreatingNextValidatorSet(Box<EpochUpdate>),
+
+    BootupValidator(Box<EpochUpdate>),
+
+    StartingNextEpoch(Box<EpochUpdate>),
+
+    StartingEpochRound(Box<EpochUpdate>),
+}
+
+/// Tokens of a single resource, withdrawn but not yet deposited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenHoldingBucket {
+    pub resource: Address,
+    pub amount: U256,
+}
+
+/// Stake ownership of an account in a validator, withdrawn but not yet staked back or unstaked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StakeOwnershipHoldingBucket {
+    pub validator: PublicKey,
+    pub owner: Address,
+    pub amount: U256,
+}
+
+/// Stake data of a validator, as updated in the course of an epoch update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidatorScratchPad {
+    pub validator: PublicKey,
+    pub is_registered: bool,
+    pub total_stake: U256,
+    pub total_ownership: U256,
+    pub rake_percentage: u32,
+    pub owner: Address,
+}
+
+/// Progress of an epoch update, carried across its states
+///
+/// Work left is kept in maps ordered by validator key and by owner, which is the order the
+/// updates must appear in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochUpdate {
+    /// Epoch being ended
+    pub prev_epoch: u64,
+    /// Unlocked stake left to return, as owner and amount of XRD
+    pub exiting: VecDeque<(Address, U256)>,
+    /// Stake data of the validators spun down so far
+    pub validators: BTreeMap<PublicKey, ValidatorScratchPad>,
+    /// Emissions left to reward, per validator
+    pub rewards: BTreeMap<PublicKey, U256>,
+    /// Stake ownership left to unstake, per validator and owner
+    pub unstakes: BTreeMap<PublicKey, BTreeMap<Address, U256>>,
+    /// XRD left to stake, per validator and owner
+    pub stakes: BTreeMap<PublicKey, BTreeMap<Address, U256>>,
+    /// Rakes taking effect, left to reset
+    pub rake_updates: BTreeMap<PublicKey, u32>,
+    /// Owners taking effect, left to reset
+    pub owner_updates: BTreeMap<PublicKey, Address>,
+    /// Registrations taking effect, left to reset
+    pub registered_updates: BTreeMap<PublicKey, bool>,
+    /// Validators of the next epoch left to boot up
+    pub next_validators: BTreeSet<PublicKey>,
+}
+
+/// Token resource, as needed to authorize mints and burns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resource {
+    pub is_mutable: bool,
+    pub minter: Option<PublicKey>,
+}
+
+/// Operand of a procedure call
+#[derive(Debug, Clone, Copy)]
+pub enum ProcedureInput<'a> {
+    Substate(&'a SubstateData),
+
+    /// Prefix of `READINDEX` or `DOWNINDEX`, and the substates matching it
+    Substates(&'a IndexPrefix, &'a [&'a SubstateData]),
+
+    Syscall(&'a Syscall),
+
+    End,
+}
+
+/// Level of privilege a transaction is executed at, from the lowest to the highest
+///
+/// A level is allowed all actions of the levels below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PermissionLevel {
+    /// Normal transactions
+    User,
+
+    /// Updates of the epoch and view
+    SuperUser,
+
+    /// Allocation of native tokens and genesis, skipping authorization and fees
+    System,
+}
+
+/// Returns the permission level required to call a procedure, given the current state and the
+/// operand
+pub type RequiredLevel = fn(Option<&ReducerState>, ProcedureInput<'_>) -> PermissionLevel;
+
+/// Checks the signer is allowed to call a procedure, given the current state and the operand
+pub type Authorizer = fn(
+    Option<&ReducerState>,
+    ProcedureInput<'_>,
+    &ExecutionContext,
+    &dyn Resources,
+) -> Result<(), ConstraintMachineErrorKind>;
+
+/// Computes the next current state, `None` meaning void
+pub type Reducer = fn(
+    Option<ReducerState>,
+    ProcedureInput<'_>,
+    &mut ExecutionContext,
+    &dyn Resources,
+) -> Result<Option<ReducerState>, ConstraintMachineErrorKind>;
+
+/// State transition procedure
+#[derive(Clone, Copy)]
+pub struct Procedure {
+    pub required_level: RequiredLevel,
+    pub authorizer: Authorizer,
+    pub reducer: Reducer,
+}
+
+/// Registered procedures, by key
+#[derive(Clone, Default)]
+pub struct Procedures {
+    procedures: HashMap<ProcedureKey, Procedure>,
+}
+
+/// Loads token resources, either created by the transaction or from the store
+pub trait Resources {
+    fn load_resource(&self, address: &Address) -> Result<Resource, ConstraintMachineErrorKind>;
+}
+
+/// ID of a substate spun down, virtual substates being identified by their parent and key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DownSubstateId {
+    Stored(SubstateId),
+
+    Virtual { parent: SubstateId, key: Vec<u8> },
+}
+
+/// Change to the ledger state made by a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateUpdate {
+    /// A substate booted up, as serialized
+    Up {
+        id: SubstateId,
+        substate: Vec<u8>,
+    },
+
+    Down(DownSubstateId),
+}
+
+/// Fees charged by the meters, in the smallest XRD unit
+#[derive(Debug, Clone, Default)]
+pub struct FeeTable {
+    pub per_byte_fee: U256,
+    pub per_up_substate_fee: HashMap<SubstateTypeId, U256>,
+    /// Maximum transaction size, which the system loan covers the fee of
+    pub max_tx_size: usize,
+}
+
+/// Transaction-wide context of the procedures, including the fee reserve
+#[derive(Debug)]
+pub struct ExecutionContext {
+    permission_level: PermissionLevel,
+    signer: Option<PublicKey>,
+    mint_burn_disabled: bool,
+    tx_size: usize,
+    reserve: TokenHoldingBucket,
+    fee_deposit: Option<U256>,
+    system_loan: U256,
+    charged_one_time_fee: bool,
+}
+
+/// The Radix Constraint Machine, which executes the instructions of a transaction against the
+/// ledger state, as described in the "Stateful Validation" section of the spec
+pub struct ConstraintMachine {
+    procedures: Procedures,
+    fees: FeeTable,
+}
+
+/// Internal state of the constraint machine, as listed in the "Validation State" section of the
+/// spec
+struct ValidationState<'a> {
+    store: &'a dyn SubstateStore,
+    tx_id: Hash,
+    current_state: Option<ReducerState>,
+    end_expected: bool,
+    up_instruction_count: usize,
+    local_up_substates: HashMap<SubstateIndex, &'a SubstateData>,
+    /// Resources booted up locally, which are not in the store yet
+    local_resources: HashMap<Address, Resource>,
+    remote_down_substates: HashSet<DownSubstateId>,
+}
+
+impl Operation {
+    fn of(instruction: &Instruction) -> Option<Self> {
+        let operation = match instruction {
+            Instruction::UP(_) => Self::Up,
+            Instruction::DOWN(_)
+            | Instruction::LDOWN(_)
+            | Instruction::VDOWN(_)
+            | Instruction::LVDOWN(_) => Self::Down,
+            Instruction::READ(_)
+            | Instruction::LREAD(_)
+            | Instruction::VREAD(_)
+            | Instruction::LVREAD(_) => Self::Read,
+            Instruction::DOWNINDEX(_) => Self::DownIndex,
+            Instruction::READINDEX(_) => Self::ReadIndex,
+            Instruction::END => Self::End,
+            Instruction::SYSCALL(_) => Self::Syscall,
+            Instruction::SIG(_) | Instruction::MSG(_) | Instruction::HEADER(_) => return None,
+        };
+        Some(operation)
+    }
+}
+
+impl ReducerState {
+    pub fn kind(&self) -> StateKind {
+        match self {
+            Self::TokenHoldingBucket(_) => StateKind::TokenHoldingBucket,
+            Self::REAddrClaimStart { .. } => StateKind::REAddrClaimStart,
+            Self::REAddrClaim { .. } => StateKind::REAddrClaim,
+            Self::NeedFixedTokenSupply { .. } => StateKind::NeedFixedTokenSupply,
+            Self::NeedMetadata { .. } => StateKind::NeedMetadata,
+            Self::AllocatingSystem => StateKind::AllocatingSystem,
+            Self::AllocatingVirtualState(_) => StateKind::AllocatingVirtualState,
+            Self::OwnerStakePrepare { .. } => StateKind::OwnerStakePrepare,
+            Self::StakePrepare { .. } => StateKind::StakePrepare,
+            Self::StakeOwnershipHoldingBucket(_) => StateKind::StakeOwnershipHoldingBucket,
+            Self::UpdatingValidatorInfo(_) => StateKind::UpdatingValidatorInfo,
+            Self::UpdatingDelegationFlag(_) => StateKind::UpdatingDelegationFlag,
+            Self::UpdatingRegisteredNeedToReadEpoch(_) => {
+                StateKind::UpdatingRegisteredNeedToReadEpoch
+            }
+            Self::UpdatingRegistered { .. } => StateKind::UpdatingRegistered,
+            Self::UpdatingRakeNeedToReadEpoch(_) => StateKind::UpdatingRakeNeedToReadEpoch,
+            Self::UpdatingRakeNeedToReadCurrentRake { .. } => {
+                StateKind::UpdatingRakeNeedToReadCurrentRake
+            }
+            Self::UpdatingRakeReady { .. } => StateKind::UpdatingRakeReady,
+            Self::UpdatingOwnerNeedToReadEpoch(_) => StateKind::UpdatingOwnerNeedToReadEpoch,
+            Self::UpdatingValidatorOwner { .. } => StateKind::UpdatingValidatorOwner,
+            Self::EndPrevRound { .. } => StateKind::EndPrevRound,
+            Self::StartValidatorBFTUpdate { .. } => StateKind::StartValidatorBFTUpdate,
+            Self::UpdatingValidatorBFTData { .. } => StateKind::UpdatingValidatorBFTData,
+            Self::StartNextRound { .. } => StateKind::StartNextRound,
+            Self::UpdatingEpoch(_) => StateKind::UpdatingEpoch,
+            Self::ProcessExittingStake(_) => StateKind::ProcessExittingStake,
+            Self::RewardingValidators(_) => StateKind::RewardingValidators,
+            Self::LoadingStake { .. } => StateKind::LoadingStake,
+            Self::PreparingUnstake(_) => StateKind::PreparingUnstake,
+            Self::Unstaking(_) => StateKind::Unstaking,
+            Self::PreparingStake(_) => StateKind::PreparingStake,
+            Self::Staking(_) => StateKind::Staking,
+            Self::PreparingRakeUpdate(_) => StateKind::PreparingRakeUpdate,
+            Self::ResetRakeUpdate(_) => StateKind::ResetRakeUpdate,
+            Self::PreparingOwnerUpdate(_) => StateKind::PreparingOwnerUpdate,
+            Self::ResetOwnerUpdate(_) => StateKind::ResetOwnerUpdate,
+            Self::PreparingRegisteredUpdate(_) => StateKind::PreparingRegisteredUpdate,
+            Self::ResetRegisteredUpdate(_) => StateKind::ResetRegisteredUpdate,
+            Self::UpdatingValidatorStakes(_) => StateKind::UpdatingValidatorStakes,
+            Self::CreatingNextValidatorSet(_) => StateKind::CreatingNextValidatorSet,
+            Self::BootupValidator(_) => StateKind::BootupValidator,
+            Self::StartingNextEpoch(_) => StateKind::StartingNextEpoch,
+            Self::StartingEpochRound(_) => StateKind::StartingEpochRound,
+        }
+    }
+}
+
+impl TokenHoldingBucket {
+    pub fn new(resource: Address, amount: U256) -> Self {
+        Self { resource, amount }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amount.is_zero()
+    }
+
+    pub fn deposit(
+        &mut self,
+        resource: Address,
+        amount: U256,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        self.check_resource(resource)?;
+        self.amount =
+            self.amount
+                .checked_add(amount)
+                .ok_or(ConstraintMachineErrorKind::Procedure(
+                    "Token amount overflow",
+                ))?;
+        Ok(())
+    }
+
+    pub fn withdraw(
+        &mut self,
+        resource: Address,
+        amount: U256,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        self.check_resource(resource)?;
+        if amount > self.amount {
+            return Err(ConstraintMachineErrorKind::NotEnoughResources {
+                request: amount,
+                amount: self.amount,
+            });
+        }
+        self.amount -= amount;
+        Ok(())
+    }
+
+    fn check_resource(&self, resource: Address) -> Result<(), ConstraintMachineErrorKind> {
+        if resource != self.resource {
+            return Err(ConstraintMachineErrorKind::InvalidResource {
+                expected: resource,
+                actual: self.resource,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl StakeOwnershipHoldingBucket {
+    pub fn new(ownership: &StakeOwnership) -> Self {
+        Self {
+            validator: ownership.validator,
+            owner: ownership.owner,
+            amount: ownership.amount.raw,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amount.is_zero()
+    }
+
+    pub fn deposit(
+        &mut self,
+        ownership: &StakeOwnership,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        if ownership.owner != self.owner {
+            return Err(ConstraintMachineErrorKind::Procedure(
+                "Shareholder mismatch",
+            ));
+        }
+        if ownership.validator != self.validator {
+            return Err(ConstraintMachineErrorKind::Procedure("Delegate mismatch"));
+        }
+        self.amount = self.amount.checked_add(ownership.amount.raw).ok_or(
+            ConstraintMachineErrorKind::Procedure("Stake ownership overflow"),
+        )?;
+        Ok(())
+    }
+
+    /// Withdraws ownership of the same validator and owner, i.e. for a `STAKE_OWNERSHIP` or a
+    /// `PREPARED_UNSTAKE`.
+    pub fn withdraw(
+        &mut self,
+        validator: &PublicKey,
+        owner: &Address,
+        amount: U256,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        if *owner != self.owner {
+            return Err(ConstraintMachineErrorKind::Procedure(
+                "Must unstake to self",
+            ));
+        }
+        if *validator != self.validator {
+            return Err(ConstraintMachineErrorKind::Procedure("Delegate mismatch"));
+        }
+        if amount > self.amount {
+            return Err(ConstraintMachineErrorKind::NotEnoughResources {
+                request: amount,
+                amount: self.amount,
+            });
+        }
+        self.amount -= amount;
+        Ok(())
+    }
+}
+
+impl ValidatorScratchPad {
+    pub fn new(stake: &ValidatorStakeData) -> Self {
+        Self {
+            validator: stake.validator,
+            is_registered: stake.is_registered.raw != 0,
+            total_stake: stake.amount.raw,
+            total_ownership: stake.ownership.raw,
+            rake_percentage: stake.rake_percentage,
+            owner: stake.owner,
+        }
+    }
+
+    /// Adds emitted XRD to the stake, increasing the value of the ownership.
+    pub fn add_emission(&mut self, amount: U256) -> Result<(), ConstraintMachineErrorKind> {
+        self.total_stake = self
+            .total_stake
+            .checked_add(amount)
+            .ok_or(ConstraintMachineErrorKind::Procedure("Stake overflow"))?;
+        Ok(())
+    }
+
+    /// Stakes XRD, returning the ownership it buys at the current ratio.
+    pub fn stake(&mut self, amount: U256) -> Result<U256, ConstraintMachineErrorKind> {
+        let ownership = if self.total_stake.is_zero() {
+            amount
+        } else {
+            mul_div(self.total_ownership, amount, self.total_stake)?
+        };
+        self.total_stake = self
+            .total_stake
+            .checked_add(amount)
+            .ok_or(ConstraintMachineErrorKind::Procedure("Stake overflow"))?;
+        self.total_ownership = self
+            .total_ownership
+            .checked_add(ownership)
+            .ok_or(ConstraintMachineErrorKind::Procedure("Stake overflow"))?;
+        Ok(ownership)
+    }
+
+    /// Unstakes ownership, returning the XRD it is worth at the current ratio.
+    pub fn unstake(&mut self, ownership: U256) -> Result<U256, ConstraintMachineErrorKind> {
+        if ownership > self.total_ownership {
+            return Err(ConstraintMachineErrorKind::NotEnoughResources {
+                request: ownership,
+                amount: self.total_ownership,
+            });
+        }
+        let amount = mul_div(self.total_stake, ownership, self.total_ownership)?;
+        self.total_stake -= amount;
+        self.total_ownership -= ownership;
+        Ok(amount)
+    }
+
+    /// Returns whether a `VALIDATOR_STAKE_DATA` substate holds this stake data.
+    pub fn matches(&self, stake: &ValidatorStakeData) -> bool {
+        stake.validator == self.validator
+            && (stake.is_registered.raw != 0) == self.is_registered
+            && stake.amount.raw == self.total_stake
+            && stake.ownership.raw == self.total_ownership
+            && stake.rake_percentage == self.rake_percentage
+            && stake.owner == self.owner
+    }
+}
+
+/// Computes `a * b / c` without intermediate overflow.
+fn mul_div(a: U256, b: U256, c: U256) -> Result<U256, ConstraintMachineErrorKind> {
+    let result = a.full_mul(b) / U512::from(c);
+    U256::try_from(result).map_err(|_| ConstraintMachineErrorKind::Procedure("Stake overflow"))
+}
+
+impl EpochUpdate {
+    pub fn new(prev_epoch: u64) -> Self {
+        Self {
+            prev_epoch,
+            exiting: VecDeque::new(),
+            validators: BTreeMap::new(),
+            rewards: BTreeMap::new(),
+            unstakes: BTreeMap::new(),
+            stakes: BTreeMap::new(),
+            rake_updates: BTreeMap::new(),
+            owner_updates: BTreeMap::new(),
+            registered_updates: BTreeMap::new(),
+            next_validators: BTreeSet::new(),
+        }
+    }
+}
+
+impl<'a> ProcedureInput<'a> {
+    /// Returns the substate of `UP`, `DOWN` and `READ` instructions and their variants.
+    pub fn substate(&self) -> Option<&'a SubstateData> {
+        match self {
+            Self::Substate(substate) => Some(substate),
+            _ => None,
+        }
+    }
+}
+
+impl Procedures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: ProcedureKey, procedure: Procedure) {
+        self.procedures.insert(key, procedure);
+    }
+
+    pub fn get(&self, key: &ProcedureKey) -> Result<&Procedure, ConstraintMachineErrorKind> {
+        self.procedures
+            .get(key)
+            .ok_or(ConstraintMachineErrorKind::MissingProcedure(*key))
+    }
+}
+
+impl FeeTable {
+    /// Fees of the Olympia mainnet: 0.0002 XRD per byte and a fee per substate booted up.
+    pub fn mainnet() -> Self {
+        let milli_xrd = U256::exp10(15);
+        let per_up_substate_fee = [
+            (SubstateTypeId::TokenResource, milli_xrd * 1_000_000),
+            (
+                SubstateTypeId::ValidatorRegisteredFlagCopy,
+                milli_xrd * 5000,
+            ),
+            (SubstateTypeId::ValidatorRakeCopy, milli_xrd * 5000),
+            (SubstateTypeId::ValidatorOwnerCopy, milli_xrd * 5000),
+            (SubstateTypeId::ValidatorMetadata, milli_xrd * 5000),
+            (
+                SubstateTypeId::ValidatorAllowDelegationFlag,
+                milli_xrd * 5000,
+            ),
+            (SubstateTypeId::PreparedStake, milli_xrd * 500),
+            (SubstateTypeId::PreparedUnstake, milli_xrd * 500),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        Self {
+            per_byte_fee: U256::exp10(12) * 200,
+            per_up_substate_fee,
+            max_tx_size: 1024 * 1024,
+        }
+    }
+
+    /// Returns the loan granted to every transaction, i.e. the fee of the largest transaction.
+    pub fn system_loan(&self) -> U256 {
+        self.per_byte_fee * self.max_tx_size
+    }
+}
+
+impl ExecutionContext {
+    /// Creates the context of a transaction executed at `permission_level` and signed by
+    /// `signer`, if any.
+    pub fn new(
+        tx: &Transaction,
+        permission_level: PermissionLevel,
+        signer: Option<PublicKey>,
+    ) -> Self {
+        let tx_size = tx.bytes().map_or_else(|| tx.to_bytes().len(), |b| b.len());
+        Self {
+            permission_level,
+            signer,
+            mint_burn_disabled: tx.header().is_some_and(|h| h.flags.mint_burn_disabled()),
+            tx_size,
+            reserve: TokenHoldingBucket::new(Address::RadixNativeToken, U256::zero()),
+            fee_deposit: None,
+            system_loan: U256::zero(),
+            charged_one_time_fee: false,
+        }
+    }
+
+    pub fn permission_level(&self) -> PermissionLevel {
+        self.permission_level
+    }
+
+    pub fn signer(&self) -> Option<&PublicKey> {
+        self.signer.as_ref()
+    }
+
+    /// Checks the transaction is executed at a required level, and not signed if the level is
+    /// above `User`.
+    pub fn verify_permission_level(
+        &self,
+        required: PermissionLevel,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        if self.permission_level < required {
+            return Err(ConstraintMachineErrorKind::InvalidPermission {
+                required,
+                actual: self.permission_level,
+            });
+        }
+        if required >= PermissionLevel::SuperUser && self.signer.is_some() {
+            return Err(ConstraintMachineErrorKind::SignedSystem);
+        }
+        Ok(())
+    }
+
+    pub fn verify_can_alloc_and_destroy_resources(&self) -> Result<(), ConstraintMachineErrorKind> {
+        if self.mint_burn_disabled {
+            return Err(ConstraintMachineErrorKind::ResourceAllocationAndDestruction);
+        }
+        Ok(())
+    }
+
+    /// Deposits XRD into the fee reserve, which is allowed once per transaction.
+    pub fn deposit_fee_reserve(&mut self, amount: U256) -> Result<(), ConstraintMachineErrorKind> {
+        if self.fee_deposit.is_some() {
+            return Err(ConstraintMachineErrorKind::MultipleFeeReserveDeposit);
+        }
+        self.reserve.deposit(Address::RadixNativeToken, amount)?;
+        self.fee_deposit = Some(amount);
+        Ok(())
+    }
+
+    pub fn withdraw_fee_reserve(
+        &mut self,
+        amount: U256,
+    ) -> Result<TokenHoldingBucket, ConstraintMachineErrorKind> {
+        self.reserve.withdraw(Address::RadixNativeToken, amount)?;
+        Ok(TokenHoldingBucket::new(Address::RadixNativeToken, amount))
+    }
+
+    fn add_system_loan(&mut self, loan: U256) -> Result<(), ConstraintMachineErrorKind> {
+        let overflow = || ConstraintMachineErrorKind::Procedure("System loan overflow");
+        self.system_loan = self.system_loan.checked_add(loan).ok_or_else(overflow)?;
+        self.reserve.amount = self.reserve.amount.checked_add(loan).ok_or_else(overflow)?;
+        Ok(())
+    }
+
+    fn charge(&mut self, amount: U256) -> Result<(), ConstraintMachineErrorKind> {
+        self.reserve
+            .withdraw(Address::RadixNativeToken, amount)
+            .map_err(|_| ConstraintMachineErrorKind::DepletedFeeReserve {
+                charge: amount,
+                reserve: self.reserve.amount,
+            })
+    }
+
+    fn charge_one_time_fee(&mut self, fee: U256) -> Result<(), ConstraintMachineErrorKind> {
+        if !self.charged_one_time_fee {
+            self.charge(fee)?;
+            self.charged_one_time_fee = true;
+        }
+        Ok(())
+    }
+
+    fn pay_off_loan(&mut self) -> Result<(), ConstraintMachineErrorKind> {
+        if self.system_loan.is_zero() {
+            return Ok(());
+        }
+        let loan = self.system_loan;
+        let reserve = self.reserve.amount;
+        self.charge(loan)
+            .map_err(|_| ConstraintMachineErrorKind::DefaultedSystemLoan {
+                fee_deposit: self.fee_deposit.unwrap_or_default(),
+                missing: loan - reserve,
+            })?;
+        self.system_loan = U256::zero();
+        Ok(())
+    }
+
+    fn destroy(&mut self) -> Result<(), ConstraintMachineErrorKind> {
+        self.pay_off_loan()?;
+        if !self.reserve.is_empty() {
+            return Err(ConstraintMachineErrorKind::ExecutionContextDestroy(
+                self.reserve.amount,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl ConstraintMachine {
+    pub fn new(procedures: Procedures, fees: FeeTable) -> Self {
+        Self { procedures, fees }
+    }
+
+    /// Executes a transaction which passed stateless validation, returning its state updates by
+    /// instruction group.
+    pub fn verify(
+        &self,
+        store: &dyn SubstateStore,
+        mut context: ExecutionContext,
+        tx: &Transaction,
+    ) -> Result<Vec<Vec<StateUpdate>>, ConstraintMachineError> {
+        let mut state = ValidationState {
+            store,
+            tx_id: *tx.id(),
+            current_state: None,
+            end_expected: false,
+            up_instruction_count: 0,
+            local_up_substates: HashMap::new(),
+            local_resources: HashMap::new(),
+            remote_down_substates: HashSet::new(),
+        };
+        let mut groups = Vec::new();
+        let mut updates = Vec::new();
+        if context.permission_level == PermissionLevel::User {
+            context
+                .add_system_loan(self.fees.system_loan())
+                .map_err(|kind| ConstraintMachineError {
+                    kind,
+                    instruction_index: 0,
+                })?;
+        }
+        for (index, instruction) in tx.instructions().iter().enumerate() {
+            self.execute(&mut state, &mut context, instruction, &mut updates)
+                .map_err(|kind| ConstraintMachineError {
+                    kind,
+                    instruction_index: index,
+                })?;
+            if let Instruction::END = instruction {
+                groups.push(std::mem::take(&mut updates));
+            }
+        }
+        context.destroy().map_err(|kind| ConstraintMachineError {
+            kind,
+            instruction_index: tx.instructions().len(),
+        })?;
+        Ok(groups)
+    }
+
+    fn execute<'a>(
+        &self,
+        state: &mut ValidationState<'a>,
+        context: &mut ExecutionContext,
+        instruction: &'a Instruction,
+        updates: &mut Vec<StateUpdate>,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        if state.end_expected && !matches!(instruction, Instruction::END) {
+            return Err(ConstraintMachineErrorKind::MissingExpectedEnd);
+        }
+        let operation = match Operation::of(instruction) {
+            Some(operation) => operation,
+            None => return Ok(()),
+        };
+        let loaded;
+        let substate = match instruction {
+            Instruction::UP(substate) => {
+                let id = state.boot_up(substate);
+                updates.push(StateUpdate::Up {
+                    id,
+                    substate: substate.to_bytes(),
+                });
+                substate
+            }
+            Instruction::READ(id) => {
+                loaded = state.read(id)?;
+                &loaded
+            }
+            Instruction::LREAD(index) => state.local_read(*index)?,
+            Instruction::VREAD(id) => {
+                loaded = state.virtual_read(&id.parent, &id.key)?;
+                &loaded
+            }
+            Instruction::LVREAD(id) => {
+                loaded = state.local_virtual_read(id.parent, &id.key)?;
+                &loaded
+            }
+            Instruction::DOWN(id) => {
+                loaded = state.read(id)?;
+                state.shut_down(DownSubstateId::Stored(*id), updates);
+                &loaded
+            }
+            Instruction::LDOWN(index) => {
+                let substate = state.local_shutdown(*index)?;
+                let id = state.local_id(*index);
+                updates.push(StateUpdate::Down(DownSubstateId::Stored(id)));
+                substate
+            }
+            Instruction::VDOWN(id) => {
+                loaded = state.virtual_read(&id.parent, &id.key)?;
+                state.shut_down(state.virtual_id(id.parent, &id.key), updates);
+                &loaded
+            }
+            Instruction::LVDOWN(id) => {
+                loaded = state.local_virtual_read(id.parent, &id.key)?;
+                let parent = state.local_id(id.parent);
+                state.shut_down(state.virtual_id(parent, &id.key), updates);
+                &loaded
+            }
+            Instruction::READINDEX(prefix) | Instruction::DOWNINDEX(prefix) => {
+                let down = operation == Operation::DownIndex;
+                let (local, stored) = state.index(prefix, down, updates)?;
+                let substates: Vec<&SubstateData> = local.into_iter().chain(&stored).collect();
+                let key = state.key(operation, Some(prefix.type_id));
+                return self.call(
+                    state,
+                    context,
+                    key,
+                    ProcedureInput::Substates(prefix, &substates),
+                );
+            }
+            Instruction::SYSCALL(syscall) => {
+                let key = state.key(operation, None);
+                return self.call(state, context, key, ProcedureInput::Syscall(syscall));
+            }
+            Instruction::END => {
+                if state.current_state.is_some() {
+                    let key = state.key(operation, None);
+                    self.call(state, context, key, ProcedureInput::End)?;
+                }
+                state.end_expected = false;
+                return Ok(());
+            }
+            Instruction::SIG(_) | Instruction::MSG(_) | Instruction::HEADER(_) => unreachable!(),
+        };
+        let substate_type = substate
+            .type_id()
+            .ok_or_else(|| ConstraintMachineErrorKind::UnknownSubstateType(substate.type_code()))?;
+        let key = state.key(operation, Some(substate_type));
+        self.call(state, context, key, ProcedureInput::Substate(substate))?;
+        state.end_expected = state.current_state.is_none();
+        Ok(())
+    }
+
+    /// Looks up the procedure of a key, verifies its permission level, charges the meters,
+    /// authorizes and reduces.
+    ///
+    /// Transactions executed at `System` are neither metered nor authorized.
+    fn call(
+        &self,
+        state: &mut ValidationState,
+        context: &mut ExecutionContext,
+        key: ProcedureKey,
+        input: ProcedureInput,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        let procedure = *self.procedures.get(&key)?;
+        let required = (procedure.required_level)(state.current_state.as_ref(), input);
+        context.verify_permission_level(required)?;
+        if context.permission_level != PermissionLevel::System {
+            if required == PermissionLevel::User {
+                self.meter(context, &key, input)?;
+            }
+            (procedure.authorizer)(state.current_state.as_ref(), input, context, &*state)?;
+        }
+        let current = state.current_state.take();
+        state.current_state = (procedure.reducer)(current, input, context, &*state)?;
+        Ok(())
+    }
+
+    /// Charges the transaction size once and the fee of a substate booted up, and takes back
+    /// the system loan at the first instruction which cannot deposit a fee.
+    fn meter(
+        &self,
+        context: &mut ExecutionContext,
+        key: &ProcedureKey,
+        input: ProcedureInput,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        context.charge_one_time_fee(self.fees.per_byte_fee * context.tx_size)?;
+        let native_withdrawal = key.operation == Operation::Down
+            && matches!(
+                input.substate().and_then(SubstateData::as_tokens),
+                Some(tokens) if tokens.resource == Address::RadixNativeToken
+            );
+        if key.operation != Operation::Syscall && !native_withdrawal {
+            context.pay_off_loan()?;
+        }
+        if key.operation == Operation::Up {
+            let fee = key
+                .substate_type
+                .and_then(|t| self.fees.per_up_substate_fee.get(&t));
+            if let Some(fee) = fee {
+                context.charge(*fee)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ValidationState<'a> {
+    fn key(&self, operation: Operation, substate_type: Option<SubstateTypeId>) -> ProcedureKey {
+        ProcedureKey {
+            current_state: self
+                .current_state
+                .as_ref()
+                .map_or(StateKind::Void, ReducerState::kind),
+            operation,
+            substate_type,
+        }
+    }
+
+    fn local_id(&self, index: SubstateIndex) -> SubstateId {
+        SubstateId {
+            hash: self.tx_id,
+            index: index as u32,
+        }
+    }
+
+    fn virtual_id(&self, parent: SubstateId, key: &Bytes) -> DownSubstateId {
+        DownSubstateId::Virtual {
+            parent,
+            key: key.data.clone(),
+        }
+    }
+
+    fn boot_up(&mut self, substate: &'a SubstateData) -> SubstateId {
+        let index = self.up_instruction_count as SubstateIndex;
+        self.local_up_substates.insert(index, substate);
+        if let SubstateData::TokenResource(resource) = substate {
+            self.local_resources
+                .insert(resource.resource, Resource::of(resource));
+        }
+        self.up_instruction_count += 1;
+        self.local_id(index)
+    }
+
+    fn shut_down(&mut self, id: DownSubstateId, updates: &mut Vec<StateUpdate>) {
+        self.remote_down_substates.insert(id.clone());
+        updates.push(StateUpdate::Down(id));
+    }
+
+    fn read(&self, id: &SubstateId) -> Result<SubstateData, ConstraintMachineErrorKind> {
+        if self
+            .remote_down_substates
+            .contains(&DownSubstateId::Stored(*id))
+        {
+            return Err(ConstraintMachineErrorKind::SubstateNotFound(*id));
+        }
+        let raw = self
+            .store
+            .load_substate(id)?
+            .ok_or(ConstraintMachineErrorKind::SubstateNotFound(*id))?;
+        decode(&raw).ok_or(ConstraintMachineErrorKind::CorruptSubstate(*id))
+    }
+
+    fn local_read(
+        &self,
+        index: SubstateIndex,
+    ) -> Result<&'a SubstateData, ConstraintMachineErrorKind> {
+        self.local_up_substates
+            .get(&index)
+            .copied()
+            .ok_or(ConstraintMachineErrorKind::LocalSubstateNotFound(index))
+    }
+
+    fn local_shutdown(
+        &mut self,
+        index: SubstateIndex,
+    ) -> Result<&'a SubstateData, ConstraintMachineErrorKind> {
+        self.local_up_substates
+            .remove(&index)
+            .ok_or(ConstraintMachineErrorKind::LocalSubstateNotFound(index))
+    }
+
+    fn check_virtual_up(
+        &self,
+        parent: SubstateId,
+        key: &Bytes,
+    ) -> Result<(), ConstraintMachineErrorKind> {
+        let key = key.data.clone();
+        let id = DownSubstateId::Virtual {
+            parent,
+            key: key.clone(),
+        };
+        if self.remote_down_substates.contains(&id) {
+            return Err(ConstraintMachineErrorKind::VirtualSubstateAlreadyDown { parent, key });
+        }
+        Ok(())
+    }
+
+    fn virtual_read(
+        &self,
+        parent: &SubstateId,
+        key: &Bytes,
+    ) -> Result<SubstateData, ConstraintMachineErrorKind> {
+        self.check_virtual_up(*parent, key)?;
+        let raw = self.store.verify_virtual_substate(parent, &key.data)?;
+        match decode(&raw) {
+            Some(SubstateData::VirtualParent(p)) => virtual_substate(&p, key),
+            _ => Err(ConstraintMachineErrorKind::VirtualParentStateDoesNotExist(
+                *parent,
+            )),
+        }
+    }
+
+    fn local_virtual_read(
+        &self,
+        parent: SubstateIndex,
+        key: &Bytes,
+    ) -> Result<SubstateData, ConstraintMachineErrorKind> {
+        let parent_id = self.local_id(parent);
+        self.check_virtual_up(parent_id, key)?;
+        match self.local_up_substates.get(&parent) {
+            Some(SubstateData::VirtualParent(p)) => virtual_substate(p, key),
+            _ => Err(ConstraintMachineErrorKind::VirtualParentStateDoesNotExist(
+                parent_id,
+            )),
+        }
+    }
+
+    /// Returns the local and the stored substates which are up and match an index prefix,
+    /// spinning them down for `DOWNINDEX`.
+    fn index(
+        &mut self,
+        prefix: &IndexPrefix,
+        down: bool,
+        updates: &mut Vec<StateUpdate>,
+    ) -> Result<(Vec<&'a SubstateData>, Vec<SubstateData>), ConstraintMachineErrorKind> {
+        let mut raw_prefix = vec![prefix.type_id.to_u8()];
+        raw_prefix.extend_from_slice(&prefix.prefix.data);
+
+        let mut local_indices: Vec<SubstateIndex> = self
+            .local_up_substates
+            .iter()
+            .filter(|(_, s)| s.to_bytes().starts_with(&raw_prefix))
+            .map(|(index, _)| *index)
+            .collect();
+        local_indices.sort_unstable();
+        let mut local = Vec::new();
+        for index in local_indices {
+            if down {
+                local.push(self.local_shutdown(index)?);
+                let id = self.local_id(index);
+                updates.push(StateUpdate::Down(DownSubstateId::Stored(id)));
+            } else {
+                local.push(self.local_read(index)?);
+            }
+        }
+
+        let mut stored = Vec::new();
+        for (id, raw) in self.store.index(&raw_prefix)? {
+            let down_id = DownSubstateId::Stored(id);
+            if self.remote_down_substates.contains(&down_id) {
+                continue;
+            }
+            stored.push(decode(&raw).ok_or(ConstraintMachineErrorKind::CorruptSubstate(id))?);
+            if down {
+                self.shut_down(down_id, updates);
+            }
+        }
+        Ok((local, stored))
+    }
+}
+
+impl Resources for ValidationState<'_> {
+    fn load_resource(&self, address: &Address) -> Result<Resource, ConstraintMachineErrorKind> {
+        if let Some(resource) = self.local_resources.get(address) {
+            return Ok(*resource);
+        }
+        match self
+            .store
+            .load_resource(address)?
+            .as_deref()
+            .and_then(decode)
+        {
+            Some(SubstateData::TokenResource(resource)) => Ok(Resource::of(&resource)),
+            _ => Err(ConstraintMachineErrorKind::NotAResource(*address)),
+        }
+    }
+}
+
+impl Resource {
+    fn of(resource: &TokenResource) -> Self {
+        Self {
+            is_mutable: resource.is_mutable.raw != 0,
+            minter: resource.minter,
+        }
+    }
+}
+
+fn decode(raw: &[u8]) -> Option<SubstateData> {
+    SubstateData::from_buffer_exact(&mut Reader::new(raw)).ok()
+}
+
+/// Maps a raw virtual key to the substate it stands for, decoding it by the child type of its
+/// parent.
+fn virtual_substate(
+    parent: &VirtualParent,
+    key: &Bytes,
+) -> Result<SubstateData, ConstraintMachineErrorKind> {
+    let type_code = parent.data.data.first().copied().unwrap_or_default();
+    let invalid = || ConstraintMachineErrorKind::InvalidVirtualKey(type_code);
+    let VirtualSubstate {
+        type_id: child_type,
+        key,
+    } = parent.virtual_substate(key).map_err(|_| invalid())?;
+    if child_type == SubstateTypeId::UnclaimedREAddr {
+        return match key {
+            VirtualKey::Address(
+                address
+                @ (Address::System | Address::RadixNativeToken | Address::HashedKeyNonce(_)),
+            ) => Ok(SubstateData::UnclaimedREAddr(UnclaimedREAddr {
+                reserved: 0,
+                address,
+            })),
+            _ => Err(invalid()),
+        };
+    }
+    let validator = *key.as_validator().ok_or_else(invalid)?;
+    let empty = || UTF8 {
+        length: 0,
+        data: String::new(),
+    };
+    let substate = match child_type {
+        SubstateTypeId::ValidatorMetadata => SubstateData::ValidatorMetadata(ValidatorMetadata {
+            reserved: 0,
+            validator,
+            name: empty(),
+            url: empty(),
+        }),
+        SubstateTypeId::ValidatorStakeData => {
+            SubstateData::ValidatorStakeData(ValidatorStakeData {
+                reserved: 0,
+                is_registered: Boolean { raw: 0 },
+                amount: crate::types::U256 { raw: U256::zero() },
+                validator,
+                ownership: crate::types::U256 { raw: U256::zero() },
+                rake_percentage: MAX_RAKE,
+                owner: Address::PublicKey(validator.raw),
+            })
+        }
+        SubstateTypeId::ValidatorAllowDelegationFlag => {
+            SubstateData::ValidatorAllowDelegationFlag(ValidatorAllowDelegationFlag {
+                reserved: 0,
+                validator,
+                is_delegation_allowed: Boolean { raw: 0 },
+            })
+        }
+        SubstateTypeId::ValidatorRegisteredFlagCopy => {
+            SubstateData::ValidatorRegisteredFlagCopy(ValidatorRegisteredFlagCopy {
+                reserved: 0,
+                update_epoch: None,
+                validator,
+                is_registered: Boolean { raw: 0 },
+            })
+        }
+        SubstateTypeId::ValidatorRakeCopy => SubstateData::ValidatorRakeCopy(ValidatorRakeCopy {
+            reserved: 0,
+            update_epoch: None,
+            validator,
+            rake: MAX_RAKE,
+        }),
+        SubstateTypeId::ValidatorOwnerCopy => {
+            SubstateData::ValidatorOwnerCopy(ValidatorOwnerCopy {
+                reserved: 0,
+                update_epoch: None,
+                validator,
+                owner: Address::PublicKey(validator.raw),
+            })
+        }
+        SubstateTypeId::ValidatorSystemMetadata => {
+            SubstateData::ValidatorSystemMetadata(ValidatorSystemMetadata {
+                reserved: 0,
+                validator,
+                state: Hash { raw: [0; 32] },
+            })
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(substate)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use primitive_types::U256;
+
+    use crate::engine::ConstraintMachine;
+    use crate::engine::ExecutionContext;
+    use crate::engine::FeeTable;
+    use crate::engine::PermissionLevel;
+    use crate::engine::Procedures;
+    use crate::engine::StateUpdate;
+    use crate::error::ConstraintMachineErrorKind;
+    use crate::procedures::MAX_ROUNDS;
+    use crate::reader::Reader;
+    use crate::samples::samples;
+    use crate::store::InMemoryStore;
+    use crate::store::SubstateStore;
+    use crate::substates::*;
+    use crate::syscall::Syscall;
+    use crate::transaction::Instruction;
+    use crate::transaction::Transaction;
+    use crate::types::*;
+
+    const KEY: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const VALIDATOR: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    fn key(raw: &str) -> PublicKey {
+        let mut key = PublicKey { raw: [0; 33] };
+        key.raw.copy_from_slice(&hex::decode(raw).unwrap());
+        key
+    }
+
+    fn signer() -> PublicKey {
+        key(KEY)
+    }
+
+    fn account() -> Address {
+        Address::PublicKey(signer().raw)
+    }
+
+    /// XRD owned by the signer
+    fn tokens_of(amount: U256) -> SubstateData {
+        let raw = hex::decode(format!("0600{}01{:064x}", "04".to_owned() + KEY, amount)).unwrap();
+        SubstateData::from_buffer(&mut Reader::new(&raw)).unwrap()
+    }
+
+    fn substate_id(index: u32) -> SubstateId {
+        SubstateId {
+            hash: Hash { raw: [7; 32] },
+            index,
+        }
+    }
+
+    fn utf8(data: &str) -> UTF8 {
+        UTF8 {
+            length: data.len() as Size,
+            data: data.to_owned(),
+        }
+    }
+
+    fn store() -> InMemoryStore {
+        let parent = SubstateData::VirtualParent(VirtualParent {
+            reserved: 0,
+            data: Bytes::from_vec(vec![SubstateTypeId::UnclaimedREAddr.to_u8()]),
+        });
+        let mut store = InMemoryStore::new();
+        store
+            .commit(&[
+                StateUpdate::Up {
+                    id: substate_id(0),
+                    substate: tokens_of(U256::exp10(18)).to_bytes(),
+                },
+                StateUpdate::Up {
+                    id: substate_id(1),
+                    substate: parent.to_bytes(),
+                },
+            ])
+            .unwrap();
+        store
+    }
+
+    fn verify_at(
+        fees: FeeTable,
+        level: PermissionLevel,
+        signer: Option<PublicKey>,
+        instructions: Vec<Instruction>,
+    ) -> Result<Vec<Vec<StateUpdate>>, (ConstraintMachineErrorKind, usize)> {
+        verify_in(&store(), fees, level, signer, instructions)
+    }
+
+    fn verify_in(
+        store: &InMemoryStore,
+        fees: FeeTable,
+        level: PermissionLevel,
+        signer: Option<PublicKey>,
+        instructions: Vec<Instruction>,
+    ) -> Result<Vec<Vec<StateUpdate>>, (ConstraintMachineErrorKind, usize)> {
+        let tx = Transaction::new(instructions);
+        let machine = ConstraintMachine::new(Procedures::olympia(), fees);
+        machine
+            .verify(store, ExecutionContext::new(&tx, level, signer), &tx)
+            .map_err(|e| (e.kind, e.instruction_index))
+    }
+
+    fn verify(
+        fees: FeeTable,
+        signer: Option<PublicKey>,
+        instructions: Vec<Instruction>,
+    ) -> Result<Vec<Vec<StateUpdate>>, (ConstraintMachineErrorKind, usize)> {
+        verify_at(fees, PermissionLevel::User, signer, instructions)
+    }
+
+    fn execute(instructions: Vec<Instruction>) -> Result<(), (ConstraintMachineErrorKind, usize)> {
+        verify(FeeTable::default(), Some(signer()), instructions).map(|_| ())
+    }
+
+    fn token_creation(symbol: &str) -> Vec<Instruction> {
+        let resource = Address::resource_from(&signer(), symbol);
+        vec![
+            Instruction::SYSCALL(Syscall::ReaddrClaim(Bytes::from_slice(symbol.as_bytes()))),
+            Instruction::VDOWN(VirtualSubstateID {
+                parent: substate_id(1),
+                key: VirtualKey::Address(resource).to_bytes(),
+            }),
+            Instruction::UP(SubstateData::TokenResource(TokenResource {
+                reserved: 0,
+                resource,
+                granularity: crate::types::U256 { raw: U256::one() },
+                is_mutable: Boolean { raw: 0 },
+                minter: None,
+            })),
+            Instruction::UP(SubstateData::Tokens(Tokens {
+                reserved: 0,
+                owner: account(),
+                resource,
+                amount: crate::types::U256 {
+                    raw: U256::exp10(21),
+                },
+            })),
+            Instruction::UP(SubstateData::TokenResourceMetadata(TokenResourceMetadata {
+                reserved: 0,
+                resource,
+                symbol: utf8(symbol),
+                name: utf8("Foo"),
+                description: utf8(""),
+                url: utf8(""),
+                icon_url: utf8(""),
+            })),
+            Instruction::END,
+        ]
+    }
+
+    /// Creation of XRD at genesis
+    fn native_token_creation() -> Vec<Instruction> {
+        vec![
+            Instruction::SYSCALL(Syscall::ReaddrClaim(Bytes::from_slice(b"xrd"))),
+            Instruction::VDOWN(VirtualSubstateID {
+                parent: substate_id(1),
+                key: VirtualKey::Address(Address::RadixNativeToken).to_bytes(),
+            }),
+            Instruction::UP(SubstateData::TokenResource(TokenResource {
+                reserved: 0,
+                resource: Address::RadixNativeToken,
+                granularity: crate::types::U256 { raw: U256::one() },
+                is_mutable: Boolean { raw: 1 },
+                minter: None,
+            })),
+            Instruction::UP(SubstateData::TokenResourceMetadata(TokenResourceMetadata {
+                reserved: 0,
+                resource: Address::RadixNativeToken,
+                symbol: utf8("xrd"),
+                name: utf8("Radix"),
+                description: utf8(""),
+                url: utf8(""),
+                icon_url: utf8(""),
+            })),
+            Instruction::END,
+        ]
+    }
+
+    fn transfer(fee: U256, change: U256) -> Vec<Instruction> {
+        vec![
+            Instruction::DOWN(substate_id(0)),
+            Instruction::SYSCALL(Syscall::FeeReservePut(crate::types::U256 { raw: fee })),
+            Instruction::UP(tokens_of(change)),
+            Instruction::END,
+        ]
+    }
+
+    #[test]
+    fn transfers_tokens() {
+        let updates = verify(
+            FeeTable::default(),
+            Some(signer()),
+            vec![
+                Instruction::DOWN(substate_id(0)),
+                Instruction::UP(tokens_of(U256::exp10(17))),
+                Instruction::UP(tokens_of(U256::exp10(18) - U256::exp10(17))),
+                Instruction::END,
+            ],
+        )
+        .unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].len(), 3);
+
+        let other = key("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        assert_eq!(
+            verify(
+                FeeTable::default(),
+                Some(other),
+                vec![Instruction::DOWN(substate_id(0)), Instruction::END],
+            ),
+            Err((ConstraintMachineErrorKind::Unauthorized(Some(other)), 0))
+        );
+        assert_eq!(
+            execute(vec![
+                Instruction::DOWN(substate_id(0)),
+                Instruction::UP(tokens_of(U256::exp10(18) + 1)),
+            ]),
+            Err((
+                ConstraintMachineErrorKind::NotEnoughResources {
+                    request: U256::exp10(18) + 1,
+                    amount: U256::exp10(18)
+                },
+                1
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_substates() {
+        assert_eq!(
+            execute(vec![Instruction::DOWN(substate_id(2))]),
+            Err((
+                ConstraintMachineErrorKind::SubstateNotFound(substate_id(2)),
+                0
+            ))
+        );
+        assert_eq!(
+            execute(vec![
+                Instruction::DOWN(substate_id(0)),
+                Instruction::DOWN(substate_id(0)),
+            ]),
+            Err((
+                ConstraintMachineErrorKind::SubstateNotFound(substate_id(0)),
+                1
+            ))
+        );
+        assert_eq!(
+            execute(vec![Instruction::LDOWN(3)]),
+            Err((ConstraintMachineErrorKind::LocalSubstateNotFound(3), 0))
+        );
+    }
+
+    #[test]
+    fn instruction_sequence() {
+        assert!(matches!(
+            execute(vec![Instruction::READ(substate_id(0))]),
+            Err((ConstraintMachineErrorKind::MissingProcedure(_), 0))
+        ));
+        let mut instructions = token_creation("foo");
+        instructions.insert(5, Instruction::UP(tokens_of(U256::one())));
+        assert_eq!(
+            execute(instructions),
+            Err((ConstraintMachineErrorKind::MissingExpectedEnd, 5))
+        );
+    }
+
+    #[test]
+    fn creates_tokens() {
+        assert_eq!(execute(token_creation("foo")), Ok(()));
+        assert_eq!(
+            execute(token_creation("xrd")),
+            Err((
+                ConstraintMachineErrorKind::ReservedSymbol("xrd".to_owned()),
+                2
+            ))
+        );
+        assert_eq!(
+            verify(FeeTable::default(), None, token_creation("foo")).map(|_| ()),
+            Err((ConstraintMachineErrorKind::Procedure("Missing key"), 1))
+        );
+
+        let mut instructions = native_token_creation();
+        if let Instruction::UP(SubstateData::TokenResource(resource)) = &mut instructions[2] {
+            resource.granularity = crate::types::U256 { raw: 2.into() };
+        }
+        assert_eq!(
+            verify_at(
+                FeeTable::default(),
+                PermissionLevel::System,
+                None,
+                instructions
+            )
+            .map(|_| ()),
+            Err((
+                ConstraintMachineErrorKind::Procedure("Granularity must be one"),
+                2
+            ))
+        );
+    }
+
+    #[test]
+    fn commits_updates() {
+        let machine = ConstraintMachine::new(Procedures::olympia(), FeeTable::default());
+        let mut store = store();
+        let tx = Transaction::new(token_creation("foo"));
+        let context = || ExecutionContext::new(&tx, PermissionLevel::User, Some(signer()));
+        let updates = machine.verify(&store, context(), &tx).unwrap();
+        store.commit(&updates.concat()).unwrap();
+
+        let resource = Address::resource_from(&signer(), "foo");
+        assert!(store.load_resource(&resource).unwrap().is_some());
+        let mut key = Vec::new();
+        resource.write_to(&mut key);
+        assert_eq!(
+            machine.verify(&store, context(), &tx).map_err(|e| e.kind),
+            Err(ConstraintMachineErrorKind::VirtualSubstateAlreadyDown {
+                parent: substate_id(1),
+                key
+            })
+        );
+    }
+
+    #[test]
+    fn permission_levels() {
+        let mint = |level, signer| {
+            let instructions = vec![Instruction::UP(tokens_of(U256::one())), Instruction::END];
+            verify_at(FeeTable::mainnet(), level, signer, instructions).map(|_| ())
+        };
+        assert_eq!(
+            mint(PermissionLevel::User, Some(signer())),
+            Err((
+                ConstraintMachineErrorKind::InvalidPermission {
+                    required: PermissionLevel::System,
+                    actual: PermissionLevel::User
+                },
+                0
+            ))
+        );
+        assert_eq!(
+            mint(PermissionLevel::SuperUser, None),
+            Err((
+                ConstraintMachineErrorKind::InvalidPermission {
+                    required: PermissionLevel::System,
+                    actual: PermissionLevel::SuperUser
+                },
+                0
+            ))
+        );
+        assert_eq!(
+            mint(PermissionLevel::System, Some(signer())),
+            Err((ConstraintMachineErrorKind::SignedSystem, 0))
+        );
+        assert_eq!(mint(PermissionLevel::System, None), Ok(()));
+
+        let create = |level, signer| {
+            verify_at(FeeTable::mainnet(), level, signer, native_token_creation()).map(|_| ())
+        };
+        assert_eq!(create(PermissionLevel::System, None), Ok(()));
+        assert_eq!(
+            create(PermissionLevel::User, Some(signer())),
+            Err((
+                ConstraintMachineErrorKind::InvalidPermission {
+                    required: PermissionLevel::System,
+                    actual: PermissionLevel::User
+                },
+                1
+            ))
+        );
+    }
+
+    #[test]
+    fn charges_fees() {
+        let fees = FeeTable::mainnet();
+        let size = Transaction::new(transfer(U256::zero(), U256::zero()))
+            .to_bytes()
+            .len();
+        let fee = fees.per_byte_fee * size;
+        let balance = U256::exp10(18);
+        let verify = |fee: U256| {
+            verify(
+                FeeTable::mainnet(),
+                Some(signer()),
+                transfer(fee, balance - fee),
+            )
+            .map(|_| ())
+        };
+        assert_eq!(verify(fee), Ok(()));
+        assert_eq!(
+            verify(fee + 1),
+            Err((
+                ConstraintMachineErrorKind::ExecutionContextDestroy(U256::one()),
+                4
+            ))
+        );
+        assert_eq!(
+            verify(fee - 1),
+            Err((
+                ConstraintMachineErrorKind::DefaultedSystemLoan {
+                    fee_deposit: fee - 1,
+                    missing: U256::one()
+                },
+                2
+            ))
+        );
+    }
+
+    /// Substates of the genesis and of an earlier stake which the samples spend
+    fn sample_store() -> InMemoryStore {
+        let genesis = |index| SubstateId {
+            hash: Hash {
+                raw: hex_array("16e3e0c264f0cf21436ff2dbe163b6dd140b68a51f9bbe2154a8ed317a79c922"),
+            },
+            index,
+        };
+        let parent = |type_id: SubstateTypeId| {
+            SubstateData::VirtualParent(VirtualParent {
+                reserved: 0,
+                data: Bytes::from_vec(vec![type_id.to_u8()]),
+            })
+        };
+        let xrd = SubstateData::TokenResource(TokenResource {
+            reserved: 0,
+            resource: Address::RadixNativeToken,
+            granularity: crate::types::U256 { raw: U256::one() },
+            is_mutable: Boolean { raw: 1 },
+            minter: None,
+        });
+        let stake = SubstateData::StakeOwnership(StakeOwnership {
+            reserved: 0,
+            validator: key(VALIDATOR),
+            owner: account(),
+            amount: crate::types::U256 {
+                raw: U256::exp10(18) * 200,
+            },
+        });
+        let substates = vec![
+            (genesis(0), parent(SubstateTypeId::UnclaimedREAddr)),
+            (genesis(1), xrd),
+            (
+                genesis(5),
+                parent(SubstateTypeId::ValidatorAllowDelegationFlag),
+            ),
+            (
+                genesis(6),
+                parent(SubstateTypeId::ValidatorRegisteredFlagCopy),
+            ),
+            (genesis(8), parent(SubstateTypeId::ValidatorOwnerCopy)),
+            (
+                genesis(13),
+                tokens_of(
+                    U256::from_dec_str("1000100400000000000000").unwrap()
+                        + U256::from_dec_str("998999899600000000000000").unwrap(),
+                ),
+            ),
+            (
+                genesis(24),
+                SubstateData::EpochData(EpochData {
+                    reserved: 0,
+                    epoch: 1,
+                }),
+            ),
+            (
+                SubstateId {
+                    hash: Hash {
+                        raw: hex_array(
+                            "714e4e7291f37b81ed6304304950f46e52f7f590b9b3e0a25a8db05ed6cf34d4",
+                        ),
+                    },
+                    index: 0,
+                },
+                stake,
+            ),
+        ];
+        let mut store = InMemoryStore::new();
+        let updates: Vec<StateUpdate> = substates
+            .into_iter()
+            .map(|(id, substate)| StateUpdate::Up {
+                id,
+                substate: substate.to_bytes(),
+            })
+            .collect();
+        store.commit(&updates).unwrap();
+        store
+    }
+
+    fn hex_array(raw: &str) -> [u8; 32] {
+        let mut array = [0; 32];
+        array.copy_from_slice(&hex::decode(raw).unwrap());
+        array
+    }
+
+    #[test]
+    fn accepts_samples() {
+        // In the order the samples spend the outputs of each other
+        let order = [
+            "token_create",
+            "token_mint",
+            "token_transfer",
+            "token_burn",
+            "xrd_transfer",
+            "xrd_transfer_with_msg",
+            "validator_register",
+            "validator_unregister",
+            "validator_re_register",
+            "other_stake_from_validator_1",
+            "validator_allow_delegation",
+            "xrd_stake",
+            "other_stake_from_validator_2",
+            "xrd_unstake1",
+            "xrd_unstake2",
+            "other_transfer_to_self",
+            "other_transfer_mixed_tokens",
+            "other_complex_fee",
+        ];
+        let mut transactions: HashMap<String, Transaction> = samples()
+            .into_iter()
+            .map(|(name, _, tx)| (name, tx))
+            .collect();
+        assert_eq!(order.len(), transactions.len());
+        let machine = ConstraintMachine::new(Procedures::olympia(), FeeTable::mainnet());
+        let mut store = sample_store();
+        for sample in order.iter() {
+            let tx = transactions.remove(*sample).unwrap();
+            let signer = tx.recover_signer().unwrap();
+            let context = ExecutionContext::new(&tx, PermissionLevel::User, Some(signer));
+            let updates = machine
+                .verify(&store, context, &tx)
+                .unwrap_or_else(|e| panic!("{}: {:?}", sample, e));
+            let updates: Vec<StateUpdate> = updates.into_iter().flatten().collect();
+            store.commit(&updates).unwrap();
+        }
+    }
+
+    fn validator() -> PublicKey {
+        key(VALIDATOR)
+    }
+
+    fn xrd(amount: u64) -> crate::types::U256 {
+        crate::types::U256 {
+            raw: U256::exp10(18) * amount,
+        }
+    }
+
+    fn round(view: u64) -> SubstateData {
+        SubstateData::RoundData(RoundData {
+            reserved: 0,
+            view,
+            timestamp: view * 1000,
+        })
+    }
+
+    fn bft_data(proposals_completed: u64) -> SubstateData {
+        SubstateData::ValidatorBFTData(ValidatorBFTData {
+            reserved: 0,
+            validator: validator(),
+            proposals_completed,
+            proposals_missed: 0,
+        })
+    }
+
+    fn stake_data(amount: crate::types::U256, ownership: crate::types::U256) -> SubstateData {
+        SubstateData::ValidatorStakeData(ValidatorStakeData {
+            reserved: 0,
+            is_registered: Boolean { raw: 1 },
+            amount,
+            validator: validator(),
+            ownership,
+            rake_percentage: 1000,
+            owner: account(),
+        })
+    }
+
+    fn index_prefix(type_id: SubstateTypeId, prefix: Vec<u8>) -> IndexPrefix {
+        IndexPrefix {
+            type_id,
+            prefix: Bytes::from_vec(prefix),
+        }
+    }
+
+    /// Round 5 of epoch 1, with a validator staked to by the signer
+    fn system_store(epoch: u64) -> InMemoryStore {
+        let mut store = InMemoryStore::new();
+        let substates = vec![
+            round(5),
+            bft_data(1),
+            SubstateData::EpochData(EpochData { reserved: 0, epoch }),
+            stake_data(xrd(100), xrd(100)),
+            SubstateData::PreparedStake(PreparedStake {
+                reserved: 0,
+                validator: validator(),
+                owner: account(),
+                amount: xrd(90),
+            }),
+        ];
+        let updates: Vec<StateUpdate> = substates
+            .into_iter()
+            .enumerate()
+            .map(|(index, substate)| StateUpdate::Up {
+                id: substate_id(index as u32),
+                substate: substate.to_bytes(),
+            })
+            .collect();
+        store.commit(&updates).unwrap();
+        store
+    }
+
+    fn round_update(view: u64) -> Vec<Instruction> {
+        vec![
+            Instruction::DOWN(substate_id(0)),
+            Instruction::DOWN(substate_id(1)),
+            Instruction::UP(bft_data(10)),
+            Instruction::UP(round(view)),
+            Instruction::END,
+        ]
+    }
+
+    #[test]
+    fn updates_rounds_as_super_user() {
+        let update = |level, signer, view| {
+            verify_in(
+                &system_store(1),
+                FeeTable::mainnet(),
+                level,
+                signer,
+                round_update(view),
+            )
+            .map(|_| ())
+        };
+        assert_eq!(
+            update(PermissionLevel::User, Some(signer()), 6),
+            Err((
+                ConstraintMachineErrorKind::InvalidPermission {
+                    required: PermissionLevel::SuperUser,
+                    actual: PermissionLevel::User
+                },
+                0
+            ))
+        );
+        assert_eq!(update(PermissionLevel::SuperUser, None, 6), Ok(()));
+        assert_eq!(update(PermissionLevel::System, None, 6), Ok(()));
+        assert_eq!(
+            update(PermissionLevel::SuperUser, Some(signer()), 6),
+            Err((ConstraintMachineErrorKind::SignedSystem, 0))
+        );
+        assert_eq!(
+            update(PermissionLevel::SuperUser, None, 5),
+            Err((
+                ConstraintMachineErrorKind::Procedure("Next view must be greater than previous"),
+                3
+            ))
+        );
+    }
+
+    #[test]
+    fn updates_epochs() {
+        // The owner's rake of the 100 XRD emission is staked along with the prepared stake
+        let reward = U256::exp10(18) * 100;
+        let raked = U256::exp10(18) * 10;
+        let total_stake = xrd(100).raw + reward - raked;
+        let ownership = xrd(100).raw * (xrd(90).raw + raked) / total_stake;
+        let epoch_update = |staked: U256| {
+            let mut instructions = round_update(MAX_ROUNDS);
+            instructions.extend(vec![
+                Instruction::LDOWN(1),
+                Instruction::DOWN(substate_id(2)),
+                Instruction::DOWNINDEX(index_prefix(
+                    SubstateTypeId::ExitingStake,
+                    [vec![0], 2u64.to_be_bytes().to_vec()].concat(),
+                )),
+                Instruction::DOWNINDEX(index_prefix(SubstateTypeId::ValidatorBFTData, vec![])),
+                Instruction::DOWN(substate_id(3)),
+                Instruction::DOWNINDEX(index_prefix(SubstateTypeId::PreparedUnstake, vec![])),
+                Instruction::DOWNINDEX(index_prefix(SubstateTypeId::PreparedStake, vec![])),
+                Instruction::UP(SubstateData::StakeOwnership(StakeOwnership {
+                    reserved: 0,
+                    validator: validator(),
+                    owner: account(),
+                    amount: crate::types::U256 { raw: staked },
+                })),
+                Instruction::DOWNINDEX(index_prefix(
+                    SubstateTypeId::ValidatorRakeCopy,
+                    [vec![0, 1], 2u64.to_be_bytes().to_vec()].concat(),
+                )),
+                Instruction::DOWNINDEX(index_prefix(
+                    SubstateTypeId::ValidatorOwnerCopy,
+                    [vec![0, 1], 2u64.to_be_bytes().to_vec()].concat(),
+                )),
+                Instruction::DOWNINDEX(index_prefix(
+                    SubstateTypeId::ValidatorRegisteredFlagCopy,
+                    [vec![0, 1], 2u64.to_be_bytes().to_vec()].concat(),
+                )),
+                Instruction::UP(stake_data(
+                    crate::types::U256 {
+                        raw: total_stake + xrd(90).raw + raked,
+                    },
+                    crate::types::U256 {
+                        raw: xrd(100).raw + ownership,
+                    },
+                )),
+                Instruction::READINDEX(index_prefix(
+                    SubstateTypeId::ValidatorStakeData,
+                    vec![0, 1],
+                )),
+                Instruction::UP(bft_data(0)),
+                Instruction::UP(SubstateData::EpochData(EpochData {
+                    reserved: 0,
+                    epoch: 2,
+                })),
+                Instruction::UP(round(0)),
+                Instruction::END,
+            ]);
+            instructions
+        };
+        let update_at = |epoch, instructions| {
+            verify_in(
+                &system_store(epoch),
+                FeeTable::mainnet(),
+                PermissionLevel::SuperUser,
+                None,
+                instructions,
+            )
+            .map(|_| ())
+        };
+        let update = |instructions| update_at(1, instructions);
+        assert_eq!(update(epoch_update(ownership)), Ok(()));
+
+        // Staking is checked against the stake ratio after the emission
+        assert_eq!(
+            update(epoch_update(xrd(100).raw)),
+            Err((
+                ConstraintMachineErrorKind::Procedure("Invalid stake ownership"),
+                12
+            ))
+        );
+
+        // An epoch only ends at the last round
+        let mut instructions = round_update(6);
+        instructions.extend(vec![
+            Instruction::LDOWN(1),
+            Instruction::DOWN(substate_id(2)),
+        ]);
+        assert_eq!(
+            update(instructions),
+            Err((
+                ConstraintMachineErrorKind::Procedure("Must execute epoch update on end of round"),
+                6
+            ))
+        );
+
+        // The stored epoch is not trusted to have a next one
+        assert_eq!(
+            update_at(u64::MAX, epoch_update(ownership)),
+            Err((ConstraintMachineErrorKind::Procedure("Epoch overflow"), 7))
+        );
+    }
+}
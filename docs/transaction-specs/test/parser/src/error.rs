@@ -0,0 +1,658 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::engine::PermissionLevel;
+use crate::engine::ProcedureKey;
+use crate::types::Address;
+use crate::types::PublicKey;
+use crate::types::SubstateId;
+use crate::types::SubstateIndex;
+
+/// Parsing error, located by byte offset and instruction index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Offset of the offending byte, relative to the start of the input
+    pub offset: usize,
+    /// Index of the instruction being parsed, if parsing a transaction
+    pub instruction_index: Option<usize>,
+}
+
+/// Parsing failures, as listed in the "Error Handling" section of the spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// More bytes are required than remain unparsed
+    Underflow {
+        field: &'static str,
+        needed: usize,
+        available: usize,
+    },
+
+    InvalidBoolean(u8),
+
+    InvalidAddressType(u8),
+
+    InvalidOpcode(u8),
+
+    InvalidSubstateType(u8),
+
+    /// Substate type which has no virtual substates
+    InvalidVirtualSubstateType(u8),
+
+    /// A virtual key was decoded without consuming all of its bytes
+    InvalidVirtualKeyLength {
+        type_id: u8,
+        length: usize,
+    },
+
+    InvalidSyscall(u8),
+
+    InvalidHeaderVersion(u8),
+
+    InvalidHeaderFlags(u8),
+
+    /// Call data length, including the function code, does not match the system function
+    InvalidCallDataLength(usize),
+
+    NonZeroReservedByte(u8),
+
+    InvalidUtf8,
+
+    /// A substate was decoded without consuming all bytes of its size prefix
+    SubstateSizeMismatch {
+        declared: usize,
+        consumed: usize,
+    },
+
+    /// Not a compressed point on the curve, with `ParseOptions::strict_crypto`
+    InvalidPublicKey,
+
+    /// The recovery byte `v` is not within 0..=3, with `ParseOptions::strict_crypto`
+    InvalidRecoveryId(u8),
+
+    /// `r` or `s` is zero or not below the curve order, with `ParseOptions::strict_crypto`
+    InvalidSignature,
+
+    /// `s` is above half the curve order, with `ParseOptions::strict_crypto`
+    HighSignatureS,
+}
+
+/// Failures to build a transaction, following the grouping rules of the engine parser
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// An `END` was added without any substate update since the previous one
+    EmptyGroup,
+
+    /// Substate updates were added without a terminating `END`
+    MissingEnd,
+
+    /// No instruction group was added
+    NoStateUpdates,
+
+    /// More `UP` instructions than can be addressed by a substate index
+    TooManySubstates,
+}
+
+/// Failures to verify a signature or recover its signer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The transaction has no `SIG` instruction
+    MissingSignature,
+
+    /// The recovery byte `v` is not within 0..=3
+    InvalidRecoveryId(u8),
+
+    InvalidSignature,
+
+    InvalidPrivateKey,
+
+    /// The transaction already has a `SIG` instruction
+    AlreadySigned,
+}
+
+/// Failures to encode or decode a Bech32 address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// Not a valid Bech32 string, or a bad checksum
+    InvalidBech32,
+
+    /// The human-readable part does not match the network or address kind
+    InvalidHrp(String),
+
+    /// The address type byte does not match the address kind
+    InvalidAddressType(u8),
+
+    /// The data is too short or too long for the address kind
+    InvalidLength(usize),
+
+    /// The resource symbol is not allowed in a human-readable part
+    InvalidSymbol(String),
+
+    /// The data is not a compressed point on the curve
+    InvalidPublicKey,
+}
+
+/// Failures to match a token creation with its `READDR_CLAIM` syscall
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenCreationError {
+    /// The address does not derive from the signer's key and the claimed symbol
+    AddressMismatch { expected: Address, actual: Address },
+
+    /// The metadata symbol differs from the claimed symbol
+    SymbolMismatch { expected: String, actual: String },
+
+    /// The group of a claim has no `TokenResource` substate
+    MissingTokenResource,
+
+    /// The group of a claim has no `TokenResourceMetadata` substate
+    MissingMetadata,
+
+    /// The key spun down in the group of a claim is not an address
+    InvalidClaimedAddress(ParseError),
+
+    /// The group of a claim is not terminated by `END`
+    MissingEnd,
+}
+
+/// Violation of a static rule by a substate field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticCheckError {
+    pub field: &'static str,
+    pub kind: StaticCheckErrorKind,
+}
+
+/// Static rules from the "Substate Static Check" section of the spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StaticCheckErrorKind {
+    /// More characters than allowed
+    TooLong {
+        max: usize,
+        actual: usize,
+    },
+
+    /// Neither empty nor of OWASP URL format
+    InvalidUrl,
+
+    /// An amount which must be non-zero
+    Zero,
+
+    /// An address which must be an account address
+    NotAccountAddress,
+
+    OutOfRange {
+        max: u32,
+        actual: u32,
+    },
+}
+
+/// Stateless validation error, located by instruction index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub kind: ValidationErrorKind,
+    pub instruction_index: usize,
+}
+
+/// Violations of the instruction rules in the "Stateless Validation" section of the spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationErrorKind {
+    /// `HEADER` is repeated or not the first instruction
+    MisplacedHeader,
+
+    InvalidHeaderVersion(u8),
+
+    /// `SIG` is repeated or not the last instruction
+    MisplacedSignature,
+
+    /// More than one `MSG` instruction
+    DuplicateMessage,
+
+    /// A local substate index which is not below the number of preceding `UP` instructions
+    InvalidLocalIndex {
+        index: SubstateIndex,
+        up_count: usize,
+    },
+
+    /// An index prefix, including its substate type, of 10 bytes or more
+    InvalidIndexPrefixLength(usize),
+
+    /// Call data length, including the function code, does not match the system function
+    InvalidCallDataLength(usize),
+
+    /// The substate of an `UP` instruction violates a static rule
+    InvalidSubstate(StaticCheckError),
+
+    /// A group without state updates, located at its `END`
+    EmptyGroup,
+
+    /// State updates after the last `END`, located at the first of them
+    MissingEnd,
+
+    /// No instruction group at all, located past the last instruction
+    NoStateUpdates,
+}
+
+/// Constraint machine abort, located by instruction index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintMachineError {
+    pub kind: ConstraintMachineErrorKind,
+    /// Index of the offending instruction, or the instruction count if the transaction aborted
+    /// once all instructions were executed
+    pub instruction_index: usize,
+}
+
+/// Abort reasons of the constraint machine, after the causes of the engine's
+/// `ConstraintMachineException`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstraintMachineErrorKind {
+    /// An instruction other than `END` after the current state became void
+    MissingExpectedEnd,
+
+    MissingProcedure(ProcedureKey),
+
+    /// The transaction is executed below the level a procedure requires
+    InvalidPermission {
+        required: PermissionLevel,
+        actual: PermissionLevel,
+    },
+
+    /// A signed transaction calls a procedure which requires `SuperUser` or above
+    SignedSystem,
+
+    SubstateNotFound(SubstateId),
+
+    LocalSubstateNotFound(SubstateIndex),
+
+    VirtualSubstateAlreadyDown {
+        parent: SubstateId,
+        key: Vec<u8>,
+    },
+
+    VirtualParentStateDoesNotExist(SubstateId),
+
+    /// The virtual key does not fit the substate type of its parent
+    InvalidVirtualKey(u8),
+
+    /// A substate type the engine cannot deserialize
+    UnknownSubstateType(u8),
+
+    /// A stored substate which fails to deserialize
+    CorruptSubstate(SubstateId),
+
+    /// The signer, if any, is not authorized
+    Unauthorized(Option<PublicKey>),
+
+    InvalidResource {
+        expected: Address,
+        actual: Address,
+    },
+
+    NotEnoughResources {
+        request: primitive_types::U256,
+        amount: primitive_types::U256,
+    },
+
+    NotAResource(Address),
+
+    ReservedSymbol(String),
+
+    /// The claimed address does not derive from the signer's key and symbol
+    InvalidHashedKey,
+
+    MinimumStake {
+        minimum: primitive_types::U256,
+        actual: primitive_types::U256,
+    },
+
+    /// Mint or burn with `TxFlags::MINT_BURN_DISABLED`
+    ResourceAllocationAndDestruction,
+
+    MultipleFeeReserveDeposit,
+
+    DepletedFeeReserve {
+        charge: primitive_types::U256,
+        reserve: primitive_types::U256,
+    },
+
+    /// The fee reserve cannot pay back the system loan
+    DefaultedSystemLoan {
+        fee_deposit: primitive_types::U256,
+        missing: primitive_types::U256,
+    },
+
+    /// The fee reserve is not empty once all instructions were executed
+    ExecutionContextDestroy(primitive_types::U256),
+
+    /// Any other procedure failure, as a `ProcedureException` message
+    Procedure(&'static str),
+
+    /// Failure to read the substate store, which leaves the transaction unverified rather than
+    /// rejected
+    Store(StoreError),
+}
+
+/// Failures of the substate store, to read it or to commit state updates, in which case none of
+/// them is applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// A substate spun down is not up
+    SubstateNotFound(SubstateId),
+
+    /// A substate booted up has the ID of an existing one
+    SubstateAlreadyExists(SubstateId),
+
+    VirtualSubstateAlreadyDown {
+        parent: SubstateId,
+        key: Vec<u8>,
+    },
+
+    VirtualParentStateDoesNotExist(SubstateId),
+
+    /// Failure to read or write the underlying storage, as an I/O error message
+    Io(String),
+}
+
+impl ConstraintMachineErrorKind {
+    /// Returns whether the transaction is invalid, as opposed to the store failing to verify it.
+    pub fn is_rejection(&self) -> bool {
+        !matches!(self, Self::Store(_))
+    }
+}
+
+impl From<StoreError> for ConstraintMachineErrorKind {
+    fn from(e: StoreError) -> Self {
+        Self::Store(e)
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, offset: usize) -> Self {
+        Self {
+            kind,
+            offset,
+            instruction_index: None,
+        }
+    }
+
+    pub fn at_instruction(mut self, index: usize) -> Self {
+        self.instruction_index = Some(index);
+        self
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Underflow {
+                field,
+                needed,
+                available,
+            } => write!(
+                f,
+                "Underflow reading {}: {} bytes needed, {} available",
+                field, needed, available
+            ),
+            Self::InvalidBoolean(v) => write!(f, "Invalid boolean value: {}", v),
+            Self::InvalidAddressType(t) => write!(f, "Invalid address type: {:#04X}", t),
+            Self::InvalidOpcode(t) => write!(f, "Unexpected opcode: {:#04X}", t),
+            Self::InvalidSubstateType(t) => write!(f, "Unsupported substate type: {:#04X}", t),
+            Self::InvalidVirtualSubstateType(t) => {
+                write!(f, "Substate type {:#04X} has no virtual substates", t)
+            }
+            Self::InvalidVirtualKeyLength { type_id, length } => write!(
+                f,
+                "Invalid virtual key length for type {:#04X}: {}",
+                type_id, length
+            ),
+            Self::InvalidSyscall(t) => write!(f, "Invalid system function: {:#04X}", t),
+            Self::InvalidHeaderVersion(v) => write!(f, "Unsupported header version: {:#04X}", v),
+            Self::InvalidHeaderFlags(v) => write!(f, "Invalid header flags: {:#04X}", v),
+            Self::InvalidCallDataLength(l) => write!(f, "Invalid call data length: {}", l),
+            Self::NonZeroReservedByte(v) => {
+                write!(f, "Reserved byte should be zero, actual: {}", v)
+            }
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 string"),
+            Self::SubstateSizeMismatch { declared, consumed } => write!(
+                f,
+                "Substate size mismatch: {} bytes declared, {} consumed",
+                declared, consumed
+            ),
+            Self::InvalidPublicKey => write!(f, "Invalid public key"),
+            Self::InvalidRecoveryId(v) => write!(f, "Invalid recovery byte: {}", v),
+            Self::InvalidSignature => write!(f, "Invalid signature"),
+            Self::HighSignatureS => write!(f, "Signature is not canonical (high s)"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)?;
+        if let Some(index) = self.instruction_index {
+            write!(f, " (instruction #{})", index)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyGroup => write!(f, "Empty group"),
+            Self::MissingEnd => write!(f, "Missing end"),
+            Self::NoStateUpdates => write!(f, "No state updates"),
+            Self::TooManySubstates => write!(f, "Too many substates"),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSignature => write!(f, "Missing signature"),
+            Self::InvalidRecoveryId(v) => write!(f, "Invalid recovery byte: {}", v),
+            Self::InvalidSignature => write!(f, "Invalid signature"),
+            Self::InvalidPrivateKey => write!(f, "Invalid private key"),
+            Self::AlreadySigned => write!(f, "Transaction is already signed"),
+        }
+    }
+}
+
+impl Error for SignatureError {}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidBech32 => write!(f, "Invalid Bech32 string"),
+            Self::InvalidHrp(hrp) => write!(f, "Unexpected human-readable part: {}", hrp),
+            Self::InvalidAddressType(t) => write!(f, "Unexpected address type: {:#04X}", t),
+            Self::InvalidLength(l) => write!(f, "Invalid address length: {}", l),
+            Self::InvalidSymbol(s) => write!(f, "Invalid resource symbol: {}", s),
+            Self::InvalidPublicKey => write!(f, "Invalid public key"),
+        }
+    }
+}
+
+impl Error for AddressError {}
+
+impl fmt::Display for TokenCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddressMismatch { expected, actual } => write!(
+                f,
+                "Resource address mismatch: expected {:?}, actual {:?}",
+                expected, actual
+            ),
+            Self::SymbolMismatch { expected, actual } => write!(
+                f,
+                "Symbol mismatch: expected {}, actual {}",
+                expected, actual
+            ),
+            Self::MissingTokenResource => write!(f, "Missing token resource"),
+            Self::MissingMetadata => write!(f, "Missing token resource metadata"),
+            Self::InvalidClaimedAddress(e) => write!(f, "Invalid claimed address: {}", e),
+            Self::MissingEnd => write!(f, "Missing end of token creation"),
+        }
+    }
+}
+
+impl Error for TokenCreationError {}
+
+impl StaticCheckError {
+    pub fn new(field: &'static str, kind: StaticCheckErrorKind) -> Self {
+        Self { field, kind }
+    }
+}
+
+impl fmt::Display for StaticCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.field)?;
+        match &self.kind {
+            StaticCheckErrorKind::TooLong { max, actual } => {
+                write!(f, "{} characters, at most {} allowed", actual, max)
+            }
+            StaticCheckErrorKind::InvalidUrl => write!(f, "not a valid URL"),
+            StaticCheckErrorKind::Zero => write!(f, "must be non-zero"),
+            StaticCheckErrorKind::NotAccountAddress => write!(f, "not an account address"),
+            StaticCheckErrorKind::OutOfRange { max, actual } => {
+                write!(f, "{} is not within [0, {}]", actual, max)
+            }
+        }
+    }
+}
+
+impl Error for StaticCheckError {}
+
+impl fmt::Display for ValidationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MisplacedHeader => write!(f, "Header must be first"),
+            Self::InvalidHeaderVersion(v) => write!(f, "Unsupported header version: {:#04X}", v),
+            Self::MisplacedSignature => write!(f, "Signature must be last"),
+            Self::DuplicateMessage => write!(f, "Too many messages"),
+            Self::InvalidLocalIndex { index, up_count } => write!(
+                f,
+                "Invalid local substate index: {}, {} substates up",
+                index, up_count
+            ),
+            Self::InvalidIndexPrefixLength(l) => write!(f, "Invalid index prefix length: {}", l),
+            Self::InvalidCallDataLength(l) => write!(f, "Invalid call data length: {}", l),
+            Self::InvalidSubstate(e) => write!(f, "Invalid substate: {}", e),
+            Self::EmptyGroup => write!(f, "Empty group"),
+            Self::MissingEnd => write!(f, "Missing end"),
+            Self::NoStateUpdates => write!(f, "No state updates"),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (instruction #{})", self.kind, self.instruction_index)
+    }
+}
+
+impl Error for ValidationError {}
+
+impl fmt::Display for ConstraintMachineErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingExpectedEnd => write!(f, "Missing expected end"),
+            Self::MissingProcedure(key) => write!(f, "Missing procedure: {:?}", key),
+            Self::InvalidPermission { required, actual } => write!(
+                f,
+                "Required permission level {:?} but was {:?}",
+                required, actual
+            ),
+            Self::SignedSystem => write!(f, "System updates must not be signed"),
+            Self::SubstateNotFound(id) => write!(f, "Substate {:?} not found", id),
+            Self::LocalSubstateNotFound(index) => {
+                write!(f, "Local substate with index {} not found", index)
+            }
+            Self::VirtualSubstateAlreadyDown { parent, key } => write!(
+                f,
+                "Virtual substate {} of {:?} already down",
+                hex::encode(key),
+                parent
+            ),
+            Self::VirtualParentStateDoesNotExist(id) => {
+                write!(f, "Virtual parent {:?} does not exist", id)
+            }
+            Self::InvalidVirtualKey(t) => write!(f, "Invalid virtual key for type {:#04X}", t),
+            Self::UnknownSubstateType(t) => write!(f, "Unknown substate type: {:#04X}", t),
+            Self::CorruptSubstate(id) => write!(f, "Substate {:?} cannot be deserialized", id),
+            Self::Unauthorized(key) => write!(f, "Key not authorized: {:?}", key),
+            Self::InvalidResource { expected, actual } => {
+                write!(f, "Expected resource {:?} but was {:?}", expected, actual)
+            }
+            Self::NotEnoughResources { request, amount } => {
+                write!(f, "Requested {} but only {} available", request, amount)
+            }
+            Self::NotAResource(address) => write!(f, "{:?} is not a resource", address),
+            Self::ReservedSymbol(symbol) => write!(f, "Reserved symbol: {}", symbol),
+            Self::InvalidHashedKey => write!(f, "Hashed key does not match"),
+            Self::MinimumStake { minimum, actual } => {
+                write!(f, "Minimum stake is {} but was {}", minimum, actual)
+            }
+            Self::ResourceAllocationAndDestruction => {
+                write!(f, "Allocation and destruction of resources not enabled")
+            }
+            Self::MultipleFeeReserveDeposit => write!(f, "Multiple fee reserve deposits"),
+            Self::DepletedFeeReserve { charge, reserve } => write!(
+                f,
+                "Charging {} but fee reserve only contains {}",
+                charge, reserve
+            ),
+            Self::DefaultedSystemLoan {
+                fee_deposit,
+                missing,
+            } => write!(
+                f,
+                "Fee deposit {} is {} short of the basic transaction fee",
+                fee_deposit, missing
+            ),
+            Self::ExecutionContextDestroy(remaining) => {
+                write!(f, "Fee reserve not empty: {}", remaining)
+            }
+            Self::Procedure(message) => write!(f, "{}", message),
+            Self::Store(e) => write!(f, "Store failure: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for ConstraintMachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (instruction #{})", self.kind, self.instruction_index)
+    }
+}
+
+impl Error for ConstraintMachineError {}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SubstateNotFound(id) => write!(f, "Substate {:?} not found", id),
+            Self::SubstateAlreadyExists(id) => write!(f, "Substate {:?} already exists", id),
+            Self::VirtualSubstateAlreadyDown { parent, key } => write!(
+                f,
+                "Virtual substate {} of {:?} already down",
+                hex::encode(key),
+                parent
+            ),
+            Self::VirtualParentStateDoesNotExist(id) => {
+                write!(f, "Virtual parent {:?} does not exist", id)
+            }
+            Self::Io(message) => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl Error for StoreError {}
@@ -0,0 +1,734 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::engine::DownSubstateId;
+use crate::engine::StateUpdate;
+use crate::error::ConstraintMachineErrorKind;
+use crate::error::StoreError;
+use crate::reader::Reader;
+use crate::store::check_updates;
+use crate::store::SubstateStore;
+use crate::substates::SubstateData;
+use crate::types::Address;
+use crate::types::SubstateId;
+use crate::validation::MAX_INDEX_PREFIX_LENGTH;
+
+const RECORD_UP: u8 = 0x00;
+const RECORD_DOWN: u8 = 0x01;
+const RECORD_VIRTUAL_DOWN: u8 = 0x02;
+const RECORD_COMMIT: u8 = 0x03;
+
+/// Serialized length of a substate ID
+const SUBSTATE_ID_LENGTH: usize = 36;
+
+/// How many times larger than its compacted size the log may grow before it is compacted on open
+const COMPACTION_FACTOR: u64 = 2;
+
+/// Substate store backed by an append-only log file
+///
+/// Each commit appends the state updates of a transaction followed by a commit marker, which
+/// makes it atomic: a trailing commit without marker, e.g. after a crash, is discarded on open.
+/// Substates are read from the log on demand; only their locations, index keys, virtual
+/// substates which are down and resource addresses are kept in memory, so memory grows with the
+/// number of substates which are up and virtual substates which are down, never with history.
+///
+/// Substates which are down still take space in the log until it is compacted, i.e. rewritten
+/// with the current state only, by `compact` or on open once the log is more than
+/// `COMPACTION_FACTOR` times its compacted size.
+///
+/// The index key of a substate is its first `MAX_INDEX_PREFIX_LENGTH` bytes, i.e. the longest
+/// prefix `READINDEX` and `DOWNINDEX` may use.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    file: RefCell<File>,
+    length: u64,
+    locations: HashMap<SubstateId, Location>,
+    /// Index keys, each followed by the serialized ID of its substate
+    index: BTreeSet<Vec<u8>>,
+    virtual_down_substates: HashSet<(SubstateId, Vec<u8>)>,
+    resources: HashMap<Address, SubstateId>,
+    last_commit: Option<LastCommit>,
+}
+
+/// Location of a substate in the log, with its index key
+#[derive(Debug, Clone)]
+struct Location {
+    offset: u64,
+    length: u32,
+    index_key: Vec<u8>,
+}
+
+/// What it takes to roll back the last commit
+#[derive(Debug)]
+struct LastCommit {
+    length: u64,
+    updates: Vec<StateUpdate>,
+    down_locations: HashMap<SubstateId, Location>,
+}
+
+/// Record of the log, with substates located rather than loaded
+enum Record {
+    Up {
+        id: SubstateId,
+        location: Location,
+        resource: Option<Address>,
+    },
+
+    Down(SubstateId),
+
+    VirtualDown {
+        parent: SubstateId,
+        key: Vec<u8>,
+    },
+}
+
+impl FileStore {
+    /// Opens the store at `path`, creating it if missing, replaying its log and compacting it if
+    /// mostly made of substates which are down.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut store = Self::load(path, file)?;
+        if store.length > COMPACTION_FACTOR * store.compacted_length() {
+            store.compact()?;
+        }
+        Ok(store)
+    }
+
+    /// Rewrites the log with the current state only, as a single commit, after which the last
+    /// commit can no longer be rolled back.
+    ///
+    /// The compacted log is written aside then renamed over the log, so a crash leaves either.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".compact");
+        let temp = PathBuf::from(temp);
+
+        let mut substates: Vec<(&SubstateId, &Location)> = self.locations.iter().collect();
+        substates.sort_unstable_by_key(|(_, location)| location.offset);
+
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        for (id, location) in substates {
+            let mut out = Vec::new();
+            write_up(&mut out, id, &self.read(location)?);
+            writer.write_all(&out)?;
+        }
+        for (parent, key) in &self.virtual_down_substates {
+            let mut out = Vec::new();
+            write_virtual_down(&mut out, parent, key);
+            writer.write_all(&out)?;
+        }
+        writer.write_all(&[RECORD_COMMIT])?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        fs::rename(&temp, &self.path)?;
+
+        let file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        *self = Self::load(self.path.clone(), file)?;
+        Ok(())
+    }
+
+    /// Returns the length of the log once compacted.
+    fn compacted_length(&self) -> u64 {
+        let substates: u64 = self
+            .locations
+            .values()
+            .map(|location| 1 + SUBSTATE_ID_LENGTH as u64 + 4 + location.length as u64)
+            .sum();
+        let virtual_down_substates: u64 = self
+            .virtual_down_substates
+            .iter()
+            .map(|(_, key)| 1 + SUBSTATE_ID_LENGTH as u64 + 2 + key.len() as u64)
+            .sum();
+        substates + virtual_down_substates + 1
+    }
+
+    fn load(path: PathBuf, file: File) -> io::Result<Self> {
+        let mut store = Self {
+            path,
+            file: RefCell::new(file),
+            length: 0,
+            locations: HashMap::new(),
+            index: BTreeSet::new(),
+            virtual_down_substates: HashSet::new(),
+            resources: HashMap::new(),
+            last_commit: None,
+        };
+        store.replay()?;
+        Ok(store)
+    }
+
+    /// Rolls back the last transaction committed since the store was opened, returning whether
+    /// there was one.
+    pub fn rollback(&mut self) -> io::Result<bool> {
+        let last_commit = match self.last_commit.take() {
+            Some(last_commit) => last_commit,
+            None => return Ok(false),
+        };
+        let mut down_locations = last_commit.down_locations;
+        for update in last_commit.updates.iter().rev() {
+            match update {
+                StateUpdate::Up { id, .. } => {
+                    self.remove(id);
+                    self.resources.retain(|_, resource| resource != id);
+                }
+                StateUpdate::Down(DownSubstateId::Stored(id)) => {
+                    if let Some(location) = down_locations.remove(id) {
+                        self.insert(*id, location);
+                    }
+                }
+                StateUpdate::Down(DownSubstateId::Virtual { parent, key }) => {
+                    self.virtual_down_substates.remove(&(*parent, key.clone()));
+                }
+            }
+        }
+        let file = self.file.get_mut();
+        file.set_len(last_commit.length)?;
+        file.sync_data()?;
+        self.length = last_commit.length;
+        Ok(true)
+    }
+
+    /// Rebuilds the in-memory state from the log, truncating a trailing incomplete commit.
+    fn replay(&mut self) -> io::Result<()> {
+        let mut reader = BufReader::new(self.file.get_mut().try_clone()?);
+        reader.seek(SeekFrom::Start(0))?;
+        let mut offset = 0;
+        let mut committed = 0;
+        let mut records = Vec::new();
+        loop {
+            match read_record(&mut reader, &mut offset) {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) => {
+                    for record in records.drain(..) {
+                        self.apply(record);
+                    }
+                    committed = offset;
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let file = self.file.get_mut();
+        if file.metadata()?.len() != committed {
+            file.set_len(committed)?;
+            file.sync_data()?;
+        }
+        self.length = committed;
+        Ok(())
+    }
+
+    fn apply(&mut self, record: Record) -> Option<(SubstateId, Location)> {
+        match record {
+            Record::Up {
+                id,
+                location,
+                resource,
+            } => {
+                if let Some(resource) = resource {
+                    self.resources.insert(resource, id);
+                }
+                self.insert(id, location);
+                None
+            }
+            Record::Down(id) => self.remove(&id).map(|location| (id, location)),
+            Record::VirtualDown { parent, key } => {
+                self.virtual_down_substates.insert((parent, key));
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, id: SubstateId, location: Location) {
+        self.index.insert(index_entry(&id, &location));
+        self.locations.insert(id, location);
+    }
+
+    fn remove(&mut self, id: &SubstateId) -> Option<Location> {
+        let location = self.locations.remove(id)?;
+        self.index.remove(&index_entry(id, &location));
+        Some(location)
+    }
+
+    fn read(&self, location: &Location) -> io::Result<Vec<u8>> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut raw = vec![0; location.length as usize];
+        file.read_exact(&mut raw)?;
+        Ok(raw)
+    }
+
+    /// Appends a commit to the log, returning its records.
+    fn append(&mut self, updates: &[StateUpdate]) -> io::Result<Vec<Record>> {
+        let mut out = Vec::new();
+        let mut records = Vec::new();
+        for update in updates {
+            match update {
+                StateUpdate::Up { id, substate } => {
+                    write_up(&mut out, id, substate);
+                    let location = Location {
+                        offset: self.length + (out.len() - substate.len()) as u64,
+                        length: substate.len() as u32,
+                        index_key: index_key(substate),
+                    };
+                    records.push(Record::Up {
+                        id: *id,
+                        location,
+                        resource: resource_of(substate),
+                    });
+                }
+                StateUpdate::Down(DownSubstateId::Stored(id)) => {
+                    out.push(RECORD_DOWN);
+                    id.write_to(&mut out);
+                    records.push(Record::Down(*id));
+                }
+                StateUpdate::Down(DownSubstateId::Virtual { parent, key }) => {
+                    write_virtual_down(&mut out, parent, key);
+                    records.push(Record::VirtualDown {
+                        parent: *parent,
+                        key: key.clone(),
+                    });
+                }
+            }
+        }
+        out.push(RECORD_COMMIT);
+
+        let file = self.file.get_mut();
+        let result = file
+            .seek(SeekFrom::Start(self.length))
+            .and_then(|_| file.write_all(&out))
+            .and_then(|_| file.sync_data());
+        if let Err(e) = result {
+            // Best effort, as an incomplete commit is discarded on open anyway
+            let _ = file.set_len(self.length);
+            return Err(e);
+        }
+        self.length += out.len() as u64;
+        Ok(records)
+    }
+}
+
+impl SubstateStore for FileStore {
+    fn load_substate(&self, id: &SubstateId) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.locations.get(id) {
+            Some(location) => Ok(Some(self.read(location)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn verify_virtual_substate(
+        &self,
+        parent: &SubstateId,
+        key: &[u8],
+    ) -> Result<Vec<u8>, ConstraintMachineErrorKind> {
+        if self
+            .virtual_down_substates
+            .contains(&(*parent, key.to_vec()))
+        {
+            return Err(ConstraintMachineErrorKind::VirtualSubstateAlreadyDown {
+                parent: *parent,
+                key: key.to_vec(),
+            });
+        }
+        self.load_substate(parent)?.ok_or(
+            ConstraintMachineErrorKind::VirtualParentStateDoesNotExist(*parent),
+        )
+    }
+
+    fn index(&self, prefix: &[u8]) -> Result<Vec<(SubstateId, Vec<u8>)>, StoreError> {
+        let key_prefix = &prefix[..prefix.len().min(MAX_INDEX_PREFIX_LENGTH)];
+        let mut substates = Vec::new();
+        for key in self
+            .index
+            .range(key_prefix.to_vec()..)
+            .take_while(|key| key.starts_with(key_prefix))
+        {
+            let raw_id = &key[key.len() - SUBSTATE_ID_LENGTH..];
+            let id = SubstateId::from_buffer(&mut Reader::new(raw_id))
+                .map_err(|e| StoreError::Io(e.to_string()))?;
+            let raw = self.read(&self.locations[&id])?;
+            if raw.starts_with(prefix) {
+                substates.push((id, raw));
+            }
+        }
+        substates.sort_unstable_by(|(_, a), (_, b)| a.cmp(b));
+        Ok(substates)
+    }
+
+    fn load_resource(&self, address: &Address) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.resources.get(address) {
+            Some(id) => self.load_substate(id),
+            None => Ok(None),
+        }
+    }
+
+    fn commit(&mut self, updates: &[StateUpdate]) -> Result<(), StoreError> {
+        check_updates(
+            updates,
+            |id| self.locations.contains_key(id),
+            |id| self.virtual_down_substates.contains(id),
+        )?;
+        let length = self.length;
+        let records = self.append(updates)?;
+        let mut down_locations = HashMap::new();
+        for record in records {
+            if let Some((id, location)) = self.apply(record) {
+                down_locations.insert(id, location);
+            }
+        }
+        self.last_commit = Some(LastCommit {
+            length,
+            updates: updates.to_vec(),
+            down_locations,
+        });
+        Ok(())
+    }
+}
+
+/// Returns the entry of a substate in the index, i.e. its index key followed by its ID.
+fn index_entry(id: &SubstateId, location: &Location) -> Vec<u8> {
+    let mut entry = location.index_key.clone();
+    id.write_to(&mut entry);
+    entry
+}
+
+fn index_key(substate: &[u8]) -> Vec<u8> {
+    substate[..substate.len().min(MAX_INDEX_PREFIX_LENGTH)].to_vec()
+}
+
+fn resource_of(substate: &[u8]) -> Option<Address> {
+    match SubstateData::from_buffer_exact(&mut Reader::new(substate)) {
+        Ok(SubstateData::TokenResource(resource)) => Some(resource.resource),
+        _ => None,
+    }
+}
+
+fn write_up(out: &mut Vec<u8>, id: &SubstateId, substate: &[u8]) {
+    out.push(RECORD_UP);
+    id.write_to(out);
+    out.extend_from_slice(&(substate.len() as u32).to_be_bytes());
+    out.extend_from_slice(substate);
+}
+
+fn write_virtual_down(out: &mut Vec<u8>, parent: &SubstateId, key: &[u8]) {
+    out.push(RECORD_VIRTUAL_DOWN);
+    parent.write_to(out);
+    out.extend_from_slice(&(key.len() as u16).to_be_bytes());
+    out.extend_from_slice(key);
+}
+
+/// Reads the next record of the log, or `None` for a commit marker.
+fn read_record<R: Read>(reader: &mut R, offset: &mut u64) -> io::Result<Option<Record>> {
+    let tag = read_bytes(reader, offset, 1)?[0];
+    let record = match tag {
+        RECORD_UP => {
+            let id = read_id(reader, offset)?;
+            let length = read_bytes(reader, offset, 4)?;
+            let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]);
+            let location_offset = *offset;
+            let substate = read_bytes(reader, offset, length as usize)?;
+            Record::Up {
+                id,
+                location: Location {
+                    offset: location_offset,
+                    length,
+                    index_key: index_key(&substate),
+                },
+                resource: resource_of(&substate),
+            }
+        }
+        RECORD_DOWN => Record::Down(read_id(reader, offset)?),
+        RECORD_VIRTUAL_DOWN => {
+            let parent = read_id(reader, offset)?;
+            let length = read_bytes(reader, offset, 2)?;
+            let length = u16::from_be_bytes([length[0], length[1]]);
+            let key = read_bytes(reader, offset, length as usize)?;
+            Record::VirtualDown { parent, key }
+        }
+        RECORD_COMMIT => return Ok(None),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid record type: {:#04X}", tag),
+            ))
+        }
+    };
+    Ok(Some(record))
+}
+
+fn read_bytes<R: Read>(reader: &mut R, offset: &mut u64, length: usize) -> io::Result<Vec<u8>> {
+    let mut buffer = vec![0; length];
+    reader.read_exact(&mut buffer)?;
+    *offset += length as u64;
+    Ok(buffer)
+}
+
+fn read_id<R: Read>(reader: &mut R, offset: &mut u64) -> io::Result<SubstateId> {
+    let raw = read_bytes(reader, offset, SUBSTATE_ID_LENGTH)?;
+    SubstateId::from_buffer(&mut Reader::new(&raw))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use crate::engine::ConstraintMachine;
+    use crate::engine::DownSubstateId;
+    use crate::engine::ExecutionContext;
+    use crate::engine::FeeTable;
+    use crate::engine::PermissionLevel;
+    use crate::engine::Procedures;
+    use crate::engine::StateUpdate;
+    use crate::error::ConstraintMachineErrorKind;
+    use crate::error::StoreError;
+    use crate::file_store::FileStore;
+    use crate::store::SubstateStore;
+    use crate::substates::SubstateData;
+    use crate::substates::TokenResource;
+    use crate::transaction::Instruction;
+    use crate::transaction::Transaction;
+    use crate::types::*;
+
+    fn path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("parser-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn substate_id(index: u32) -> SubstateId {
+        SubstateId {
+            hash: Hash { raw: [7; 32] },
+            index,
+        }
+    }
+
+    fn up(index: u32, substate: &[u8]) -> StateUpdate {
+        StateUpdate::Up {
+            id: substate_id(index),
+            substate: substate.to_vec(),
+        }
+    }
+
+    fn down(index: u32) -> StateUpdate {
+        StateUpdate::Down(DownSubstateId::Stored(substate_id(index)))
+    }
+
+    fn virtual_down(index: u32, key: &[u8]) -> StateUpdate {
+        StateUpdate::Down(DownSubstateId::Virtual {
+            parent: substate_id(index),
+            key: key.to_vec(),
+        })
+    }
+
+    fn token_resource() -> Vec<u8> {
+        SubstateData::TokenResource(TokenResource {
+            reserved: 0,
+            resource: Address::RadixNativeToken,
+            granularity: U256 {
+                raw: primitive_types::U256::one(),
+            },
+            is_mutable: Boolean { raw: 1 },
+            minter: None,
+        })
+        .to_bytes()
+    }
+
+    #[test]
+    fn survives_restart() {
+        let path = path("survives-restart");
+        let mut store = FileStore::open(&path).unwrap();
+        store
+            .commit(&[up(0, &token_resource()), up(1, &[6, 1, 2]), up(2, &[6, 1])])
+            .unwrap();
+        store
+            .commit(&[down(1), up(3, &[0x0d, 0, 1]), virtual_down(3, &[1])])
+            .unwrap();
+        drop(store);
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(
+            store.load_resource(&Address::RadixNativeToken),
+            Ok(Some(token_resource()))
+        );
+        assert_eq!(store.load_substate(&substate_id(1)), Ok(None));
+        assert_eq!(store.index(&[6, 1]), Ok(vec![(substate_id(2), vec![6, 1])]));
+        assert_eq!(
+            store.verify_virtual_substate(&substate_id(3), &[1]),
+            Err(ConstraintMachineErrorKind::VirtualSubstateAlreadyDown {
+                parent: substate_id(3),
+                key: vec![1]
+            })
+        );
+        assert_eq!(
+            store.verify_virtual_substate(&substate_id(3), &[2]),
+            Ok(vec![0x0d, 0, 1])
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn discards_incomplete_commit() {
+        let path = path("incomplete-commit");
+        let mut store = FileStore::open(&path).unwrap();
+        store.commit(&[up(0, &[6, 1])]).unwrap();
+        drop(store);
+        let length = fs::metadata(&path).unwrap().len();
+
+        // A complete down record followed by a torn up record, without commit marker
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        let mut torn = vec![0x01];
+        substate_id(0).write_to(&mut torn);
+        torn.extend_from_slice(&[0x00, 7, 7]);
+        file.write_all(&torn).unwrap();
+        drop(file);
+
+        let mut store = FileStore::open(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), length);
+        assert_eq!(store.load_substate(&substate_id(0)), Ok(Some(vec![6, 1])));
+        store.commit(&[down(0), up(1, &[6, 2])]).unwrap();
+        drop(store);
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.index(&[6]), Ok(vec![(substate_id(1), vec![6, 2])]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rolls_back_last_commit() {
+        let path = path("rollback");
+        let mut store = FileStore::open(&path).unwrap();
+        store
+            .commit(&[up(0, &[6, 1]), up(1, &[0x0d, 0, 1])])
+            .unwrap();
+        let length = fs::metadata(&path).unwrap().len();
+        store
+            .commit(&[up(2, &[6, 2]), down(0), down(2), virtual_down(1, &[1])])
+            .unwrap();
+        assert_eq!(
+            store.commit(&[down(0)]),
+            Err(StoreError::SubstateNotFound(substate_id(0)))
+        );
+
+        assert!(store.rollback().unwrap());
+        assert!(!store.rollback().unwrap());
+        assert_eq!(fs::metadata(&path).unwrap().len(), length);
+        assert_eq!(store.index(&[6]), Ok(vec![(substate_id(0), vec![6, 1])]));
+        assert!(store.verify_virtual_substate(&substate_id(1), &[1]).is_ok());
+        drop(store);
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.load_substate(&substate_id(0)), Ok(Some(vec![6, 1])));
+        assert_eq!(store.load_substate(&substate_id(2)), Ok(None));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reports_read_failures() {
+        let path = path("read-failures");
+        let mut store = FileStore::open(&path).unwrap();
+        store.commit(&[up(0, &[6, 1])]).unwrap();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+
+        assert!(matches!(
+            store.load_substate(&substate_id(0)),
+            Err(StoreError::Io(_))
+        ));
+        assert!(matches!(store.index(&[6]), Err(StoreError::Io(_))));
+
+        let tx = Transaction::new(vec![Instruction::READ(substate_id(0)), Instruction::END]);
+        let machine = ConstraintMachine::new(Procedures::olympia(), FeeTable::default());
+        let error = machine
+            .verify(
+                &store,
+                ExecutionContext::new(&tx, PermissionLevel::User, None),
+                &tx,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error.kind,
+            ConstraintMachineErrorKind::Store(StoreError::Io(_))
+        ));
+        assert!(!error.kind.is_rejection());
+        assert_eq!(error.instruction_index, 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compacts_down_substates() {
+        let path = path("compaction");
+        let mut store = FileStore::open(&path).unwrap();
+        store
+            .commit(&[up(0, &[6, 1]), up(1, &[0x0d, 0, 1]), virtual_down(1, &[1])])
+            .unwrap();
+        let compacted = fs::metadata(&path).unwrap().len();
+        for index in 2..100 {
+            store.commit(&[up(index, &[6, 2, 2, 2])]).unwrap();
+            store.commit(&[down(index)]).unwrap();
+        }
+        drop(store);
+        assert!(fs::metadata(&path).unwrap().len() > 10 * compacted);
+
+        let mut store = FileStore::open(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), compacted);
+        assert_eq!(store.index(&[6]), Ok(vec![(substate_id(0), vec![6, 1])]));
+        assert!(store
+            .verify_virtual_substate(&substate_id(1), &[1])
+            .is_err());
+        assert!(store.verify_virtual_substate(&substate_id(1), &[2]).is_ok());
+
+        // A few substates which are down are kept until they dominate the log
+        store.commit(&[down(0)]).unwrap();
+        let length = fs::metadata(&path).unwrap().len();
+        drop(store);
+        let mut store = FileStore::open(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), length);
+
+        store.compact().unwrap();
+        assert!(!store.rollback().unwrap());
+        assert!(fs::metadata(&path).unwrap().len() < compacted);
+        assert_eq!(store.index(&[6]), Ok(vec![]));
+        assert_eq!(
+            store.load_substate(&substate_id(1)),
+            Ok(Some(vec![0x0d, 0, 1]))
+        );
+        store.commit(&[up(3, &[6, 3])]).unwrap();
+        drop(store);
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.index(&[6]), Ok(vec![(substate_id(3), vec![6, 3])]));
+        assert!(store
+            .verify_virtual_substate(&substate_id(1), &[1])
+            .is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,3 +1,18 @@
+pub mod builder;
+pub mod crypto;
+pub mod engine;
+pub mod error;
+pub mod file_store;
+pub mod network;
+pub mod procedures;
+pub mod reader;
+#[cfg(test)]
+mod samples;
+pub mod store;
 pub mod substates;
+pub mod syscall;
+pub mod token;
 pub mod transaction;
 pub mod types;
+pub mod validation;
+pub mod view;
@@ -0,0 +1,303 @@
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::Bech32;
+use bech32::Hrp;
+
+use crate::error::AddressError;
+use crate::error::ParseErrorKind;
+use crate::reader::Reader;
+use crate::types::is_compressed_point;
+use crate::types::Address;
+use crate::types::PublicKey;
+use crate::types::VirtualKey;
+use crate::types::VirtualSubstate;
+
+/// Network which determines the human-readable part of Bech32 identifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+
+    Testnet,
+
+    Devnet,
+}
+
+impl Network {
+    pub fn account_hrp(&self) -> &'static str {
+        match self {
+            Self::Mainnet => "rdx",
+            Self::Testnet => "tdx",
+            Self::Devnet => "ddx",
+        }
+    }
+
+    pub fn validator_hrp(&self) -> &'static str {
+        match self {
+            Self::Mainnet => "rv",
+            Self::Testnet => "tv",
+            Self::Devnet => "dv",
+        }
+    }
+
+    /// Suffix appended to the resource symbol to form the human-readable part
+    pub fn resource_hrp_suffix(&self) -> &'static str {
+        match self {
+            Self::Mainnet => "_rr",
+            Self::Testnet => "_tr",
+            Self::Devnet => "_dr",
+        }
+    }
+}
+
+impl Address {
+    /// Encodes an account address, e.g. `rdx1...`.
+    pub fn to_account_bech32(&self, network: Network) -> Result<String, AddressError> {
+        match self {
+            Self::PublicKey(_) => encode(network.account_hrp(), &self.encoded()),
+            _ => Err(AddressError::InvalidAddressType(self.type_byte())),
+        }
+    }
+
+    pub fn from_account_bech32(network: Network, s: &str) -> Result<Self, AddressError> {
+        let (hrp, data) = decode(s)?;
+        if hrp != network.account_hrp() {
+            return Err(AddressError::InvalidHrp(hrp));
+        }
+        match decode_address(&data)? {
+            address @ Self::PublicKey(_) => Ok(address),
+            address => Err(AddressError::InvalidAddressType(address.type_byte())),
+        }
+    }
+
+    /// Encodes a resource address with its symbol, e.g. `xrd_rr1...`.
+    pub fn to_resource_bech32(
+        &self,
+        network: Network,
+        symbol: &str,
+    ) -> Result<String, AddressError> {
+        if !is_valid_symbol(symbol) {
+            return Err(AddressError::InvalidSymbol(symbol.to_string()));
+        }
+        match self {
+            Self::RadixNativeToken | Self::HashedKeyNonce(_) => encode(
+                &format!("{}{}", symbol, network.resource_hrp_suffix()),
+                &self.encoded(),
+            ),
+            _ => Err(AddressError::InvalidAddressType(self.type_byte())),
+        }
+    }
+
+    /// Decodes a resource address, returning the symbol and the address.
+    pub fn from_resource_bech32(network: Network, s: &str) -> Result<(String, Self), AddressError> {
+        decode_resource(network.resource_hrp_suffix(), s)
+    }
+
+    fn encoded(self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(&mut out);
+        out
+    }
+
+    fn type_byte(self) -> u8 {
+        self.encoded()[0]
+    }
+}
+
+impl PublicKey {
+    /// Encodes a validator identifier, e.g. `rv1...`.
+    pub fn to_validator_bech32(&self, network: Network) -> String {
+        encode(network.validator_hrp(), &self.raw).expect("Validator HRPs are valid")
+    }
+
+    pub fn from_validator_bech32(network: Network, s: &str) -> Result<Self, AddressError> {
+        let (hrp, data) = decode(s)?;
+        if hrp != network.validator_hrp() {
+            return Err(AddressError::InvalidHrp(hrp));
+        }
+        let mut raw = [0u8; 33];
+        if data.len() != raw.len() {
+            return Err(AddressError::InvalidLength(data.len()));
+        }
+        if !is_compressed_point(&data) {
+            return Err(AddressError::InvalidPublicKey);
+        }
+        raw.copy_from_slice(&data);
+        Ok(Self { raw })
+    }
+}
+
+impl VirtualSubstate {
+    /// Formats the substate with its key in Bech32 where it has one, e.g.
+    /// `validator-metadata(rv1...)`.
+    pub fn to_bech32_string(&self, network: Network) -> String {
+        let key = match &self.key {
+            VirtualKey::Validator(key) => key.to_validator_bech32(network),
+            VirtualKey::Address(address) => address
+                .to_account_bech32(network)
+                .unwrap_or_else(|_| format!("{:?}", address)),
+        };
+        format!("{}({})", self.type_id.name(), key)
+    }
+}
+
+/// Symbols are lower-case alphanumeric, so that they form a valid human-readable part.
+fn is_valid_symbol(symbol: &str) -> bool {
+    !symbol.is_empty()
+        && symbol
+            .bytes()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+}
+
+fn encode(hrp: &str, data: &[u8]) -> Result<String, AddressError> {
+    let hrp = Hrp::parse(hrp).map_err(|_| AddressError::InvalidHrp(hrp.to_string()))?;
+    bech32::encode::<Bech32>(hrp, data).map_err(|_| AddressError::InvalidBech32)
+}
+
+/// Decodes a Bech32 (not Bech32m) string into its lower-case HRP and data bytes.
+fn decode(s: &str) -> Result<(String, Vec<u8>), AddressError> {
+    let checked = CheckedHrpstring::new::<Bech32>(s).map_err(|_| AddressError::InvalidBech32)?;
+    Ok((checked.hrp().to_lowercase(), checked.byte_iter().collect()))
+}
+
+fn decode_resource(suffix: &str, s: &str) -> Result<(String, Address), AddressError> {
+    let (hrp, data) = decode(s)?;
+    let symbol = match hrp.strip_suffix(suffix) {
+        Some(symbol) if is_valid_symbol(symbol) => symbol.to_string(),
+        _ => return Err(AddressError::InvalidHrp(hrp)),
+    };
+    match decode_address(&data)? {
+        address @ (Address::RadixNativeToken | Address::HashedKeyNonce(_)) => Ok((symbol, address)),
+        address => Err(AddressError::InvalidAddressType(address.type_byte())),
+    }
+}
+
+fn decode_address(data: &[u8]) -> Result<Address, AddressError> {
+    let mut reader = Reader::new(data);
+    let address = Address::from_buffer(&mut reader).map_err(|e| match e.kind {
+        ParseErrorKind::InvalidAddressType(t) => AddressError::InvalidAddressType(t),
+        _ => AddressError::InvalidLength(data.len()),
+    })?;
+    if !reader.is_empty() {
+        return Err(AddressError::InvalidLength(data.len()));
+    }
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::AddressError;
+    use crate::network::Network;
+    use crate::types::Address;
+    use crate::types::PublicKey;
+
+    fn address(s: &str) -> Address {
+        let bytes = hex::decode(s).unwrap();
+        super::decode_address(&bytes).unwrap()
+    }
+
+    #[test]
+    fn encodes_engine_vectors() {
+        // Vectors from ResourceAddressingTest, which uses the "_rb" suffix
+        assert_eq!(super::encode("xrd_rb", &[0x01]).unwrap(), "xrd_rb1qya85pwq");
+        assert_eq!(
+            super::encode(
+                "usdc_rb",
+                &hex::decode(format!("03{}", "00".repeat(26))).unwrap()
+            )
+            .unwrap(),
+            "usdc_rb1qvqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq6gwwwd"
+        );
+        assert_eq!(
+            Address::RadixNativeToken
+                .to_resource_bech32(Network::Mainnet, "xrd")
+                .unwrap(),
+            "xrd_rr1qy5wfsfh"
+        );
+    }
+
+    #[test]
+    fn round_trips_accounts_and_validators() {
+        let s = "ddx1qspll7tm6464am4yypzn59p42g6a8qhkguhc269p3vhs27s5vq5h24sfvvdfj";
+        let account = Address::from_account_bech32(Network::Devnet, s).unwrap();
+        assert_eq!(account.to_account_bech32(Network::Devnet).unwrap(), s);
+
+        let s = "dv1q0llj774w40wafpqg5apgd2jxhfc9aj897zk3gvt9uzh59rq9964vjryzf9";
+        let key = PublicKey::from_validator_bech32(Network::Devnet, s).unwrap();
+        assert_eq!(key.to_validator_bech32(Network::Devnet), s);
+        assert_eq!(account, Address::PublicKey(key.raw));
+
+        let mainnet = account.to_account_bech32(Network::Mainnet).unwrap();
+        assert!(mainnet.starts_with("rdx1"));
+        assert_eq!(
+            Address::from_account_bech32(Network::Mainnet, &mainnet).unwrap(),
+            account
+        );
+        assert_eq!(
+            Address::from_account_bech32(Network::Testnet, &mainnet),
+            Err(AddressError::InvalidHrp("rdx".to_string()))
+        );
+    }
+
+    #[test]
+    fn round_trips_resources() {
+        let s = "fix_dr1qd5ah4xye2svl2smkk06st3q9lkqnkc6wgy0zxrrkmyszlawrz";
+        let (symbol, resource) = Address::from_resource_bech32(Network::Devnet, s).unwrap();
+        assert_eq!(symbol, "fix");
+        assert!(matches!(resource, Address::HashedKeyNonce(_)));
+        assert_eq!(
+            resource
+                .to_resource_bech32(Network::Devnet, &symbol)
+                .unwrap(),
+            s
+        );
+        assert_eq!(
+            Address::from_resource_bech32(Network::Mainnet, s),
+            Err(AddressError::InvalidHrp("fix_dr".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_addresses() {
+        // Invalid RRIs from ResourceAddressingTest
+        for (s, error) in [
+            ("xrd1pzdsczc", AddressError::InvalidHrp("xrd".to_string())),
+            ("xrd_rb1avu205I", AddressError::InvalidBech32),
+            ("usdc_rb1qg8vs72e", AddressError::InvalidAddressType(0x02)),
+            ("usdc_rb1qqqsqs6ztc", AddressError::InvalidLength(2)),
+            ("usdc_rb1qvgxjc9r", AddressError::InvalidLength(1)),
+        ] {
+            assert_eq!(super::decode_resource("_rb", s), Err(error), "{}", s);
+        }
+
+        let account = address(&format!("04{}", "02".repeat(33)));
+        assert_eq!(
+            account.to_resource_bech32(Network::Mainnet, "xrd"),
+            Err(AddressError::InvalidAddressType(0x04))
+        );
+        assert_eq!(
+            Address::RadixNativeToken.to_account_bech32(Network::Mainnet),
+            Err(AddressError::InvalidAddressType(0x01))
+        );
+        assert_eq!(
+            Address::RadixNativeToken.to_resource_bech32(Network::Mainnet, "XRD"),
+            Err(AddressError::InvalidSymbol("XRD".to_string()))
+        );
+        assert_eq!(
+            Address::from_resource_bech32(Network::Mainnet, "xrd_rr1qy5wfsfj"),
+            Err(AddressError::InvalidBech32)
+        );
+        assert_eq!(
+            PublicKey::from_validator_bech32(
+                Network::Mainnet,
+                &super::encode("rv", &[3; 33]).unwrap()
+            ),
+            Err(AddressError::InvalidPublicKey)
+        );
+        assert_eq!(
+            PublicKey::from_validator_bech32(
+                Network::Mainnet,
+                &super::encode("rv", &[2; 32]).unwrap()
+            ),
+            Err(AddressError::InvalidLength(32))
+        );
+    }
+}
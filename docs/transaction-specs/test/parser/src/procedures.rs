@@ -0,0 +1,1962 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use primitive_types::U256;
+
+use crate::engine::EpochUpdate;
+use crate::engine::ExecutionContext;
+use crate::engine::Operation;
+use crate::engine::PermissionLevel;
+use crate::engine::Procedure;
+use crate::engine::ProcedureInput;
+use crate::engine::ProcedureKey;
+use crate::engine::Procedures;
+use crate::engine::ReducerState;
+use crate::engine::Resources;
+use crate::engine::StakeOwnershipHoldingBucket;
+use crate::engine::StateKind;
+use crate::engine::TokenHoldingBucket;
+use crate::engine::ValidatorScratchPad;
+use crate::error::ConstraintMachineErrorKind;
+use crate::substates::SubstateData;
+use crate::substates::SubstateTypeId;
+use crate::syscall::Syscall;
+use crate::types::Address;
+use crate::types::PublicKey;
+
+/// Symbols which only the system may claim
+pub const RESERVED_SYMBOLS: [&str; 9] = [
+    "xrd", "xrds", "exrd", "exrds", "rad", "rads", "rdx", "rdxs", "radix",
+];
+
+/// Child types of the virtual parents booted up at genesis, in order
+const GENESIS_VIRTUAL_TYPES: [SubstateTypeId; 7] = [
+    SubstateTypeId::ValidatorMetadata,
+    SubstateTypeId::ValidatorStakeData,
+    SubstateTypeId::ValidatorAllowDelegationFlag,
+    SubstateTypeId::ValidatorRegisteredFlagCopy,
+    SubstateTypeId::ValidatorRakeCopy,
+    SubstateTypeId::ValidatorOwnerCopy,
+    SubstateTypeId::ValidatorSystemMetadata,
+];
+
+/// Maximum increase of a validator rake at once, in hundredths of a percent
+pub const MAX_RAKE_INCREASE: u32 = 1000;
+
+/// Number of epochs after which a rake increase takes effect, decreases taking effect at the
+/// next epoch
+pub const RAKE_INCREASE_DEBOUNCE_EPOCH_LENGTH: u64 = 2;
+
+/// Rake of 100%, in hundredths of a percent
+pub const RAKE_MAX: u32 = 10_000;
+
+/// Number of rounds of an epoch, the last of which ends it
+pub const MAX_ROUNDS: u64 = 10_000;
+
+/// Number of epochs unstaked XRD stays locked for
+pub const UNSTAKING_DELAY_EPOCH_LENGTH: u64 = 500;
+
+/// Share of its proposals a validator must complete to be rewarded, in hundredths of a percent
+pub const MINIMUM_COMPLETED_PROPOSALS_PERCENTAGE: u64 = 9800;
+
+/// Maximum number of validators of an epoch
+pub const MAX_VALIDATORS: usize = 100;
+
+type Result<T> = std::result::Result<T, ConstraintMachineErrorKind>;
+
+impl Procedures {
+    /// Procedures of the Olympia rules, i.e. those of the system, token, staking, validator,
+    /// round, epoch and mutex constraint scrypts.
+    ///
+    /// Round and epoch updates require `SuperUser`.
+    pub fn olympia() -> Self {
+        let mut procedures = Self::new();
+        system(&mut procedures);
+        tokens(&mut procedures);
+        staking(&mut procedures);
+        validators(&mut procedures);
+        rounds(&mut procedures);
+        epochs(&mut procedures);
+        mutex(&mut procedures);
+        procedures
+    }
+}
+
+/// Minimum amount of a stake, i.e. 90 XRD
+pub fn minimum_stake() -> U256 {
+    U256::exp10(18) * 90
+}
+
+fn key(
+    current_state: StateKind,
+    operation: Operation,
+    substate_type: Option<SubstateTypeId>,
+) -> ProcedureKey {
+    ProcedureKey {
+        current_state,
+        operation,
+        substate_type,
+    }
+}
+
+fn user_level(_: Option<&ReducerState>, _: ProcedureInput) -> PermissionLevel {
+    PermissionLevel::User
+}
+
+fn super_user_level(_: Option<&ReducerState>, _: ProcedureInput) -> PermissionLevel {
+    PermissionLevel::SuperUser
+}
+
+fn system_level(_: Option<&ReducerState>, _: ProcedureInput) -> PermissionLevel {
+    PermissionLevel::System
+}
+
+fn authorize_all(
+    _: Option<&ReducerState>,
+    _: ProcedureInput,
+    _: &ExecutionContext,
+    _: &dyn Resources,
+) -> Result<()> {
+    Ok(())
+}
+
+/// Extracts the operand of a procedure, which its key guarantees to be of the expected type.
+macro_rules! operand {
+    ($input: expr, $accessor: ident) => {
+        $input
+            .substate()
+            .and_then(SubstateData::$accessor)
+            .expect("Procedure keys match the substate type")
+    };
+}
+
+fn system(procedures: &mut Procedures) {
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Up,
+            Some(SubstateTypeId::VirtualParent),
+        ),
+        Procedure {
+            required_level: system_level,
+            authorizer: authorize_all,
+            reducer: |_, input, _, _| {
+                let parent = operand!(input, as_virtual_parent);
+                if parent.child_type() != Some(SubstateTypeId::UnclaimedREAddr) {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Invalid virtual parent data",
+                    ));
+                }
+                Ok(None)
+            },
+        },
+    );
+    procedures.insert(
+        key(StateKind::TokenHoldingBucket, Operation::Syscall, None),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, context, _| {
+                let mut bucket = bucket(state);
+                match input {
+                    ProcedureInput::Syscall(Syscall::FeeReservePut(amount)) => {
+                        bucket.withdraw(Address::RadixNativeToken, amount.raw)?;
+                        context.deposit_fee_reserve(amount.raw)?;
+                        Ok(Some(ReducerState::TokenHoldingBucket(bucket)))
+                    }
+                    _ => Err(ConstraintMachineErrorKind::Procedure("Invalid call type")),
+                }
+            },
+        },
+    );
+    procedures.insert(
+        key(StateKind::Void, Operation::Syscall, None),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |_, input, context, _| match input {
+                ProcedureInput::Syscall(Syscall::FeeReserveTake(amount)) => {
+                    let bucket = context.withdraw_fee_reserve(amount.raw)?;
+                    Ok(Some(ReducerState::TokenHoldingBucket(bucket)))
+                }
+                ProcedureInput::Syscall(Syscall::ReaddrClaim(symbol)) => {
+                    Ok(Some(ReducerState::REAddrClaimStart {
+                        symbol: symbol.data.clone(),
+                    }))
+                }
+                _ => Err(ConstraintMachineErrorKind::Procedure("Invalid call type")),
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::REAddrClaimStart,
+            Operation::Down,
+            Some(SubstateTypeId::UnclaimedREAddr),
+        ),
+        Procedure {
+            required_level: |_, input| {
+                // Addresses which are not derived from a key belong to the system
+                match operand!(input, as_unclaimed_readdr).address {
+                    Address::HashedKeyNonce(_) => PermissionLevel::User,
+                    _ => PermissionLevel::System,
+                }
+            },
+            authorizer: authorize_all,
+            reducer: |state, input, context, _| {
+                let symbol = match state {
+                    Some(ReducerState::REAddrClaimStart { symbol }) => symbol,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let address = operand!(input, as_unclaimed_readdr).address;
+                if context.permission_level() != PermissionLevel::System {
+                    let signer = context
+                        .signer()
+                        .ok_or(ConstraintMachineErrorKind::Procedure("Missing key"))?;
+                    let expected =
+                        Address::resource_from(signer, &String::from_utf8_lossy(&symbol));
+                    if address != expected {
+                        return Err(ConstraintMachineErrorKind::InvalidHashedKey);
+                    }
+                }
+                Ok(Some(ReducerState::REAddrClaim { symbol, address }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::REAddrClaim,
+            Operation::Up,
+            Some(SubstateTypeId::EpochData),
+        ),
+        Procedure {
+            required_level: system_level,
+            authorizer: authorize_all,
+            reducer: |_, input, _, _| {
+                if operand!(input, as_epoch_data).epoch != 0 {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "First epoch must be 0",
+                    ));
+                }
+                Ok(Some(ReducerState::AllocatingSystem))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::AllocatingSystem,
+            Operation::Up,
+            Some(SubstateTypeId::RoundData),
+        ),
+        Procedure {
+            required_level: system_level,
+            authorizer: authorize_all,
+            reducer: |_, input, _, _| {
+                if operand!(input, as_round_data).view != 0 {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "First view must be 0",
+                    ));
+                }
+                Ok(Some(ReducerState::AllocatingVirtualState(
+                    GENESIS_VIRTUAL_TYPES.to_vec(),
+                )))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::AllocatingVirtualState,
+            Operation::Up,
+            Some(SubstateTypeId::VirtualParent),
+        ),
+        Procedure {
+            required_level: system_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut remaining = match state {
+                    Some(ReducerState::AllocatingVirtualState(remaining)) => remaining,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let expected = remaining.remove(0);
+                if operand!(input, as_virtual_parent).child_type() != Some(expected) {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Unexpected virtual parent",
+                    ));
+                }
+                if remaining.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(ReducerState::AllocatingVirtualState(remaining)))
+            },
+        },
+    );
+}
+
+fn tokens(procedures: &mut Procedures) {
+    // Token creation
+    procedures.insert(
+        key(
+            StateKind::REAddrClaim,
+            Operation::Up,
+            Some(SubstateTypeId::TokenResource),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, context, _| {
+                let (symbol, address) = match state {
+                    Some(ReducerState::REAddrClaim { symbol, address }) => (symbol, address),
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let resource = operand!(input, as_token_resource);
+                if resource.resource != address {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Addresses don't match",
+                    ));
+                }
+                let name = String::from_utf8_lossy(&symbol).into_owned();
+                if context.permission_level() != PermissionLevel::System
+                    && RESERVED_SYMBOLS.contains(&name.as_str())
+                {
+                    return Err(ConstraintMachineErrorKind::ReservedSymbol(name));
+                }
+                if !symbol
+                    .iter()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+                {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Invalid token symbol",
+                    ));
+                }
+                if resource.granularity.raw != U256::one() {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Granularity must be one",
+                    ));
+                }
+                if resource.is_mutable.raw != 0 {
+                    return Ok(Some(ReducerState::NeedMetadata {
+                        symbol,
+                        resource: address,
+                    }));
+                }
+                Ok(Some(ReducerState::NeedFixedTokenSupply {
+                    symbol,
+                    resource: address,
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::NeedFixedTokenSupply,
+            Operation::Up,
+            Some(SubstateTypeId::Tokens),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (symbol, resource) = match state {
+                    Some(ReducerState::NeedFixedTokenSupply { symbol, resource }) => {
+                        (symbol, resource)
+                    }
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                if operand!(input, as_tokens).resource != resource {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Addresses don't match",
+                    ));
+                }
+                Ok(Some(ReducerState::NeedMetadata { symbol, resource }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::NeedMetadata,
+            Operation::Up,
+            Some(SubstateTypeId::TokenResourceMetadata),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (symbol, resource) = match state {
+                    Some(ReducerState::NeedMetadata { symbol, resource }) => (symbol, resource),
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let metadata = operand!(input, as_token_resource_metadata);
+                if metadata.resource != resource {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Addresses don't match",
+                    ));
+                }
+                if metadata.symbol.data.as_bytes() != &symbol[..] {
+                    return Err(ConstraintMachineErrorKind::Procedure("Symbols don't match"));
+                }
+                Ok(None)
+            },
+        },
+    );
+
+    // Mint
+    procedures.insert(
+        key(StateKind::Void, Operation::Up, Some(SubstateTypeId::Tokens)),
+        Procedure {
+            required_level: |_, input| {
+                if operand!(input, as_tokens).resource == Address::RadixNativeToken {
+                    PermissionLevel::System
+                } else {
+                    PermissionLevel::User
+                }
+            },
+            authorizer: |_, input, context, resources| {
+                let resource = resources.load_resource(&operand!(input, as_tokens).resource)?;
+                verify_minter(resource.minter, context)
+            },
+            reducer: |_, _, context, _| {
+                context.verify_can_alloc_and_destroy_resources()?;
+                Ok(None)
+            },
+        },
+    );
+
+    // Burn
+    procedures.insert(
+        key(StateKind::TokenHoldingBucket, Operation::End, None),
+        Procedure {
+            required_level: user_level,
+            authorizer: |state, _, context, resources| {
+                let bucket = match state {
+                    Some(ReducerState::TokenHoldingBucket(bucket)) => bucket,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                if bucket.is_empty() {
+                    return Ok(());
+                }
+                let resource = resources.load_resource(&bucket.resource)?;
+                verify_minter(resource.minter, context)
+            },
+            reducer: |state, _, context, resources| {
+                let bucket = bucket(state);
+                if !bucket.is_empty() {
+                    context.verify_can_alloc_and_destroy_resources()?;
+                    if !resources.load_resource(&bucket.resource)?.is_mutable {
+                        return Err(ConstraintMachineErrorKind::Procedure(
+                            "Can only burn mutable tokens",
+                        ));
+                    }
+                }
+                Ok(None)
+            },
+        },
+    );
+
+    // Withdrawals
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Down,
+            Some(SubstateTypeId::Tokens),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_withdrawal,
+            reducer: |_, input, _, _| {
+                let tokens = operand!(input, as_tokens);
+                Ok(Some(ReducerState::TokenHoldingBucket(
+                    TokenHoldingBucket::new(tokens.resource, tokens.amount.raw),
+                )))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::TokenHoldingBucket,
+            Operation::Down,
+            Some(SubstateTypeId::Tokens),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_withdrawal,
+            reducer: |state, input, _, _| {
+                let mut bucket = bucket(state);
+                let tokens = operand!(input, as_tokens);
+                bucket.deposit(tokens.resource, tokens.amount.raw)?;
+                Ok(Some(ReducerState::TokenHoldingBucket(bucket)))
+            },
+        },
+    );
+
+    // Deposit
+    procedures.insert(
+        key(
+            StateKind::TokenHoldingBucket,
+            Operation::Up,
+            Some(SubstateTypeId::Tokens),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut bucket = bucket(state);
+                let tokens = operand!(input, as_tokens);
+                bucket.withdraw(tokens.resource, tokens.amount.raw)?;
+                Ok(Some(ReducerState::TokenHoldingBucket(bucket)))
+            },
+        },
+    );
+}
+
+fn staking(procedures: &mut Procedures) {
+    // Stake
+    procedures.insert(
+        key(
+            StateKind::TokenHoldingBucket,
+            Operation::Read,
+            Some(SubstateTypeId::ValidatorAllowDelegationFlag),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let bucket = bucket(state);
+                let flag = operand!(input, as_validator_allow_delegation_flag);
+                if flag.is_delegation_allowed.raw != 0 {
+                    return Ok(Some(ReducerState::StakePrepare {
+                        bucket,
+                        validator: flag.validator,
+                        owner: None,
+                    }));
+                }
+                Ok(Some(ReducerState::OwnerStakePrepare {
+                    bucket,
+                    validator: flag.validator,
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::OwnerStakePrepare,
+            Operation::Read,
+            Some(SubstateTypeId::ValidatorOwnerCopy),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (bucket, validator) = match state {
+                    Some(ReducerState::OwnerStakePrepare { bucket, validator }) => {
+                        (bucket, validator)
+                    }
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let owner = operand!(input, as_validator_owner_copy);
+                if owner.validator != validator {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Not matching validator keys",
+                    ));
+                }
+                Ok(Some(ReducerState::StakePrepare {
+                    bucket,
+                    validator,
+                    owner: Some(owner.owner),
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::StakePrepare,
+            Operation::Up,
+            Some(SubstateTypeId::PreparedStake),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (mut bucket, validator, owner) = match state {
+                    Some(ReducerState::StakePrepare {
+                        bucket,
+                        validator,
+                        owner,
+                    }) => (bucket, validator, owner),
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let stake = operand!(input, as_prepared_stake);
+                if stake.amount.raw < minimum_stake() {
+                    return Err(ConstraintMachineErrorKind::MinimumStake {
+                        minimum: minimum_stake(),
+                        actual: stake.amount.raw,
+                    });
+                }
+                bucket.withdraw(Address::RadixNativeToken, stake.amount.raw)?;
+                if stake.validator != validator {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Not matching validator keys",
+                    ));
+                }
+                if owner.is_some_and(|owner| owner != stake.owner) {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Delegation not allowed",
+                    ));
+                }
+                Ok(Some(ReducerState::TokenHoldingBucket(bucket)))
+            },
+        },
+    );
+
+    // Unstake
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Down,
+            Some(SubstateTypeId::StakeOwnership),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_stake_withdrawal,
+            reducer: |_, input, _, _| {
+                let ownership = operand!(input, as_stake_ownership);
+                Ok(Some(ReducerState::StakeOwnershipHoldingBucket(
+                    StakeOwnershipHoldingBucket::new(ownership),
+                )))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::StakeOwnershipHoldingBucket,
+            Operation::Down,
+            Some(SubstateTypeId::StakeOwnership),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_stake_withdrawal,
+            reducer: |state, input, _, _| {
+                let mut bucket = stake_bucket(state);
+                bucket.deposit(operand!(input, as_stake_ownership))?;
+                Ok(Some(ReducerState::StakeOwnershipHoldingBucket(bucket)))
+            },
+        },
+    );
+    // Change
+    procedures.insert(
+        key(
+            StateKind::StakeOwnershipHoldingBucket,
+            Operation::Up,
+            Some(SubstateTypeId::StakeOwnership),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut bucket = stake_bucket(state);
+                let ownership = operand!(input, as_stake_ownership);
+                bucket.withdraw(&ownership.validator, &ownership.owner, ownership.amount.raw)?;
+                Ok(Some(ReducerState::StakeOwnershipHoldingBucket(bucket)))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::StakeOwnershipHoldingBucket,
+            Operation::Up,
+            Some(SubstateTypeId::PreparedUnstake),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut bucket = stake_bucket(state);
+                let unstake = operand!(input, as_prepared_unstake);
+                bucket.withdraw(&unstake.validator, &unstake.owner, unstake.amount.raw)?;
+                Ok(Some(ReducerState::StakeOwnershipHoldingBucket(bucket)))
+            },
+        },
+    );
+    procedures.insert(
+        key(StateKind::StakeOwnershipHoldingBucket, Operation::End, None),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, _, _, _| {
+                if !stake_bucket(state).is_empty() {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Stake ownership not empty",
+                    ));
+                }
+                Ok(None)
+            },
+        },
+    );
+}
+
+fn validators(procedures: &mut Procedures) {
+    // Metadata
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Down,
+            Some(SubstateTypeId::ValidatorMetadata),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_validator,
+            reducer: |_, input, _, _| {
+                let metadata = operand!(input, as_validator_metadata);
+                Ok(Some(ReducerState::UpdatingValidatorInfo(
+                    metadata.validator,
+                )))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingValidatorInfo,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorMetadata),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let validator = match state {
+                    Some(ReducerState::UpdatingValidatorInfo(validator)) => validator,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                verify_same_validator(
+                    &validator,
+                    &operand!(input, as_validator_metadata).validator,
+                )?;
+                Ok(None)
+            },
+        },
+    );
+
+    // Allow delegation flag
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Down,
+            Some(SubstateTypeId::ValidatorAllowDelegationFlag),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_validator,
+            reducer: |_, input, _, _| {
+                let flag = operand!(input, as_validator_allow_delegation_flag);
+                Ok(Some(ReducerState::UpdatingDelegationFlag(flag.validator)))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingDelegationFlag,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorAllowDelegationFlag),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let validator = match state {
+                    Some(ReducerState::UpdatingDelegationFlag(validator)) => validator,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let flag = operand!(input, as_validator_allow_delegation_flag);
+                verify_same_validator(&validator, &flag.validator)?;
+                Ok(None)
+            },
+        },
+    );
+
+    // Registration
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Down,
+            Some(SubstateTypeId::ValidatorRegisteredFlagCopy),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_validator,
+            reducer: |_, input, _, _| {
+                let copy = operand!(input, as_validator_registered_flag_copy);
+                Ok(Some(ReducerState::UpdatingRegisteredNeedToReadEpoch(
+                    copy.validator,
+                )))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingRegisteredNeedToReadEpoch,
+            Operation::Read,
+            Some(SubstateTypeId::EpochData),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let validator = match state {
+                    Some(ReducerState::UpdatingRegisteredNeedToReadEpoch(validator)) => validator,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                Ok(Some(ReducerState::UpdatingRegistered {
+                    validator,
+                    epoch: operand!(input, as_epoch_data).epoch,
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingRegistered,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorRegisteredFlagCopy),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (validator, epoch) = match state {
+                    Some(ReducerState::UpdatingRegistered { validator, epoch }) => {
+                        (validator, epoch)
+                    }
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let copy = operand!(input, as_validator_registered_flag_copy);
+                verify_same_validator(&validator, &copy.validator)?;
+                verify_update_epoch(copy.update_epoch, epoch_after(epoch, 1)?)?;
+                Ok(None)
+            },
+        },
+    );
+
+    // Rake
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Down,
+            Some(SubstateTypeId::ValidatorRakeCopy),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_validator,
+            reducer: |_, input, _, _| {
+                let copy = operand!(input, as_validator_rake_copy);
+                Ok(Some(ReducerState::UpdatingRakeNeedToReadEpoch(
+                    copy.validator,
+                )))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingRakeNeedToReadEpoch,
+            Operation::Read,
+            Some(SubstateTypeId::EpochData),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let validator = match state {
+                    Some(ReducerState::UpdatingRakeNeedToReadEpoch(validator)) => validator,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                Ok(Some(ReducerState::UpdatingRakeNeedToReadCurrentRake {
+                    validator,
+                    epoch: operand!(input, as_epoch_data).epoch,
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingRakeNeedToReadCurrentRake,
+            Operation::Read,
+            Some(SubstateTypeId::ValidatorStakeData),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (validator, epoch) = match state {
+                    Some(ReducerState::UpdatingRakeNeedToReadCurrentRake { validator, epoch }) => {
+                        (validator, epoch)
+                    }
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let stake = operand!(input, as_validator_stake_data);
+                verify_same_validator(&validator, &stake.validator)?;
+                Ok(Some(ReducerState::UpdatingRakeReady {
+                    validator,
+                    epoch,
+                    current_rake: stake.rake_percentage,
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingRakeReady,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorRakeCopy),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (validator, epoch, current_rake) = match state {
+                    Some(ReducerState::UpdatingRakeReady {
+                        validator,
+                        epoch,
+                        current_rake,
+                    }) => (validator, epoch, current_rake),
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let copy = operand!(input, as_validator_rake_copy);
+                verify_same_validator(&validator, &copy.validator)?;
+                let increase = copy.rake.saturating_sub(current_rake);
+                if increase > MAX_RAKE_INCREASE {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Rake increase too large",
+                    ));
+                }
+                let expected_epoch = if increase > 0 {
+                    epoch_after(epoch, RAKE_INCREASE_DEBOUNCE_EPOCH_LENGTH)?
+                } else {
+                    epoch_after(epoch, 1)?
+                };
+                verify_update_epoch(copy.update_epoch, expected_epoch)?;
+                Ok(None)
+            },
+        },
+    );
+
+    // Owner
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Down,
+            Some(SubstateTypeId::ValidatorOwnerCopy),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_validator,
+            reducer: |_, input, _, _| {
+                let copy = operand!(input, as_validator_owner_copy);
+                Ok(Some(ReducerState::UpdatingOwnerNeedToReadEpoch(
+                    copy.validator,
+                )))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingOwnerNeedToReadEpoch,
+            Operation::Read,
+            Some(SubstateTypeId::EpochData),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let validator = match state {
+                    Some(ReducerState::UpdatingOwnerNeedToReadEpoch(validator)) => validator,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                Ok(Some(ReducerState::UpdatingValidatorOwner {
+                    validator,
+                    epoch: operand!(input, as_epoch_data).epoch,
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingValidatorOwner,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorOwnerCopy),
+        ),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (validator, epoch) = match state {
+                    Some(ReducerState::UpdatingValidatorOwner { validator, epoch }) => {
+                        (validator, epoch)
+                    }
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let copy = operand!(input, as_validator_owner_copy);
+                verify_same_validator(&validator, &copy.validator)?;
+                verify_update_epoch(copy.update_epoch, epoch_after(epoch, 1)?)?;
+                Ok(None)
+            },
+        },
+    );
+}
+
+fn rounds(procedures: &mut Procedures) {
+    procedures.insert(
+        key(
+            StateKind::Void,
+            Operation::Down,
+            Some(SubstateTypeId::RoundData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |_, input, _, _| {
+                Ok(Some(ReducerState::EndPrevRound {
+                    view: operand!(input, as_round_data).view,
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::EndPrevRound,
+            Operation::Down,
+            Some(SubstateTypeId::ValidatorBFTData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let view = match state {
+                    Some(ReducerState::EndPrevRound { view }) => view,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let mut validators = BTreeSet::new();
+                validators.insert(operand!(input, as_validator_bft_data).validator);
+                Ok(Some(ReducerState::StartValidatorBFTUpdate {
+                    view,
+                    validators,
+                }))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::StartValidatorBFTUpdate,
+            Operation::Down,
+            Some(SubstateTypeId::ValidatorBFTData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (view, mut validators) = match state {
+                    Some(ReducerState::StartValidatorBFTUpdate { view, validators }) => {
+                        (view, validators)
+                    }
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                if !validators.insert(operand!(input, as_validator_bft_data).validator) {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Validator already started to update",
+                    ));
+                }
+                Ok(Some(ReducerState::StartValidatorBFTUpdate {
+                    view,
+                    validators,
+                }))
+            },
+        },
+    );
+    for current_state in [
+        StateKind::StartValidatorBFTUpdate,
+        StateKind::UpdatingValidatorBFTData,
+    ]
+    .iter()
+    {
+        procedures.insert(
+            key(
+                *current_state,
+                Operation::Up,
+                Some(SubstateTypeId::ValidatorBFTData),
+            ),
+            Procedure {
+                required_level: super_user_level,
+                authorizer: authorize_all,
+                reducer: |state, input, _, _| {
+                    let (view, mut validators) = match state {
+                        Some(ReducerState::StartValidatorBFTUpdate { view, validators })
+                        | Some(ReducerState::UpdatingValidatorBFTData { view, validators }) => {
+                            (view, validators)
+                        }
+                        _ => unreachable!("Procedure keys match the current state"),
+                    };
+                    // Validators are updated in key order
+                    let validator = operand!(input, as_validator_bft_data).validator;
+                    if validators.iter().next() != Some(&validator) {
+                        return Err(ConstraintMachineErrorKind::Procedure("Invalid key update"));
+                    }
+                    validators.remove(&validator);
+                    if validators.is_empty() {
+                        return Ok(Some(ReducerState::StartNextRound { view }));
+                    }
+                    Ok(Some(ReducerState::UpdatingValidatorBFTData {
+                        view,
+                        validators,
+                    }))
+                },
+            },
+        );
+    }
+    procedures.insert(
+        key(
+            StateKind::StartNextRound,
+            Operation::Up,
+            Some(SubstateTypeId::RoundData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let closed_view = match state {
+                    Some(ReducerState::StartNextRound { view }) => view,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let view = operand!(input, as_round_data).view;
+                if view <= closed_view {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Next view must be greater than previous",
+                    ));
+                }
+                if view > MAX_ROUNDS {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Next view exceeds max rounds",
+                    ));
+                }
+                Ok(None)
+            },
+        },
+    );
+}
+
+fn epochs(procedures: &mut Procedures) {
+    procedures.insert(
+        key(
+            StateKind::EndPrevRound,
+            Operation::Down,
+            Some(SubstateTypeId::EpochData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let view = match state {
+                    Some(ReducerState::EndPrevRound { view }) => view,
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                if view != MAX_ROUNDS {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Must execute epoch update on end of round",
+                    ));
+                }
+                let prev_epoch = operand!(input, as_epoch_data).epoch;
+                Ok(Some(ReducerState::UpdatingEpoch(Box::new(
+                    EpochUpdate::new(prev_epoch),
+                ))))
+            },
+        },
+    );
+
+    // Unlocking of unstaked XRD
+    procedures.insert(
+        key(
+            StateKind::UpdatingEpoch,
+            Operation::DownIndex,
+            Some(SubstateTypeId::ExitingStake),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let epoch_unlocked = epoch_after(update.prev_epoch, 1)?;
+                let mut prefix = vec![0];
+                prefix.extend_from_slice(&epoch_unlocked.to_be_bytes());
+                for substate in indexed(input, &prefix)? {
+                    let exit = substate
+                        .as_exiting_stake()
+                        .expect("Index prefixes match the substate type");
+                    update.exiting.push_back((exit.owner, exit.amount.raw));
+                }
+                if update.exiting.is_empty() {
+                    return Ok(Some(ReducerState::RewardingValidators(update)));
+                }
+                Ok(Some(ReducerState::ProcessExittingStake(update)))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::ProcessExittingStake,
+            Operation::Up,
+            Some(SubstateTypeId::Tokens),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let (owner, amount) = update
+                    .exiting
+                    .pop_front()
+                    .expect("Exiting stake is left to unlock");
+                let tokens = operand!(input, as_tokens);
+                if tokens.owner != owner
+                    || tokens.resource != Address::RadixNativeToken
+                    || tokens.amount.raw != amount
+                {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Invalid unlocked stake",
+                    ));
+                }
+                if update.exiting.is_empty() {
+                    return Ok(Some(ReducerState::RewardingValidators(update)));
+                }
+                Ok(Some(ReducerState::ProcessExittingStake(update)))
+            },
+        },
+    );
+
+    // Emissions
+    procedures.insert(
+        key(
+            StateKind::RewardingValidators,
+            Operation::DownIndex,
+            Some(SubstateTypeId::ValidatorBFTData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                for substate in indexed(input, &[])? {
+                    let data = substate
+                        .as_validator_bft_data()
+                        .expect("Index prefixes match the substate type");
+                    let proposals =
+                        data.proposals_completed as u128 + data.proposals_missed as u128;
+                    if proposals == 0 {
+                        continue;
+                    }
+                    // Validators below the threshold are not rewarded
+                    let completed = data.proposals_completed as u128 * 10_000 / proposals;
+                    if completed < MINIMUM_COMPLETED_PROPOSALS_PERCENTAGE as u128 {
+                        continue;
+                    }
+                    let reward = rewards_per_proposal() * data.proposals_completed;
+                    if !reward.is_zero() {
+                        update.rewards.insert(data.validator, reward);
+                    }
+                }
+                next_reward(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::LoadingStake,
+            Operation::Down,
+            Some(SubstateTypeId::ValidatorStakeData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let (mut update, validator, then) = match state {
+                    Some(ReducerState::LoadingStake {
+                        update,
+                        validator,
+                        then,
+                    }) => (update, validator, then),
+                    _ => unreachable!("Procedure keys match the current state"),
+                };
+                let stake = operand!(input, as_validator_stake_data);
+                if stake.validator != validator {
+                    return Err(ConstraintMachineErrorKind::Procedure("Invalid stake load"));
+                }
+                update
+                    .validators
+                    .insert(validator, ValidatorScratchPad::new(stake));
+                resume(update, validator, then)
+            },
+        },
+    );
+
+    // Unstakes
+    procedures.insert(
+        key(
+            StateKind::PreparingUnstake,
+            Operation::DownIndex,
+            Some(SubstateTypeId::PreparedUnstake),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                for substate in indexed(input, &[])? {
+                    let unstake = substate
+                        .as_prepared_unstake()
+                        .expect("Index prefixes match the substate type");
+                    let owners = update.unstakes.entry(unstake.validator).or_default();
+                    add_to(owners, unstake.owner, unstake.amount.raw)?;
+                }
+                next_unstake(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::Unstaking,
+            Operation::Up,
+            Some(SubstateTypeId::ExitingStake),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let (validator, owner, ownership) = first_of(&mut update.unstakes);
+                let amount = scratch_pad(&mut update, &validator).unstake(ownership)?;
+                let epoch_unlocked =
+                    epoch_after(update.prev_epoch, 1 + UNSTAKING_DELAY_EPOCH_LENGTH)?;
+                let exit = operand!(input, as_exiting_stake);
+                if exit.validator != validator
+                    || exit.owner != owner
+                    || exit.amount.raw != amount
+                    || exit.epoch_unlocked != epoch_unlocked
+                {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Invalid exiting stake",
+                    ));
+                }
+                if update.unstakes.contains_key(&validator) {
+                    return Ok(Some(ReducerState::Unstaking(update)));
+                }
+                next_unstake(update)
+            },
+        },
+    );
+
+    // Stakes
+    procedures.insert(
+        key(
+            StateKind::PreparingStake,
+            Operation::DownIndex,
+            Some(SubstateTypeId::PreparedStake),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                for substate in indexed(input, &[])? {
+                    let stake = substate
+                        .as_prepared_stake()
+                        .expect("Index prefixes match the substate type");
+                    let owners = update.stakes.entry(stake.validator).or_default();
+                    add_to(owners, stake.owner, stake.amount.raw)?;
+                }
+                next_stake(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::Staking,
+            Operation::Up,
+            Some(SubstateTypeId::StakeOwnership),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let (validator, owner, amount) = first_of(&mut update.stakes);
+                let expected = scratch_pad(&mut update, &validator).stake(amount)?;
+                let ownership = operand!(input, as_stake_ownership);
+                if ownership.validator != validator
+                    || ownership.owner != owner
+                    || ownership.amount.raw != expected
+                {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Invalid stake ownership",
+                    ));
+                }
+                if update.stakes.contains_key(&validator) {
+                    return Ok(Some(ReducerState::Staking(update)));
+                }
+                next_stake(update)
+            },
+        },
+    );
+
+    // Validator updates taking effect
+    procedures.insert(
+        key(
+            StateKind::PreparingRakeUpdate,
+            Operation::DownIndex,
+            Some(SubstateTypeId::ValidatorRakeCopy),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                for substate in indexed(input, &update_prefix(update.prev_epoch)?)? {
+                    let copy = substate
+                        .as_validator_rake_copy()
+                        .expect("Index prefixes match the substate type");
+                    update.rake_updates.insert(copy.validator, copy.rake);
+                }
+                next_rake_update(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::ResetRakeUpdate,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorRakeCopy),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let (validator, rake) = pop_first(&mut update.rake_updates);
+                let copy = operand!(input, as_validator_rake_copy);
+                verify_same_validator(&validator, &copy.validator)?;
+                if copy.rake != rake || copy.update_epoch.is_some() {
+                    return Err(ConstraintMachineErrorKind::Procedure("Invalid rake reset"));
+                }
+                next_rake_update(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::PreparingOwnerUpdate,
+            Operation::DownIndex,
+            Some(SubstateTypeId::ValidatorOwnerCopy),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                for substate in indexed(input, &update_prefix(update.prev_epoch)?)? {
+                    let copy = substate
+                        .as_validator_owner_copy()
+                        .expect("Index prefixes match the substate type");
+                    update.owner_updates.insert(copy.validator, copy.owner);
+                }
+                next_owner_update(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::ResetOwnerUpdate,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorOwnerCopy),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let (validator, owner) = pop_first(&mut update.owner_updates);
+                let copy = operand!(input, as_validator_owner_copy);
+                verify_same_validator(&validator, &copy.validator)?;
+                if copy.owner != owner || copy.update_epoch.is_some() {
+                    return Err(ConstraintMachineErrorKind::Procedure("Invalid owner reset"));
+                }
+                next_owner_update(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::PreparingRegisteredUpdate,
+            Operation::DownIndex,
+            Some(SubstateTypeId::ValidatorRegisteredFlagCopy),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                for substate in indexed(input, &update_prefix(update.prev_epoch)?)? {
+                    let copy = substate
+                        .as_validator_registered_flag_copy()
+                        .expect("Index prefixes match the substate type");
+                    update
+                        .registered_updates
+                        .insert(copy.validator, copy.is_registered.raw != 0);
+                }
+                next_registered_update(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::ResetRegisteredUpdate,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorRegisteredFlagCopy),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let (validator, is_registered) = pop_first(&mut update.registered_updates);
+                let copy = operand!(input, as_validator_registered_flag_copy);
+                verify_same_validator(&validator, &copy.validator)?;
+                if (copy.is_registered.raw != 0) != is_registered || copy.update_epoch.is_some() {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Invalid registration reset",
+                    ));
+                }
+                next_registered_update(update)
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::UpdatingValidatorStakes,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorStakeData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let (_, expected) = pop_first(&mut update.validators);
+                if !expected.matches(operand!(input, as_validator_stake_data)) {
+                    return Err(ConstraintMachineErrorKind::Procedure("Invalid stake data"));
+                }
+                if update.validators.is_empty() {
+                    return Ok(Some(ReducerState::CreatingNextValidatorSet(update)));
+                }
+                Ok(Some(ReducerState::UpdatingValidatorStakes(update)))
+            },
+        },
+    );
+
+    // Next epoch
+    procedures.insert(
+        key(
+            StateKind::CreatingNextValidatorSet,
+            Operation::ReadIndex,
+            Some(SubstateTypeId::ValidatorStakeData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                // Registered validators, by stake descending and then by key
+                let mut candidates: Vec<(U256, PublicKey)> = Vec::new();
+                for substate in indexed(input, &[0, 1])? {
+                    let stake = substate
+                        .as_validator_stake_data()
+                        .expect("Index prefixes match the substate type");
+                    if !stake.amount.raw.is_zero() {
+                        candidates.push((stake.amount.raw, stake.validator));
+                    }
+                }
+                candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+                update.next_validators = candidates
+                    .into_iter()
+                    .take(MAX_VALIDATORS)
+                    .map(|(_, validator)| validator)
+                    .collect();
+                if update.next_validators.is_empty() {
+                    return Ok(Some(ReducerState::StartingNextEpoch(update)));
+                }
+                Ok(Some(ReducerState::BootupValidator(update)))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::BootupValidator,
+            Operation::Up,
+            Some(SubstateTypeId::ValidatorBFTData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let mut update = epoch_update(state);
+                let validator = *update
+                    .next_validators
+                    .iter()
+                    .next()
+                    .expect("Validators are left to boot up");
+                update.next_validators.remove(&validator);
+                let data = operand!(input, as_validator_bft_data);
+                if data.validator != validator
+                    || data.proposals_completed != 0
+                    || data.proposals_missed != 0
+                {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Invalid validator BFT data",
+                    ));
+                }
+                if update.next_validators.is_empty() {
+                    return Ok(Some(ReducerState::StartingNextEpoch(update)));
+                }
+                Ok(Some(ReducerState::BootupValidator(update)))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::StartingNextEpoch,
+            Operation::Up,
+            Some(SubstateTypeId::EpochData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |state, input, _, _| {
+                let update = epoch_update(state);
+                if operand!(input, as_epoch_data).epoch != epoch_after(update.prev_epoch, 1)? {
+                    return Err(ConstraintMachineErrorKind::Procedure("Invalid next epoch"));
+                }
+                Ok(Some(ReducerState::StartingEpochRound(update)))
+            },
+        },
+    );
+    procedures.insert(
+        key(
+            StateKind::StartingEpochRound,
+            Operation::Up,
+            Some(SubstateTypeId::RoundData),
+        ),
+        Procedure {
+            required_level: super_user_level,
+            authorizer: authorize_all,
+            reducer: |_, input, _, _| {
+                if operand!(input, as_round_data).view != 0 {
+                    return Err(ConstraintMachineErrorKind::Procedure(
+                        "Epoch must start with view 0",
+                    ));
+                }
+                Ok(None)
+            },
+        },
+    );
+}
+
+fn mutex(procedures: &mut Procedures) {
+    // An address claimed without creating anything
+    procedures.insert(
+        key(StateKind::REAddrClaim, Operation::End, None),
+        Procedure {
+            required_level: user_level,
+            authorizer: authorize_all,
+            reducer: |_, _, _, _| Ok(None),
+        },
+    );
+}
+
+/// Emission of a validator per completed proposal, i.e. 10 XRD
+pub fn rewards_per_proposal() -> U256 {
+    U256::exp10(19)
+}
+
+fn epoch_update(state: Option<ReducerState>) -> Box<EpochUpdate> {
+    match state {
+        Some(ReducerState::UpdatingEpoch(update))
+        | Some(ReducerState::ProcessExittingStake(update))
+        | Some(ReducerState::RewardingValidators(update))
+        | Some(ReducerState::PreparingUnstake(update))
+        | Some(ReducerState::Unstaking(update))
+        | Some(ReducerState::PreparingStake(update))
+        | Some(ReducerState::Staking(update))
+        | Some(ReducerState::PreparingRakeUpdate(update))
+        | Some(ReducerState::ResetRakeUpdate(update))
+        | Some(ReducerState::PreparingOwnerUpdate(update))
+        | Some(ReducerState::ResetOwnerUpdate(update))
+        | Some(ReducerState::PreparingRegisteredUpdate(update))
+        | Some(ReducerState::ResetRegisteredUpdate(update))
+        | Some(ReducerState::UpdatingValidatorStakes(update))
+        | Some(ReducerState::CreatingNextValidatorSet(update))
+        | Some(ReducerState::BootupValidator(update))
+        | Some(ReducerState::StartingNextEpoch(update))
+        | Some(ReducerState::StartingEpochRound(update)) => update,
+        _ => unreachable!("Procedure keys match the current state"),
+    }
+}
+
+/// Extracts the substates of an index procedure, checking its prefix following the type byte
+/// selects exactly the substates expected.
+fn indexed<'a>(input: ProcedureInput<'a>, expected: &[u8]) -> Result<&'a [&'a SubstateData]> {
+    match input {
+        ProcedureInput::Substates(prefix, substates) => {
+            if prefix.prefix.data != expected {
+                return Err(ConstraintMachineErrorKind::Procedure(
+                    "Invalid index prefix",
+                ));
+            }
+            Ok(substates)
+        }
+        _ => unreachable!("Procedure keys match the operation"),
+    }
+}
+
+/// Returns the index prefix of the validator copies which take effect at the next epoch.
+fn update_prefix(prev_epoch: u64) -> Result<Vec<u8>> {
+    let mut prefix = vec![0, 1];
+    prefix.extend_from_slice(&epoch_after(prev_epoch, 1)?.to_be_bytes());
+    Ok(prefix)
+}
+
+/// Returns the epoch `length` epochs after `epoch`, which comes from the store so may overflow.
+fn epoch_after(epoch: u64, length: u64) -> Result<u64> {
+    epoch
+        .checked_add(length)
+        .ok_or(ConstraintMachineErrorKind::Procedure("Epoch overflow"))
+}
+
+fn add_to(amounts: &mut BTreeMap<Address, U256>, owner: Address, amount: U256) -> Result<()> {
+    let total = amounts.entry(owner).or_insert_with(U256::zero);
+    *total = total
+        .checked_add(amount)
+        .ok_or(ConstraintMachineErrorKind::Procedure("Stake overflow"))?;
+    Ok(())
+}
+
+fn pop_first<K: Ord + Copy, V>(map: &mut BTreeMap<K, V>) -> (K, V) {
+    let key = *map.keys().next().expect("Updates are left");
+    let value = map.remove(&key).expect("Updates are left");
+    (key, value)
+}
+
+/// Removes the amount of the first owner of the first validator, in order.
+fn first_of(
+    amounts: &mut BTreeMap<PublicKey, BTreeMap<Address, U256>>,
+) -> (PublicKey, Address, U256) {
+    let validator = *amounts.keys().next().expect("Updates are left");
+    let owners = amounts.get_mut(&validator).expect("Updates are left");
+    let (owner, amount) = pop_first(owners);
+    if owners.is_empty() {
+        amounts.remove(&validator);
+    }
+    (validator, owner, amount)
+}
+
+fn scratch_pad<'a>(
+    update: &'a mut EpochUpdate,
+    validator: &PublicKey,
+) -> &'a mut ValidatorScratchPad {
+    update
+        .validators
+        .get_mut(validator)
+        .expect("Stake is loaded before being updated")
+}
+
+/// Spins down the stake data of a validator unless it is loaded already, then resumes the
+/// epoch update at `then`.
+fn load_stake(
+    update: Box<EpochUpdate>,
+    validator: PublicKey,
+    then: StateKind,
+) -> Result<Option<ReducerState>> {
+    if update.validators.contains_key(&validator) {
+        return resume(update, validator, then);
+    }
+    Ok(Some(ReducerState::LoadingStake {
+        update,
+        validator,
+        then,
+    }))
+}
+
+fn resume(
+    mut update: Box<EpochUpdate>,
+    validator: PublicKey,
+    then: StateKind,
+) -> Result<Option<ReducerState>> {
+    let update_ref = &mut *update;
+    let pad = update_ref
+        .validators
+        .get_mut(&validator)
+        .expect("Stake is loaded before being updated");
+    match then {
+        StateKind::RewardingValidators => {
+            // The owner's rake is staked to the validator, the rest adds to all stakes
+            let reward = update_ref
+                .rewards
+                .remove(&validator)
+                .expect("Rewards are loaded in order");
+            let raked = reward * pad.rake_percentage / RAKE_MAX;
+            pad.add_emission(reward - raked)?;
+            if !raked.is_zero() {
+                let owners = update_ref.stakes.entry(validator).or_default();
+                add_to(owners, pad.owner, raked)?;
+            }
+            next_reward(update)
+        }
+        StateKind::Unstaking => Ok(Some(ReducerState::Unstaking(update))),
+        StateKind::Staking => Ok(Some(ReducerState::Staking(update))),
+        StateKind::ResetRakeUpdate => {
+            pad.rake_percentage = update_ref.rake_updates[&validator];
+            Ok(Some(ReducerState::ResetRakeUpdate(update)))
+        }
+        StateKind::ResetOwnerUpdate => {
+            pad.owner = update_ref.owner_updates[&validator];
+            Ok(Some(ReducerState::ResetOwnerUpdate(update)))
+        }
+        StateKind::ResetRegisteredUpdate => {
+            pad.is_registered = update_ref.registered_updates[&validator];
+            Ok(Some(ReducerState::ResetRegisteredUpdate(update)))
+        }
+        _ => unreachable!("Epoch updates load stake only before these states"),
+    }
+}
+
+fn next_reward(update: Box<EpochUpdate>) -> Result<Option<ReducerState>> {
+    match update.rewards.keys().next().copied() {
+        Some(validator) => load_stake(update, validator, StateKind::RewardingValidators),
+        None => Ok(Some(ReducerState::PreparingUnstake(update))),
+    }
+}
+
+fn next_unstake(update: Box<EpochUpdate>) -> Result<Option<ReducerState>> {
+    match update.unstakes.keys().next().copied() {
+        Some(validator) => load_stake(update, validator, StateKind::Unstaking),
+        None => Ok(Some(ReducerState::PreparingStake(update))),
+    }
+}
+
+fn next_stake(update: Box<EpochUpdate>) -> Result<Option<ReducerState>> {
+    match update.stakes.keys().next().copied() {
+        Some(validator) => load_stake(update, validator, StateKind::Staking),
+        None => Ok(Some(ReducerState::PreparingRakeUpdate(update))),
+    }
+}
+
+fn next_rake_update(update: Box<EpochUpdate>) -> Result<Option<ReducerState>> {
+    match update.rake_updates.keys().next().copied() {
+        Some(validator) => load_stake(update, validator, StateKind::ResetRakeUpdate),
+        None => Ok(Some(ReducerState::PreparingOwnerUpdate(update))),
+    }
+}
+
+fn next_owner_update(update: Box<EpochUpdate>) -> Result<Option<ReducerState>> {
+    match update.owner_updates.keys().next().copied() {
+        Some(validator) => load_stake(update, validator, StateKind::ResetOwnerUpdate),
+        None => Ok(Some(ReducerState::PreparingRegisteredUpdate(update))),
+    }
+}
+
+fn next_registered_update(update: Box<EpochUpdate>) -> Result<Option<ReducerState>> {
+    match update.registered_updates.keys().next().copied() {
+        Some(validator) => load_stake(update, validator, StateKind::ResetRegisteredUpdate),
+        None if update.validators.is_empty() => {
+            Ok(Some(ReducerState::CreatingNextValidatorSet(update)))
+        }
+        None => Ok(Some(ReducerState::UpdatingValidatorStakes(update))),
+    }
+}
+
+fn bucket(state: Option<ReducerState>) -> TokenHoldingBucket {
+    match state {
+        Some(ReducerState::TokenHoldingBucket(bucket)) => bucket,
+        _ => unreachable!("Procedure keys match the current state"),
+    }
+}
+
+fn stake_bucket(state: Option<ReducerState>) -> StakeOwnershipHoldingBucket {
+    match state {
+        Some(ReducerState::StakeOwnershipHoldingBucket(bucket)) => bucket,
+        _ => unreachable!("Procedure keys match the current state"),
+    }
+}
+
+/// Only the owner of an account may withdraw from it.
+fn authorize_withdrawal(
+    _: Option<&ReducerState>,
+    input: ProcedureInput,
+    context: &ExecutionContext,
+    _: &dyn Resources,
+) -> Result<()> {
+    let owner = operand!(input, as_tokens).owner;
+    match context.signer() {
+        Some(signer) if owner == Address::PublicKey(signer.raw) => Ok(()),
+        signer => Err(ConstraintMachineErrorKind::Unauthorized(signer.copied())),
+    }
+}
+
+/// Only the owner of a stake may unstake it.
+fn authorize_stake_withdrawal(
+    _: Option<&ReducerState>,
+    input: ProcedureInput,
+    context: &ExecutionContext,
+    _: &dyn Resources,
+) -> Result<()> {
+    let owner = operand!(input, as_stake_ownership).owner;
+    match context.signer() {
+        Some(signer) if owner == Address::PublicKey(signer.raw) => Ok(()),
+        signer => Err(ConstraintMachineErrorKind::Unauthorized(signer.copied())),
+    }
+}
+
+/// Only a validator may update its own substates.
+fn authorize_validator(
+    _: Option<&ReducerState>,
+    input: ProcedureInput,
+    context: &ExecutionContext,
+    _: &dyn Resources,
+) -> Result<()> {
+    let validator = input
+        .substate()
+        .and_then(SubstateData::validator_key)
+        .expect("Procedure keys match the substate type");
+    match context.signer() {
+        Some(signer) if signer == validator => Ok(()),
+        signer => Err(ConstraintMachineErrorKind::Unauthorized(signer.copied())),
+    }
+}
+
+fn verify_same_validator(expected: &PublicKey, actual: &PublicKey) -> Result<()> {
+    if actual != expected {
+        return Err(ConstraintMachineErrorKind::Procedure(
+            "Cannot change validator key as part of update",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks an update of a validator takes effect at the expected epoch.
+fn verify_update_epoch(update_epoch: Option<u64>, expected: u64) -> Result<()> {
+    if update_epoch != Some(expected) {
+        return Err(ConstraintMachineErrorKind::Procedure(
+            "Unexpected update epoch",
+        ));
+    }
+    Ok(())
+}
+
+fn verify_minter(minter: Option<PublicKey>, context: &ExecutionContext) -> Result<()> {
+    match (minter, context.signer()) {
+        (Some(minter), Some(signer)) if minter == *signer => Ok(()),
+        (_, signer) => Err(ConstraintMachineErrorKind::Unauthorized(signer.copied())),
+    }
+}
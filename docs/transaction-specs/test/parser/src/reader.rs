@@ -0,0 +1,168 @@
+use std::convert::TryInto;
+
+use crate::error::ParseError;
+use crate::error::ParseErrorKind;
+
+/// Options controlling how strictly input is parsed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Keep substates of unrecognised types as raw bytes instead of failing, relying on the
+    /// size prefix of `UP` to skip over them
+    pub keep_unknown_substates: bool,
+
+    /// Reject public keys which are not compressed points on the curve, and signatures with an
+    /// out-of-range recovery byte, `r` or `s`, or with a high `s`
+    pub strict_crypto: bool,
+}
+
+/// Bounds-checked cursor over a byte slice
+///
+/// Every read is checked against the number of remaining bytes, and an underflow is reported
+/// with the name of the field being read.
+pub struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    base: usize,
+    options: ParseOptions,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::with_offset(data, 0)
+    }
+
+    /// Creates a reader over a slice which starts at `offset` of some larger input, so that
+    /// reported positions stay relative to that input.
+    pub fn with_offset(data: &'a [u8], offset: usize) -> Self {
+        Self {
+            data,
+            position: 0,
+            base: offset,
+            options: ParseOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> ParseOptions {
+        self.options
+    }
+
+    /// Returns the offset of the next unread byte.
+    pub fn position(&self) -> usize {
+        self.base + self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn read_slice(&mut self, size: usize, field: &'static str) -> Result<&'a [u8], ParseError> {
+        let available = self.remaining();
+        if available < size {
+            return Err(ParseError::new(
+                ParseErrorKind::Underflow {
+                    field,
+                    needed: size,
+                    available,
+                },
+                self.position(),
+            ));
+        }
+        let slice = &self.data[self.position..self.position + size];
+        self.position += size;
+        Ok(slice)
+    }
+
+    /// Reads `size` bytes and returns a reader over them, sharing offsets and options with this
+    /// reader.
+    pub fn read_sub_reader(
+        &mut self,
+        size: usize,
+        field: &'static str,
+    ) -> Result<Reader<'a>, ParseError> {
+        let offset = self.position();
+        let data = self.read_slice(size, field)?;
+        Ok(Reader::with_offset(data, offset).with_options(self.options))
+    }
+
+    pub fn read_array<const N: usize>(
+        &mut self,
+        field: &'static str,
+    ) -> Result<[u8; N], ParseError> {
+        let slice = self.read_slice(N, field)?;
+        Ok(slice.try_into().unwrap())
+    }
+
+    pub fn read_u8(&mut self, field: &'static str) -> Result<u8, ParseError> {
+        Ok(self.read_array::<1>(field)?[0])
+    }
+
+    pub fn read_u16(&mut self, field: &'static str) -> Result<u16, ParseError> {
+        Ok(u16::from_be_bytes(self.read_array(field)?))
+    }
+
+    pub fn read_u32(&mut self, field: &'static str) -> Result<u32, ParseError> {
+        Ok(u32::from_be_bytes(self.read_array(field)?))
+    }
+
+    pub fn read_u64(&mut self, field: &'static str) -> Result<u64, ParseError> {
+        Ok(u64::from_be_bytes(self.read_array(field)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ParseErrorKind;
+    use crate::reader::Reader;
+
+    #[test]
+    fn reads_big_endian() {
+        let data = hex::decode("01000200000003000000000000000405").unwrap();
+        let mut reader = Reader::new(&data);
+        assert_eq!(reader.read_u8("a").unwrap(), 1);
+        assert_eq!(reader.read_u16("b").unwrap(), 2);
+        assert_eq!(reader.read_u32("c").unwrap(), 3);
+        assert_eq!(reader.read_u64("d").unwrap(), 4);
+        assert_eq!(reader.position(), 15);
+        assert_eq!(reader.read_slice(1, "e").unwrap(), &[5]);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn reports_underflow() {
+        let data = [0u8; 3];
+        let mut reader = Reader::new(&data);
+        reader.read_u8("first").unwrap();
+        let err = reader.read_u32("second").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Underflow {
+                field: "second",
+                needed: 4,
+                available: 2
+            }
+        );
+        assert_eq!(err.offset, 1);
+        assert_eq!(reader.position(), 1);
+    }
+
+    #[test]
+    fn sub_reader_keeps_offset() {
+        let data = [0u8, 1, 2, 3];
+        let mut reader = Reader::new(&data);
+        reader.read_u8("first").unwrap();
+        let mut sub = reader.read_sub_reader(2, "window").unwrap();
+        assert_eq!(reader.position(), 3);
+        assert_eq!(sub.position(), 1);
+        let err = sub.read_u32("second").unwrap_err();
+        assert_eq!(err.offset, 1);
+    }
+}
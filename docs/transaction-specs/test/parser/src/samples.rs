@@ -0,0 +1,21 @@
+use std::fs;
+
+use crate::transaction::Transaction;
+
+/// Loads the sample transactions, sorted by name, with their original bytes.
+pub(crate) fn samples() -> Vec<(String, Vec<u8>, Transaction)> {
+    let mut samples: Vec<(String, Vec<u8>, Transaction)> = fs::read_dir("../samples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().unwrap() == "txt")
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let raw = hex::decode(fs::read_to_string(&path).unwrap()).unwrap();
+            let tx =
+                Transaction::from_bytes(raw.clone()).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            (name, raw, tx)
+        })
+        .collect();
+    samples.sort_unstable_by(|(a, ..), (b, ..)| a.cmp(b));
+    samples
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::engine::DownSubstateId;
+use crate::engine::StateUpdate;
+use crate::error::ConstraintMachineErrorKind;
+use crate::error::StoreError;
+use crate::reader::Reader;
+use crate::substates::SubstateData;
+use crate::types::Address;
+use crate::types::SubstateId;
+
+/// Committed ledger state, which the constraint machine reads and transactions are committed to
+///
+/// Substates are exchanged as serialized, i.e. the type byte followed by their fields.
+pub trait SubstateStore {
+    /// Loads a substate which is up.
+    fn load_substate(&self, id: &SubstateId) -> Result<Option<Vec<u8>>, StoreError>;
+
+    /// Loads the parent of a virtual substate, given its serialized key, failing if the virtual
+    /// substate is down, the parent does not exist or the store fails to read it.
+    fn verify_virtual_substate(
+        &self,
+        parent: &SubstateId,
+        key: &[u8],
+    ) -> Result<Vec<u8>, ConstraintMachineErrorKind>;
+
+    /// Returns the substates which are up and whose serialization starts with a prefix, ordered
+    /// by serialization.
+    fn index(&self, prefix: &[u8]) -> Result<Vec<(SubstateId, Vec<u8>)>, StoreError>;
+
+    /// Loads the `TokenResource` substate of a resource.
+    fn load_resource(&self, address: &Address) -> Result<Option<Vec<u8>>, StoreError>;
+
+    /// Applies the state updates of a transaction, in order, either all or none of them.
+    fn commit(&mut self, updates: &[StateUpdate]) -> Result<(), StoreError>;
+}
+
+/// Substate store kept in memory
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryStore {
+    substates: HashMap<SubstateId, Vec<u8>>,
+    virtual_down_substates: HashSet<(SubstateId, Vec<u8>)>,
+    resources: HashMap<Address, SubstateId>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SubstateStore for InMemoryStore {
+    fn load_substate(&self, id: &SubstateId) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.substates.get(id).cloned())
+    }
+
+    fn verify_virtual_substate(
+        &self,
+        parent: &SubstateId,
+        key: &[u8],
+    ) -> Result<Vec<u8>, ConstraintMachineErrorKind> {
+        if self
+            .virtual_down_substates
+            .contains(&(*parent, key.to_vec()))
+        {
+            return Err(ConstraintMachineErrorKind::VirtualSubstateAlreadyDown {
+                parent: *parent,
+                key: key.to_vec(),
+            });
+        }
+        self.load_substate(parent)?.ok_or(
+            ConstraintMachineErrorKind::VirtualParentStateDoesNotExist(*parent),
+        )
+    }
+
+    fn index(&self, prefix: &[u8]) -> Result<Vec<(SubstateId, Vec<u8>)>, StoreError> {
+        let mut substates: Vec<(SubstateId, Vec<u8>)> = self
+            .substates
+            .iter()
+            .filter(|(_, raw)| raw.starts_with(prefix))
+            .map(|(id, raw)| (*id, raw.clone()))
+            .collect();
+        substates.sort_unstable_by(|(_, a), (_, b)| a.cmp(b));
+        Ok(substates)
+    }
+
+    fn load_resource(&self, address: &Address) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.resources.get(address) {
+            Some(id) => self.load_substate(id),
+            None => Ok(None),
+        }
+    }
+
+    fn commit(&mut self, updates: &[StateUpdate]) -> Result<(), StoreError> {
+        check_updates(
+            updates,
+            |id| self.substates.contains_key(id),
+            |id| self.virtual_down_substates.contains(id),
+        )?;
+        for update in updates {
+            match update {
+                StateUpdate::Up { id, substate } => {
+                    let decoded = SubstateData::from_buffer_exact(&mut Reader::new(substate));
+                    if let Ok(SubstateData::TokenResource(resource)) = decoded {
+                        self.resources.insert(resource.resource, *id);
+                    }
+                    self.substates.insert(*id, substate.clone());
+                }
+                StateUpdate::Down(DownSubstateId::Stored(id)) => {
+                    self.substates.remove(id);
+                }
+                StateUpdate::Down(DownSubstateId::Virtual { parent, key }) => {
+                    self.virtual_down_substates.insert((*parent, key.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that state updates apply, in order, to a store given its substates which are up and
+/// its virtual substates which are down.
+pub(crate) fn check_updates(
+    updates: &[StateUpdate],
+    is_up: impl Fn(&SubstateId) -> bool,
+    is_virtual_down: impl Fn(&(SubstateId, Vec<u8>)) -> bool,
+) -> Result<(), StoreError> {
+    let mut up = HashSet::new();
+    let mut down = HashSet::new();
+    let mut virtual_down = HashSet::new();
+    let exists = |id: &SubstateId, up: &HashSet<SubstateId>, down: &HashSet<SubstateId>| {
+        (is_up(id) || up.contains(id)) && !down.contains(id)
+    };
+    for update in updates {
+        match update {
+            StateUpdate::Up { id, .. } => {
+                if is_up(id) || !up.insert(*id) {
+                    return Err(StoreError::SubstateAlreadyExists(*id));
+                }
+            }
+            StateUpdate::Down(DownSubstateId::Stored(id)) => {
+                if !exists(id, &up, &down) {
+                    return Err(StoreError::SubstateNotFound(*id));
+                }
+                down.insert(*id);
+            }
+            StateUpdate::Down(DownSubstateId::Virtual { parent, key }) => {
+                if !exists(parent, &up, &down) {
+                    return Err(StoreError::VirtualParentStateDoesNotExist(*parent));
+                }
+                let id = (*parent, key.clone());
+                if is_virtual_down(&id) || !virtual_down.insert(id) {
+                    return Err(StoreError::VirtualSubstateAlreadyDown {
+                        parent: *parent,
+                        key: key.clone(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::DownSubstateId;
+    use crate::engine::StateUpdate;
+    use crate::error::ConstraintMachineErrorKind;
+    use crate::error::StoreError;
+    use crate::store::InMemoryStore;
+    use crate::store::SubstateStore;
+    use crate::types::Hash;
+    use crate::types::SubstateId;
+
+    fn substate_id(index: u32) -> SubstateId {
+        SubstateId {
+            hash: Hash { raw: [7; 32] },
+            index,
+        }
+    }
+
+    fn up(index: u32, substate: &[u8]) -> StateUpdate {
+        StateUpdate::Up {
+            id: substate_id(index),
+            substate: substate.to_vec(),
+        }
+    }
+
+    fn down(index: u32) -> StateUpdate {
+        StateUpdate::Down(DownSubstateId::Stored(substate_id(index)))
+    }
+
+    fn virtual_down(index: u32, key: &[u8]) -> StateUpdate {
+        StateUpdate::Down(DownSubstateId::Virtual {
+            parent: substate_id(index),
+            key: key.to_vec(),
+        })
+    }
+
+    #[test]
+    fn commits_atomically() {
+        let mut store = InMemoryStore::new();
+        store.commit(&[up(0, &[6, 1]), up(1, &[6, 2])]).unwrap();
+        assert_eq!(
+            store.commit(&[down(0), up(2, &[6, 3]), down(0)]),
+            Err(StoreError::SubstateNotFound(substate_id(0)))
+        );
+        assert_eq!(
+            store.commit(&[up(3, &[6, 4]), up(1, &[6, 5])]),
+            Err(StoreError::SubstateAlreadyExists(substate_id(1)))
+        );
+        assert_eq!(store.load_substate(&substate_id(0)), Ok(Some(vec![6, 1])));
+        assert_eq!(store.load_substate(&substate_id(2)), Ok(None));
+        assert_eq!(store.load_substate(&substate_id(3)), Ok(None));
+
+        store.commit(&[up(2, &[6, 3]), down(2), down(0)]).unwrap();
+        assert_eq!(store.load_substate(&substate_id(0)), Ok(None));
+        assert_eq!(store.load_substate(&substate_id(2)), Ok(None));
+    }
+
+    #[test]
+    fn tracks_virtual_substates() {
+        let mut store = InMemoryStore::new();
+        assert_eq!(
+            store.commit(&[virtual_down(0, &[1])]),
+            Err(StoreError::VirtualParentStateDoesNotExist(substate_id(0)))
+        );
+        store
+            .commit(&[up(0, &[0x0d, 0, 1]), virtual_down(0, &[1])])
+            .unwrap();
+        assert_eq!(
+            store.verify_virtual_substate(&substate_id(0), &[2]),
+            Ok(vec![0x0d, 0, 1])
+        );
+        assert_eq!(
+            store.verify_virtual_substate(&substate_id(0), &[1]),
+            Err(ConstraintMachineErrorKind::VirtualSubstateAlreadyDown {
+                parent: substate_id(0),
+                key: vec![1]
+            })
+        );
+        assert_eq!(
+            store.commit(&[virtual_down(0, &[2]), virtual_down(0, &[2])]),
+            Err(StoreError::VirtualSubstateAlreadyDown {
+                parent: substate_id(0),
+                key: vec![2]
+            })
+        );
+    }
+
+    #[test]
+    fn indexes_by_prefix() {
+        let mut store = InMemoryStore::new();
+        store
+            .commit(&[up(0, &[6, 2]), up(1, &[7, 1]), up(2, &[6, 1, 0])])
+            .unwrap();
+        assert_eq!(
+            store.index(&[6]),
+            Ok(vec![
+                (substate_id(2), vec![6, 1, 0]),
+                (substate_id(0), vec![6, 2])
+            ])
+        );
+        store.commit(&[down(2)]).unwrap();
+        assert_eq!(store.index(&[6, 1]), Ok(vec![]));
+    }
+}
@@ -1,18 +1,116 @@
-extern crate bytebuffer;
-
-use bytebuffer::ByteBuffer;
 use core::fmt::Debug;
+use std::fmt;
 
+use crate::error::ParseError;
+use crate::error::ParseErrorKind;
+use crate::error::StaticCheckError;
+use crate::error::StaticCheckErrorKind;
+use crate::reader::Reader;
 use crate::types::Address;
 use crate::types::Boolean;
+use crate::types::Bytes;
+use crate::types::Hash;
 use crate::types::PublicKey;
+use crate::types::VirtualSubstate;
 use crate::types::U256;
 use crate::types::UTF8;
 
-pub trait Substate: std::fmt::Debug {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self
+pub trait Substate: StaticCheck + std::fmt::Debug {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError>
     where
         Self: Sized;
+
+    /// Writes the substate fields, i.e. everything following the type byte.
+    fn write_to(&self, out: &mut Vec<u8>);
+}
+
+/// Static rules of a substate, which hold regardless of the ledger state
+pub trait StaticCheck {
+    fn static_check(&self) -> Result<(), StaticCheckError> {
+        Ok(())
+    }
+}
+
+/// Maximum number of UTF-16 code units of a token description, as Java's `String.length()`
+pub const MAX_DESCRIPTION_LENGTH: usize = 200;
+
+/// Maximum rake, in hundredths of a percent
+pub const MAX_RAKE: u32 = 10000;
+
+/// Substate type, i.e. the first byte of a serialized substate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubstateTypeId {
+    VirtualParent = 0x00,
+    UnclaimedREAddr = 0x01,
+    RoundData = 0x02,
+    EpochData = 0x03,
+    TokenResource = 0x04,
+    TokenResourceMetadata = 0x05,
+    Tokens = 0x06,
+    PreparedStake = 0x07,
+    StakeOwnership = 0x08,
+    PreparedUnstake = 0x09,
+    ExitingStake = 0x0A,
+    ValidatorMetadata = 0x0B,
+    ValidatorStakeData = 0x0C,
+    ValidatorBFTData = 0x0D,
+    ValidatorAllowDelegationFlag = 0x0E,
+    ValidatorRegisteredFlagCopy = 0x0F,
+    ValidatorRakeCopy = 0x10,
+    ValidatorOwnerCopy = 0x11,
+    ValidatorSystemMetadata = 0x12,
+}
+
+/// A decoded substate, one variant per substate type
+pub enum SubstateData {
+    VirtualParent(VirtualParent),
+    UnclaimedREAddr(UnclaimedREAddr),
+    RoundData(RoundData),
+    EpochData(EpochData),
+    TokenResource(TokenResource),
+    TokenResourceMetadata(TokenResourceMetadata),
+    Tokens(Tokens),
+    PreparedStake(PreparedStake),
+    StakeOwnership(StakeOwnership),
+    PreparedUnstake(PreparedUnstake),
+    ExitingStake(ExitingStake),
+    ValidatorMetadata(ValidatorMetadata),
+    ValidatorStakeData(ValidatorStakeData),
+    ValidatorBFTData(ValidatorBFTData),
+    ValidatorAllowDelegationFlag(ValidatorAllowDelegationFlag),
+    ValidatorRegisteredFlagCopy(ValidatorRegisteredFlagCopy),
+    ValidatorRakeCopy(ValidatorRakeCopy),
+    ValidatorOwnerCopy(ValidatorOwnerCopy),
+    ValidatorSystemMetadata(ValidatorSystemMetadata),
+    /// A substate of an unrecognised type, kept only when parsing with
+    /// `ParseOptions::keep_unknown_substates`
+    Unknown(UnknownSubstate),
+}
+
+#[derive(Debug)]
+pub struct VirtualParent {
+    pub reserved: u8,
+    /// All remaining bytes of the substate, as serialized by the engine (not size-prefixed)
+    pub data: Bytes,
+}
+
+#[derive(Debug)]
+pub struct UnclaimedREAddr {
+    pub reserved: u8,
+    pub address: Address,
+}
+
+#[derive(Debug)]
+pub struct RoundData {
+    pub reserved: u8,
+    pub view: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug)]
+pub struct EpochData {
+    pub reserved: u8,
+    pub epoch: u64,
 }
 
 #[derive(Debug)]
@@ -72,8 +170,8 @@ pub struct ExitingStake {
     pub reserved: u8,
     pub epoch_unlocked: u64,
     pub validator: PublicKey,
+    pub owner: Address,
     pub amount: U256,
-    pub ownership: U256,
 }
 
 #[derive(Debug)]
@@ -84,6 +182,25 @@ pub struct ValidatorMetadata {
     pub url: UTF8,
 }
 
+#[derive(Debug)]
+pub struct ValidatorStakeData {
+    pub reserved: u8,
+    pub is_registered: Boolean,
+    pub amount: U256,
+    pub validator: PublicKey,
+    pub ownership: U256,
+    pub rake_percentage: u32,
+    pub owner: Address,
+}
+
+#[derive(Debug)]
+pub struct ValidatorBFTData {
+    pub reserved: u8,
+    pub validator: PublicKey,
+    pub proposals_completed: u64,
+    pub proposals_missed: u64,
+}
+
 #[derive(Debug)]
 pub struct ValidatorAllowDelegationFlag {
     pub reserved: u8,
@@ -115,46 +232,472 @@ pub struct ValidatorOwnerCopy {
     pub owner: Address,
 }
 
-fn read_reserved_byte(buffer: &mut ByteBuffer) -> u8 {
-    let reserved = buffer.read_u8();
+#[derive(Debug)]
+pub struct ValidatorSystemMetadata {
+    pub reserved: u8,
+    pub validator: PublicKey,
+    pub state: Hash,
+}
+
+/// Substate of a type this parser does not know, with its fields left undecoded
+#[derive(Debug)]
+pub struct UnknownSubstate {
+    pub type_id: u8,
+    /// All bytes following the type byte
+    pub data: Bytes,
+}
+
+impl SubstateTypeId {
+    pub fn from_u8(t: u8) -> Option<Self> {
+        let id = match t {
+            0x00 => Self::VirtualParent,
+            0x01 => Self::UnclaimedREAddr,
+            0x02 => Self::RoundData,
+            0x03 => Self::EpochData,
+            0x04 => Self::TokenResource,
+            0x05 => Self::TokenResourceMetadata,
+            0x06 => Self::Tokens,
+            0x07 => Self::PreparedStake,
+            0x08 => Self::StakeOwnership,
+            0x09 => Self::PreparedUnstake,
+            0x0A => Self::ExitingStake,
+            0x0B => Self::ValidatorMetadata,
+            0x0C => Self::ValidatorStakeData,
+            0x0D => Self::ValidatorBFTData,
+            0x0E => Self::ValidatorAllowDelegationFlag,
+            0x0F => Self::ValidatorRegisteredFlagCopy,
+            0x10 => Self::ValidatorRakeCopy,
+            0x11 => Self::ValidatorOwnerCopy,
+            0x12 => Self::ValidatorSystemMetadata,
+            _ => return None,
+        };
+        Some(id)
+    }
+
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the name of the type in kebab case, e.g. `validator-metadata`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::VirtualParent => "virtual-parent",
+            Self::UnclaimedREAddr => "unclaimed-readdr",
+            Self::RoundData => "round-data",
+            Self::EpochData => "epoch-data",
+            Self::TokenResource => "token-resource",
+            Self::TokenResourceMetadata => "token-resource-metadata",
+            Self::Tokens => "tokens",
+            Self::PreparedStake => "prepared-stake",
+            Self::StakeOwnership => "stake-ownership",
+            Self::PreparedUnstake => "prepared-unstake",
+            Self::ExitingStake => "exiting-stake",
+            Self::ValidatorMetadata => "validator-metadata",
+            Self::ValidatorStakeData => "validator-stake-data",
+            Self::ValidatorBFTData => "validator-bft-data",
+            Self::ValidatorAllowDelegationFlag => "validator-allow-delegation-flag",
+            Self::ValidatorRegisteredFlagCopy => "validator-registered-flag-copy",
+            Self::ValidatorRakeCopy => "validator-rake-copy",
+            Self::ValidatorOwnerCopy => "validator-owner-copy",
+            Self::ValidatorSystemMetadata => "validator-system-metadata",
+        }
+    }
+}
+
+macro_rules! substate_accessors {
+    ($($name: ident => $variant: ident),* $(,)?) => {
+        $(
+            pub fn $name(&self) -> Option<&$variant> {
+                match self {
+                    Self::$variant(substate) => Some(substate),
+                    _ => None,
+                }
+            }
+        )*
+    };
+}
+
+impl SubstateData {
+    /// Reads a serialized substate, i.e. the type byte followed by its fields.
+    ///
+    /// Unknown substates consume all remaining bytes, so the reader should be bounded by the
+    /// substate size.
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let t = reader.read_u8("substate.type")?;
+        let type_id = match SubstateTypeId::from_u8(t) {
+            Some(type_id) => type_id,
+            None if reader.options().keep_unknown_substates => {
+                return Ok(Self::Unknown(UnknownSubstate::read_fields(t, reader)?));
+            }
+            None => {
+                return Err(ParseError::new(
+                    ParseErrorKind::InvalidSubstateType(t),
+                    offset,
+                ))
+            }
+        };
+        let substate = match type_id {
+            SubstateTypeId::VirtualParent => {
+                Self::VirtualParent(VirtualParent::from_buffer(reader)?)
+            }
+            SubstateTypeId::UnclaimedREAddr => {
+                Self::UnclaimedREAddr(UnclaimedREAddr::from_buffer(reader)?)
+            }
+            SubstateTypeId::RoundData => Self::RoundData(RoundData::from_buffer(reader)?),
+            SubstateTypeId::EpochData => Self::EpochData(EpochData::from_buffer(reader)?),
+            SubstateTypeId::TokenResource => {
+                Self::TokenResource(TokenResource::from_buffer(reader)?)
+            }
+            SubstateTypeId::TokenResourceMetadata => {
+                Self::TokenResourceMetadata(TokenResourceMetadata::from_buffer(reader)?)
+            }
+            SubstateTypeId::Tokens => Self::Tokens(Tokens::from_buffer(reader)?),
+            SubstateTypeId::PreparedStake => {
+                Self::PreparedStake(PreparedStake::from_buffer(reader)?)
+            }
+            SubstateTypeId::StakeOwnership => {
+                Self::StakeOwnership(StakeOwnership::from_buffer(reader)?)
+            }
+            SubstateTypeId::PreparedUnstake => {
+                Self::PreparedUnstake(PreparedUnstake::from_buffer(reader)?)
+            }
+            SubstateTypeId::ExitingStake => Self::ExitingStake(ExitingStake::from_buffer(reader)?),
+            SubstateTypeId::ValidatorMetadata => {
+                Self::ValidatorMetadata(ValidatorMetadata::from_buffer(reader)?)
+            }
+            SubstateTypeId::ValidatorStakeData => {
+                Self::ValidatorStakeData(ValidatorStakeData::from_buffer(reader)?)
+            }
+            SubstateTypeId::ValidatorBFTData => {
+                Self::ValidatorBFTData(ValidatorBFTData::from_buffer(reader)?)
+            }
+            SubstateTypeId::ValidatorAllowDelegationFlag => Self::ValidatorAllowDelegationFlag(
+                ValidatorAllowDelegationFlag::from_buffer(reader)?,
+            ),
+            SubstateTypeId::ValidatorRegisteredFlagCopy => {
+                Self::ValidatorRegisteredFlagCopy(ValidatorRegisteredFlagCopy::from_buffer(reader)?)
+            }
+            SubstateTypeId::ValidatorRakeCopy => {
+                Self::ValidatorRakeCopy(ValidatorRakeCopy::from_buffer(reader)?)
+            }
+            SubstateTypeId::ValidatorOwnerCopy => {
+                Self::ValidatorOwnerCopy(ValidatorOwnerCopy::from_buffer(reader)?)
+            }
+            SubstateTypeId::ValidatorSystemMetadata => {
+                Self::ValidatorSystemMetadata(ValidatorSystemMetadata::from_buffer(reader)?)
+            }
+        };
+        Ok(substate)
+    }
+
+    /// Reads a substate which must span all remaining bytes of the reader.
+    pub fn from_buffer_exact(reader: &mut Reader) -> Result<Self, ParseError> {
+        let start = reader.position();
+        let declared = reader.remaining();
+        let substate = Self::from_buffer(reader)?;
+        if !reader.is_empty() {
+            return Err(ParseError::new(
+                ParseErrorKind::SubstateSizeMismatch {
+                    declared,
+                    consumed: reader.position() - start,
+                },
+                reader.position(),
+            ));
+        }
+        Ok(substate)
+    }
+
+    /// Returns the substate type, or `None` for an unknown substate.
+    pub fn type_id(&self) -> Option<SubstateTypeId> {
+        let type_id = match self {
+            Self::Unknown(_) => return None,
+            Self::VirtualParent(_) => SubstateTypeId::VirtualParent,
+            Self::UnclaimedREAddr(_) => SubstateTypeId::UnclaimedREAddr,
+            Self::RoundData(_) => SubstateTypeId::RoundData,
+            Self::EpochData(_) => SubstateTypeId::EpochData,
+            Self::TokenResource(_) => SubstateTypeId::TokenResource,
+            Self::TokenResourceMetadata(_) => SubstateTypeId::TokenResourceMetadata,
+            Self::Tokens(_) => SubstateTypeId::Tokens,
+            Self::PreparedStake(_) => SubstateTypeId::PreparedStake,
+            Self::StakeOwnership(_) => SubstateTypeId::StakeOwnership,
+            Self::PreparedUnstake(_) => SubstateTypeId::PreparedUnstake,
+            Self::ExitingStake(_) => SubstateTypeId::ExitingStake,
+            Self::ValidatorMetadata(_) => SubstateTypeId::ValidatorMetadata,
+            Self::ValidatorStakeData(_) => SubstateTypeId::ValidatorStakeData,
+            Self::ValidatorBFTData(_) => SubstateTypeId::ValidatorBFTData,
+            Self::ValidatorAllowDelegationFlag(_) => SubstateTypeId::ValidatorAllowDelegationFlag,
+            Self::ValidatorRegisteredFlagCopy(_) => SubstateTypeId::ValidatorRegisteredFlagCopy,
+            Self::ValidatorRakeCopy(_) => SubstateTypeId::ValidatorRakeCopy,
+            Self::ValidatorOwnerCopy(_) => SubstateTypeId::ValidatorOwnerCopy,
+            Self::ValidatorSystemMetadata(_) => SubstateTypeId::ValidatorSystemMetadata,
+        };
+        Some(type_id)
+    }
+
+    /// Writes the serialized substate, i.e. the type byte followed by its fields.
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.type_code());
+        match self {
+            Self::Unknown(s) => out.extend_from_slice(&s.data.data),
+            _ => self.as_substate().unwrap().write_to(out),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(&mut out);
+        out
+    }
+
+    /// Returns the raw type byte, which is also defined for unknown substates.
+    pub fn type_code(&self) -> u8 {
+        match self {
+            Self::Unknown(s) => s.type_id,
+            _ => self.type_id().unwrap().to_u8(),
+        }
+    }
+
+    /// Returns the validator this substate belongs to, if any.
+    pub fn validator_key(&self) -> Option<&PublicKey> {
+        match self {
+            Self::PreparedStake(s) => Some(&s.validator),
+            Self::StakeOwnership(s) => Some(&s.validator),
+            Self::PreparedUnstake(s) => Some(&s.validator),
+            Self::ExitingStake(s) => Some(&s.validator),
+            Self::ValidatorMetadata(s) => Some(&s.validator),
+            Self::ValidatorStakeData(s) => Some(&s.validator),
+            Self::ValidatorBFTData(s) => Some(&s.validator),
+            Self::ValidatorAllowDelegationFlag(s) => Some(&s.validator),
+            Self::ValidatorRegisteredFlagCopy(s) => Some(&s.validator),
+            Self::ValidatorRakeCopy(s) => Some(&s.validator),
+            Self::ValidatorOwnerCopy(s) => Some(&s.validator),
+            Self::ValidatorSystemMetadata(s) => Some(&s.validator),
+            _ => None,
+        }
+    }
+
+    substate_accessors! {
+        as_virtual_parent => VirtualParent,
+        as_unclaimed_readdr => UnclaimedREAddr,
+        as_round_data => RoundData,
+        as_epoch_data => EpochData,
+        as_token_resource => TokenResource,
+        as_token_resource_metadata => TokenResourceMetadata,
+        as_tokens => Tokens,
+        as_prepared_stake => PreparedStake,
+        as_stake_ownership => StakeOwnership,
+        as_prepared_unstake => PreparedUnstake,
+        as_exiting_stake => ExitingStake,
+        as_validator_metadata => ValidatorMetadata,
+        as_validator_stake_data => ValidatorStakeData,
+        as_validator_bft_data => ValidatorBFTData,
+        as_validator_allow_delegation_flag => ValidatorAllowDelegationFlag,
+        as_validator_registered_flag_copy => ValidatorRegisteredFlagCopy,
+        as_validator_rake_copy => ValidatorRakeCopy,
+        as_validator_owner_copy => ValidatorOwnerCopy,
+        as_validator_system_metadata => ValidatorSystemMetadata,
+    }
+
+    pub fn as_unknown(&self) -> Option<&UnknownSubstate> {
+        match self {
+            Self::Unknown(substate) => Some(substate),
+            _ => None,
+        }
+    }
+
+    /// Checks the static rules of the substate; unknown substates have none.
+    pub fn static_check(&self) -> Result<(), StaticCheckError> {
+        match self.as_substate() {
+            Some(substate) => substate.static_check(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the decoded substate, or `None` for an unknown substate.
+    fn as_substate(&self) -> Option<&dyn Substate> {
+        let substate: &dyn Substate = match self {
+            Self::VirtualParent(s) => s,
+            Self::UnclaimedREAddr(s) => s,
+            Self::RoundData(s) => s,
+            Self::EpochData(s) => s,
+            Self::TokenResource(s) => s,
+            Self::TokenResourceMetadata(s) => s,
+            Self::Tokens(s) => s,
+            Self::PreparedStake(s) => s,
+            Self::StakeOwnership(s) => s,
+            Self::PreparedUnstake(s) => s,
+            Self::ExitingStake(s) => s,
+            Self::ValidatorMetadata(s) => s,
+            Self::ValidatorStakeData(s) => s,
+            Self::ValidatorBFTData(s) => s,
+            Self::ValidatorAllowDelegationFlag(s) => s,
+            Self::ValidatorRegisteredFlagCopy(s) => s,
+            Self::ValidatorRakeCopy(s) => s,
+            Self::ValidatorOwnerCopy(s) => s,
+            Self::ValidatorSystemMetadata(s) => s,
+            Self::Unknown(_) => return None,
+        };
+        Some(substate)
+    }
+}
+
+impl fmt::Debug for SubstateData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(substate) => substate.fmt(f),
+            _ => self.as_substate().unwrap().fmt(f),
+        }
+    }
+}
+
+fn read_reserved_byte(reader: &mut Reader) -> Result<u8, ParseError> {
+    let offset = reader.position();
+    let reserved = reader.read_u8("reserved")?;
     if reserved != 0 {
-        panic!("Reserved byte should be zero, actual: {}", reserved);
+        return Err(ParseError::new(
+            ParseErrorKind::NonZeroReservedByte(reserved),
+            offset,
+        ));
+    }
+    Ok(reserved)
+}
+
+/// Writes an optional field, i.e. a boolean presence flag followed by the value if present.
+fn write_optional<T>(out: &mut Vec<u8>, value: &Option<T>, write: impl FnOnce(&T, &mut Vec<u8>)) {
+    match value {
+        Some(v) => {
+            out.push(0x01);
+            write(v, out);
+        }
+        None => out.push(0x00),
+    }
+}
+
+impl VirtualParent {
+    /// Returns the type of the virtual substates under this parent.
+    pub fn child_type(&self) -> Option<SubstateTypeId> {
+        match self.data.data[..] {
+            [t] => SubstateTypeId::from_u8(t),
+            _ => None,
+        }
+    }
+
+    /// Decodes the raw key of a virtual substate under this parent.
+    pub fn virtual_substate(&self, key: &Bytes) -> Result<VirtualSubstate, ParseError> {
+        let type_code = self.data.data.first().copied().unwrap_or_default();
+        let type_id = self.child_type().ok_or_else(|| {
+            ParseError::new(ParseErrorKind::InvalidVirtualSubstateType(type_code), 0)
+        })?;
+        VirtualSubstate::from_key(type_id, key)
+    }
+}
+
+impl Substate for VirtualParent {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let data = reader.read_slice(reader.remaining(), "data")?.to_vec();
+
+        Ok(Self {
+            reserved,
+            data: Bytes::from_vec(data),
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        out.extend_from_slice(&self.data.data);
+    }
+}
+
+impl Substate for UnclaimedREAddr {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let address = Address::from_buffer(reader)?;
+
+        Ok(Self { reserved, address })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.address.write_to(out);
+    }
+}
+
+impl Substate for RoundData {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let view = reader.read_u64("view")?;
+        let timestamp = reader.read_u64("timestamp")?;
+
+        Ok(Self {
+            reserved,
+            view,
+            timestamp,
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        out.extend_from_slice(&self.view.to_be_bytes());
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+    }
+}
+
+impl Substate for EpochData {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let epoch = reader.read_u64("epoch")?;
+
+        Ok(Self { reserved, epoch })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        out.extend_from_slice(&self.epoch.to_be_bytes());
     }
-    reserved
 }
 
 impl Substate for TokenResource {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let resource = Address::from_buffer(buffer);
-        let granularity = U256::from_buffer(buffer);
-        let is_mutable = Boolean::from_buffer(buffer);
-        let minter = match Boolean::from_buffer(buffer).raw {
-            0x01 => Some(PublicKey::from_buffer(buffer)),
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let resource = Address::from_buffer(reader)?;
+        let granularity = U256::from_buffer(reader)?;
+        let is_mutable = Boolean::from_buffer(reader)?;
+        let minter = match Boolean::from_buffer(reader)?.raw {
+            0x01 => Some(PublicKey::from_buffer(reader)?),
             _ => None,
         };
 
-        Self {
+        Ok(Self {
             reserved,
             resource,
             granularity,
             is_mutable,
             minter,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.resource.write_to(out);
+        self.granularity.write_to(out);
+        self.is_mutable.write_to(out);
+        write_optional(out, &self.minter, |key, out| key.write_to(out));
     }
 }
 
 impl Substate for TokenResourceMetadata {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let resource = Address::from_buffer(buffer);
-        let symbol = UTF8::from_buffer(buffer);
-        let name = UTF8::from_buffer(buffer);
-        let description = UTF8::from_buffer(buffer);
-        let url = UTF8::from_buffer(buffer);
-        let icon_url = UTF8::from_buffer(buffer);
-
-        Self {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let resource = Address::from_buffer(reader)?;
+        let symbol = UTF8::from_buffer(reader)?;
+        let name = UTF8::from_buffer(reader)?;
+        let description = UTF8::from_buffer(reader)?;
+        let url = UTF8::from_buffer(reader)?;
+        let icon_url = UTF8::from_buffer(reader)?;
+
+        Ok(Self {
             reserved,
             resource,
             symbol,
@@ -162,175 +705,737 @@ impl Substate for TokenResourceMetadata {
             description,
             url,
             icon_url,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.resource.write_to(out);
+        self.symbol.write_to(out);
+        self.name.write_to(out);
+        self.description.write_to(out);
+        self.url.write_to(out);
+        self.icon_url.write_to(out);
     }
 }
 
 impl Substate for Tokens {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let owner = Address::from_buffer(buffer);
-        let resource = Address::from_buffer(buffer);
-        let amount = U256::from_buffer(buffer);
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let owner = Address::from_buffer(reader)?;
+        let resource = Address::from_buffer(reader)?;
+        let amount = U256::from_buffer(reader)?;
 
-        Self {
+        Ok(Self {
             reserved,
             resource,
             owner,
             amount,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.owner.write_to(out);
+        self.resource.write_to(out);
+        self.amount.write_to(out);
     }
 }
 
 impl Substate for PreparedStake {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let validator = PublicKey::from_buffer(buffer);
-        let owner = Address::from_buffer(buffer);
-        let amount = U256::from_buffer(buffer);
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let owner = Address::from_buffer(reader)?;
+        let amount = U256::from_buffer(reader)?;
 
-        Self {
+        Ok(Self {
             reserved,
             owner,
             validator,
             amount,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.validator.write_to(out);
+        self.owner.write_to(out);
+        self.amount.write_to(out);
     }
 }
 
 impl Substate for StakeOwnership {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let validator = PublicKey::from_buffer(buffer);
-        let owner = Address::from_buffer(buffer);
-        let amount = U256::from_buffer(buffer);
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let owner = Address::from_buffer(reader)?;
+        let amount = U256::from_buffer(reader)?;
 
-        Self {
+        Ok(Self {
             reserved,
             validator,
             owner,
             amount,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.validator.write_to(out);
+        self.owner.write_to(out);
+        self.amount.write_to(out);
     }
 }
 
 impl Substate for PreparedUnstake {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let validator = PublicKey::from_buffer(buffer);
-        let owner = Address::from_buffer(buffer);
-        let amount = U256::from_buffer(buffer);
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let owner = Address::from_buffer(reader)?;
+        let amount = U256::from_buffer(reader)?;
 
-        Self {
+        Ok(Self {
             reserved,
             validator,
             owner,
             amount,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.validator.write_to(out);
+        self.owner.write_to(out);
+        self.amount.write_to(out);
     }
 }
 
 impl Substate for ExitingStake {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let epoch_unlocked = buffer.read_u64();
-        let validator = PublicKey::from_buffer(buffer);
-        let amount = U256::from_buffer(buffer);
-        let ownership = U256::from_buffer(buffer);
-
-        Self {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let epoch_unlocked = reader.read_u64("epoch_unlocked")?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let owner = Address::from_buffer(reader)?;
+        let amount = U256::from_buffer(reader)?;
+
+        Ok(Self {
             reserved,
             epoch_unlocked,
             validator,
+            owner,
             amount,
-            ownership,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        out.extend_from_slice(&self.epoch_unlocked.to_be_bytes());
+        self.validator.write_to(out);
+        self.owner.write_to(out);
+        self.amount.write_to(out);
     }
 }
 
 impl Substate for ValidatorMetadata {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let validator = PublicKey::from_buffer(buffer);
-        let name = UTF8::from_buffer(buffer);
-        let url = UTF8::from_buffer(buffer);
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let name = UTF8::from_buffer(reader)?;
+        let url = UTF8::from_buffer(reader)?;
 
-        Self {
+        Ok(Self {
             reserved,
             validator,
             name,
             url,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.validator.write_to(out);
+        self.name.write_to(out);
+        self.url.write_to(out);
+    }
+}
+
+impl Substate for ValidatorStakeData {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let is_registered = Boolean::from_buffer(reader)?;
+        let amount = U256::from_buffer(reader)?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let ownership = U256::from_buffer(reader)?;
+        let rake_percentage = reader.read_u32("rake_percentage")?;
+        let owner = Address::from_buffer(reader)?;
+
+        Ok(Self {
+            reserved,
+            is_registered,
+            amount,
+            validator,
+            ownership,
+            rake_percentage,
+            owner,
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.is_registered.write_to(out);
+        self.amount.write_to(out);
+        self.validator.write_to(out);
+        self.ownership.write_to(out);
+        out.extend_from_slice(&self.rake_percentage.to_be_bytes());
+        self.owner.write_to(out);
+    }
+}
+
+impl Substate for ValidatorBFTData {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let proposals_completed = reader.read_u64("proposals_completed")?;
+        let proposals_missed = reader.read_u64("proposals_missed")?;
+
+        Ok(Self {
+            reserved,
+            validator,
+            proposals_completed,
+            proposals_missed,
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.validator.write_to(out);
+        out.extend_from_slice(&self.proposals_completed.to_be_bytes());
+        out.extend_from_slice(&self.proposals_missed.to_be_bytes());
     }
 }
 
 impl Substate for ValidatorAllowDelegationFlag {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let validator = PublicKey::from_buffer(buffer);
-        let is_delegation_allowed = Boolean::from_buffer(buffer);
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let is_delegation_allowed = Boolean::from_buffer(reader)?;
 
-        Self {
+        Ok(Self {
             reserved,
             validator,
             is_delegation_allowed,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.validator.write_to(out);
+        self.is_delegation_allowed.write_to(out);
     }
 }
 
 impl Substate for ValidatorRegisteredFlagCopy {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let update_epoch = match Boolean::from_buffer(buffer).raw {
-            0x01 => Some(buffer.read_u64()),
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let update_epoch = match Boolean::from_buffer(reader)?.raw {
+            0x01 => Some(reader.read_u64("update_epoch")?),
             _ => None,
         };
-        let validator = PublicKey::from_buffer(buffer);
-        let is_registered = Boolean::from_buffer(buffer);
+        let validator = PublicKey::from_buffer(reader)?;
+        let is_registered = Boolean::from_buffer(reader)?;
 
-        Self {
+        Ok(Self {
             reserved,
             update_epoch,
             validator,
             is_registered,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        write_optional(out, &self.update_epoch, |epoch, out| {
+            out.extend_from_slice(&epoch.to_be_bytes())
+        });
+        self.validator.write_to(out);
+        self.is_registered.write_to(out);
     }
 }
 
 impl Substate for ValidatorRakeCopy {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let update_epoch = match Boolean::from_buffer(buffer).raw {
-            0x01 => Some(buffer.read_u64()),
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let update_epoch = match Boolean::from_buffer(reader)?.raw {
+            0x01 => Some(reader.read_u64("update_epoch")?),
             _ => None,
         };
-        let validator = PublicKey::from_buffer(buffer);
-        let rake = buffer.read_u32();
+        let validator = PublicKey::from_buffer(reader)?;
+        let rake = reader.read_u32("rake")?;
 
-        Self {
+        Ok(Self {
             reserved,
             update_epoch,
             validator,
             rake,
-        }
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        write_optional(out, &self.update_epoch, |epoch, out| {
+            out.extend_from_slice(&epoch.to_be_bytes())
+        });
+        self.validator.write_to(out);
+        out.extend_from_slice(&self.rake.to_be_bytes());
     }
 }
 
 impl Substate for ValidatorOwnerCopy {
-    fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let reserved = read_reserved_byte(buffer);
-        let update_epoch = match Boolean::from_buffer(buffer).raw {
-            0x01 => Some(buffer.read_u64()),
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let update_epoch = match Boolean::from_buffer(reader)?.raw {
+            0x01 => Some(reader.read_u64("update_epoch")?),
             _ => None,
         };
-        let validator = PublicKey::from_buffer(buffer);
-        let owner = Address::from_buffer(buffer);
+        let validator = PublicKey::from_buffer(reader)?;
+        let owner = Address::from_buffer(reader)?;
 
-        Self {
+        Ok(Self {
             reserved,
             update_epoch,
             validator,
             owner,
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        write_optional(out, &self.update_epoch, |epoch, out| {
+            out.extend_from_slice(&epoch.to_be_bytes())
+        });
+        self.validator.write_to(out);
+        self.owner.write_to(out);
+    }
+}
+
+impl Substate for ValidatorSystemMetadata {
+    fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let reserved = read_reserved_byte(reader)?;
+        let validator = PublicKey::from_buffer(reader)?;
+        let state = Hash::from_buffer(reader)?;
+
+        Ok(Self {
+            reserved,
+            validator,
+            state,
+        })
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.reserved);
+        self.validator.write_to(out);
+        self.state.write_to(out);
+    }
+}
+
+impl UnknownSubstate {
+    fn read_fields(type_id: u8, reader: &mut Reader) -> Result<Self, ParseError> {
+        let data = reader.read_slice(reader.remaining(), "substate.data")?;
+        Ok(Self {
+            type_id,
+            data: Bytes::from_slice(data),
+        })
+    }
+}
+
+impl StaticCheck for VirtualParent {}
+impl StaticCheck for UnclaimedREAddr {}
+impl StaticCheck for RoundData {}
+impl StaticCheck for EpochData {}
+impl StaticCheck for TokenResource {}
+impl StaticCheck for PreparedUnstake {}
+impl StaticCheck for ExitingStake {}
+impl StaticCheck for ValidatorStakeData {}
+impl StaticCheck for ValidatorBFTData {}
+impl StaticCheck for ValidatorAllowDelegationFlag {}
+impl StaticCheck for ValidatorRegisteredFlagCopy {}
+impl StaticCheck for ValidatorSystemMetadata {}
+
+impl StaticCheck for TokenResourceMetadata {
+    fn static_check(&self) -> Result<(), StaticCheckError> {
+        let length = self.description.data.encode_utf16().count();
+        if length > MAX_DESCRIPTION_LENGTH {
+            return Err(StaticCheckError::new(
+                "description",
+                StaticCheckErrorKind::TooLong {
+                    max: MAX_DESCRIPTION_LENGTH,
+                    actual: length,
+                },
+            ));
+        }
+        check_url("url", &self.url)?;
+        check_url("icon_url", &self.icon_url)
+    }
+}
+
+impl StaticCheck for Tokens {
+    fn static_check(&self) -> Result<(), StaticCheckError> {
+        check_account("owner", &self.owner)?;
+        check_non_zero("amount", &self.amount)
+    }
+}
+
+impl StaticCheck for PreparedStake {
+    fn static_check(&self) -> Result<(), StaticCheckError> {
+        check_non_zero("amount", &self.amount)
+    }
+}
+
+impl StaticCheck for StakeOwnership {
+    fn static_check(&self) -> Result<(), StaticCheckError> {
+        check_non_zero("amount", &self.amount)
+    }
+}
+
+impl StaticCheck for ValidatorMetadata {
+    fn static_check(&self) -> Result<(), StaticCheckError> {
+        check_url("url", &self.url)
+    }
+}
+
+impl StaticCheck for ValidatorRakeCopy {
+    fn static_check(&self) -> Result<(), StaticCheckError> {
+        if self.rake > MAX_RAKE {
+            return Err(StaticCheckError::new(
+                "rake",
+                StaticCheckErrorKind::OutOfRange {
+                    max: MAX_RAKE,
+                    actual: self.rake,
+                },
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl StaticCheck for ValidatorOwnerCopy {
+    fn static_check(&self) -> Result<(), StaticCheckError> {
+        check_account("owner", &self.owner)
+    }
+}
+
+fn check_url(field: &'static str, url: &UTF8) -> Result<(), StaticCheckError> {
+    if !is_url_valid(&url.data) {
+        return Err(StaticCheckError::new(
+            field,
+            StaticCheckErrorKind::InvalidUrl,
+        ));
+    }
+    Ok(())
+}
+
+fn check_account(field: &'static str, address: &Address) -> Result<(), StaticCheckError> {
+    if !matches!(address, Address::PublicKey(_)) {
+        return Err(StaticCheckError::new(
+            field,
+            StaticCheckErrorKind::NotAccountAddress,
+        ));
+    }
+    Ok(())
+}
+
+fn check_non_zero(field: &'static str, amount: &U256) -> Result<(), StaticCheckError> {
+    if amount.raw.is_zero() {
+        return Err(StaticCheckError::new(field, StaticCheckErrorKind::Zero));
+    }
+    Ok(())
+}
+
+/// Checks a URL against the OWASP URL pattern used by the engine, which also allows empty URLs:
+///
+/// `^((((https?|ftps?|gopher|telnet|nntp)://)|(mailto:|news:))(%[0-9A-Fa-f]{2}|[-()_.!~*';/?:@&=+$,A-Za-z0-9])+)([).!';/?:,][[:blank:]])?$`
+///
+/// The trailing group only adds characters which the main group already allows.
+pub fn is_url_valid(url: &str) -> bool {
+    const SCHEMES: [&str; 9] = [
+        "http://",
+        "https://",
+        "ftp://",
+        "ftps://",
+        "gopher://",
+        "telnet://",
+        "nntp://",
+        "mailto:",
+        "news:",
+    ];
+    if url.is_empty() {
+        return true;
+    }
+    let rest = match SCHEMES.iter().find_map(|scheme| url.strip_prefix(scheme)) {
+        Some(rest) if !rest.is_empty() => rest.as_bytes(),
+        _ => return false,
+    };
+    let mut i = 0;
+    while i < rest.len() {
+        match rest[i] {
+            b'%' => {
+                if rest.len() < i + 3 || !rest[i + 1..i + 3].iter().all(u8::is_ascii_hexdigit) {
+                    return false;
+                }
+                i += 3;
+            }
+            c if c.is_ascii_alphanumeric() || b"-()_.!~*';/?:@&=+$,".contains(&c) => i += 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::StaticCheckError;
+    use crate::error::StaticCheckErrorKind;
+    use crate::reader::Reader;
+    use crate::substates::is_url_valid;
+    use crate::substates::SubstateData;
+    use crate::substates::SubstateTypeId;
+
+    const KEY: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const ACCOUNT: &str = "0402c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const RESOURCE: &str = "03e5a57eb6f1d6d76d3fc3b5b5a6a44ce84bff1f7e3a6b0a2ff0a1";
+    const AMOUNT: &str = "00000000000000000000000000000000000000000000000000000000000003e8";
+
+    const ZERO: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+    fn substate(parts: &[&str]) -> SubstateData {
+        let raw = hex::decode(parts.concat()).unwrap();
+        SubstateData::from_buffer(&mut Reader::new(&raw)).unwrap()
+    }
+
+    fn utf8(s: &str) -> String {
+        format!("{:04x}{}", s.len(), hex::encode(s))
+    }
+
+    fn decode(parts: &[&str]) -> String {
+        let raw = hex::decode(parts.concat()).unwrap();
+        let mut reader = Reader::new(&raw);
+        let substate = SubstateData::from_buffer(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(substate.to_bytes(), raw);
+        format!("{:?}", substate)
+    }
+
+    #[test]
+    fn system_substates() {
+        let s = decode(&["00", "00", "0b"]);
+        assert_eq!(s, "VirtualParent { reserved: 0, data: 0x0b }");
+        let raw = hex::decode("00000b").unwrap();
+        let substate = SubstateData::from_buffer(&mut Reader::new(&raw)).unwrap();
+        assert_eq!(
+            substate.as_virtual_parent().unwrap().child_type(),
+            Some(SubstateTypeId::ValidatorMetadata)
+        );
+        let s = decode(&["01", "00", RESOURCE]);
+        assert!(s.starts_with("UnclaimedREAddr { reserved: 0, address: 0x03e5a57e"));
+        let s = decode(&["02", "00", "000000000000000a", "0000017b1b6c4c00"]);
+        assert_eq!(
+            s,
+            "RoundData { reserved: 0, view: 10, timestamp: 1628252687360 }"
+        );
+        let s = decode(&["03", "00", "0000000000000002"]);
+        assert_eq!(s, "EpochData { reserved: 0, epoch: 2 }");
+    }
+
+    #[test]
+    fn token_substates() {
+        let s = decode(&["04", "00", RESOURCE, AMOUNT, "01", "01", KEY]);
+        assert!(s.starts_with("TokenResource { reserved: 0, resource: 0x03e5a57e"));
+        assert!(s.ends_with(&format!("minter: Some(0x{}) }}", KEY)));
+        let s = decode(&[
+            "05", "00", RESOURCE, "000161", "000162", "000163", "0000", "0000",
+        ]);
+        assert!(s.contains("symbol: UTF8 { length: 1, data: \"a\" }"));
+        assert!(s.contains("description: UTF8 { length: 1, data: \"c\" }"));
+        let s = decode(&["06", "00", ACCOUNT, "01", AMOUNT]);
+        assert!(s.starts_with(&format!("Tokens {{ reserved: 0, owner: 0x{}", ACCOUNT)));
+        assert!(s.ends_with("resource: 0x01, amount: U256 { raw: 1000 } }"));
+    }
+
+    #[test]
+    fn stake_substates() {
+        for t in &["07", "08", "09"] {
+            let s = decode(&[t, "00", KEY, ACCOUNT, AMOUNT]);
+            assert!(s.ends_with(&format!(
+                "validator: 0x{}, owner: 0x{}, amount: U256 {{ raw: 1000 }} }}",
+                KEY, ACCOUNT
+            )));
         }
+        let s = decode(&["0a", "00", "0000000000000005", KEY, ACCOUNT, AMOUNT]);
+        assert_eq!(
+            s,
+            format!(
+                "ExitingStake {{ reserved: 0, epoch_unlocked: 5, validator: 0x{}, owner: 0x{}, amount: U256 {{ raw: 1000 }} }}",
+                KEY, ACCOUNT
+            )
+        );
+    }
+
+    #[test]
+    fn validator_substates() {
+        let s = decode(&["0b", "00", KEY, "00026e31", "0000"]);
+        assert!(s.ends_with(
+            "name: UTF8 { length: 2, data: \"n1\" }, url: UTF8 { length: 0, data: \"\" } }"
+        ));
+        let s = decode(&["0c", "00", "01", AMOUNT, KEY, AMOUNT, "00000064", ACCOUNT]);
+        assert!(
+            s.starts_with("ValidatorStakeData { reserved: 0, is_registered: Boolean { raw: 1 }")
+        );
+        assert!(s.ends_with(&format!("rake_percentage: 100, owner: 0x{} }}", ACCOUNT)));
+        let s = decode(&["0d", "00", KEY, "0000000000000007", "0000000000000001"]);
+        assert!(s.ends_with("proposals_completed: 7, proposals_missed: 1 }"));
+        let s = decode(&["0e", "00", KEY, "01"]);
+        assert!(s.ends_with("is_delegation_allowed: Boolean { raw: 1 } }"));
+        let s = decode(&["0f", "00", "010000000000000003", KEY, "00"]);
+        assert!(s.starts_with("ValidatorRegisteredFlagCopy { reserved: 0, update_epoch: Some(3)"));
+        let s = decode(&["10", "00", "00", KEY, "000003e8"]);
+        assert!(s.ends_with("rake: 1000 }"));
+        let s = decode(&["11", "00", "00", KEY, ACCOUNT]);
+        assert!(s.starts_with("ValidatorOwnerCopy { reserved: 0, update_epoch: None"));
+        let s = decode(&["12", "00", KEY, &"ab".repeat(32)]);
+        assert!(s.ends_with(&format!("state: 0x{} }}", "ab".repeat(32))));
+    }
+
+    #[test]
+    fn url_format() {
+        for url in [
+            "",
+            "https://example.com/",
+            "http://a",
+            "ftp://example.com/a%20b?c=d&e=f",
+            "mailto:someone@example.com",
+            "news:comp.lang",
+            "https://example.com/icon.png)",
+        ] {
+            assert!(is_url_valid(url), "{}", url);
+        }
+        for url in [
+            "example.com",
+            "https://",
+            "HTTPS://example.com",
+            "https://example.com/ a",
+            "https://example.com/%2",
+            "https://example.com/%zz",
+            "https://example.com/#anchor",
+            "https://exämple.com",
+            "javascript:alert(1)",
+        ] {
+            assert!(!is_url_valid(url), "{}", url);
+        }
+    }
+
+    #[test]
+    fn static_checks() {
+        let long = "a".repeat(201);
+        let metadata = |description: &str, url: &str, icon_url: &str| {
+            substate(&[
+                "05",
+                "00",
+                RESOURCE,
+                &utf8("a"),
+                &utf8("b"),
+                &utf8(description),
+                &utf8(url),
+                &utf8(icon_url),
+            ])
+            .static_check()
+        };
+        assert_eq!(metadata(&long[1..], "", "https://a"), Ok(()));
+        // Characters outside the BMP count twice, as surrogate pairs
+        let emoji = "\u{1F600}".repeat(100);
+        assert_eq!(metadata(&emoji, "", ""), Ok(()));
+        assert_eq!(
+            metadata(&format!("{}a", emoji), "", ""),
+            Err(StaticCheckError::new(
+                "description",
+                StaticCheckErrorKind::TooLong {
+                    max: 200,
+                    actual: 201
+                }
+            ))
+        );
+        assert_eq!(
+            metadata(&long, "", ""),
+            Err(StaticCheckError::new(
+                "description",
+                StaticCheckErrorKind::TooLong {
+                    max: 200,
+                    actual: 201
+                }
+            ))
+        );
+        assert_eq!(
+            metadata("", "", "a"),
+            Err(StaticCheckError::new(
+                "icon_url",
+                StaticCheckErrorKind::InvalidUrl
+            ))
+        );
+
+        assert_eq!(
+            substate(&["06", "00", ACCOUNT, "01", AMOUNT]).static_check(),
+            Ok(())
+        );
+        assert_eq!(
+            substate(&["06", "00", ACCOUNT, "01", ZERO]).static_check(),
+            Err(StaticCheckError::new("amount", StaticCheckErrorKind::Zero))
+        );
+        assert_eq!(
+            substate(&["06", "00", RESOURCE, "01", AMOUNT]).static_check(),
+            Err(StaticCheckError::new(
+                "owner",
+                StaticCheckErrorKind::NotAccountAddress
+            ))
+        );
+        for t in ["07", "08"] {
+            assert_eq!(
+                substate(&[t, "00", KEY, ACCOUNT, ZERO]).static_check(),
+                Err(StaticCheckError::new("amount", StaticCheckErrorKind::Zero))
+            );
+        }
+        // Only the unstake may carry no amount
+        assert_eq!(
+            substate(&["09", "00", KEY, ACCOUNT, ZERO]).static_check(),
+            Ok(())
+        );
+
+        assert_eq!(
+            substate(&["0b", "00", KEY, &utf8("a"), &utf8("b")]).static_check(),
+            Err(StaticCheckError::new(
+                "url",
+                StaticCheckErrorKind::InvalidUrl
+            ))
+        );
+        assert_eq!(
+            substate(&["10", "00", "00", KEY, "00002710"]).static_check(),
+            Ok(())
+        );
+        assert_eq!(
+            substate(&["10", "00", "00", KEY, "00002711"]).static_check(),
+            Err(StaticCheckError::new(
+                "rake",
+                StaticCheckErrorKind::OutOfRange {
+                    max: 10000,
+                    actual: 10001
+                }
+            ))
+        );
+        assert_eq!(
+            substate(&["11", "00", "00", KEY, "01"]).static_check(),
+            Err(StaticCheckError::new(
+                "owner",
+                StaticCheckErrorKind::NotAccountAddress
+            ))
+        );
     }
 }
@@ -0,0 +1,132 @@
+use crate::error::ParseError;
+use crate::error::ParseErrorKind;
+use crate::reader::Reader;
+use crate::types::Bytes;
+use crate::types::U256;
+
+/// Maximum length of the symbol claimed by `READDR_CLAIM`
+pub const MAX_SYMBOL_LENGTH: usize = 32;
+
+/// System function invoked by a `SYSCALL` instruction
+#[derive(Debug)]
+pub enum Syscall {
+    /// Deposit a fee into the fee reserve
+    FeeReservePut(U256),
+
+    /// Withdraw from the fee reserve
+    FeeReserveTake(U256),
+
+    /// Claim a resource address, by its symbol (raw bytes, not size-prefixed)
+    ReaddrClaim(Bytes),
+}
+
+impl Syscall {
+    /// Reads call data, i.e. the function code followed by arguments spanning all remaining bytes
+    /// of the reader.
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let length = reader.remaining();
+        let code = reader.read_u8("call_data.function")?;
+        let syscall = match code {
+            0x00 | 0x01 => {
+                if length != 1 + 32 {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidCallDataLength(length),
+                        offset,
+                    ));
+                }
+                let amount = U256::from_buffer(reader)?;
+                if code == 0x00 {
+                    Self::FeeReservePut(amount)
+                } else {
+                    Self::FeeReserveTake(amount)
+                }
+            }
+            0x02 => {
+                if !(1..=MAX_SYMBOL_LENGTH).contains(&(length - 1)) {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidCallDataLength(length),
+                        offset,
+                    ));
+                }
+                let data = reader.read_slice(reader.remaining(), "call_data.symbol")?;
+                Self::ReaddrClaim(Bytes::from_slice(data))
+            }
+            _ => {
+                return Err(ParseError::new(
+                    ParseErrorKind::InvalidSyscall(code),
+                    offset,
+                ))
+            }
+        };
+        Ok(syscall)
+    }
+
+    /// Writes call data, without size prefix.
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.code());
+        match self {
+            Self::FeeReservePut(amount) | Self::FeeReserveTake(amount) => amount.write_to(out),
+            Self::ReaddrClaim(symbol) => out.extend_from_slice(&symbol.data),
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::FeeReservePut(_) => 0x00,
+            Self::FeeReserveTake(_) => 0x01,
+            Self::ReaddrClaim(_) => 0x02,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ParseErrorKind;
+    use crate::reader::Reader;
+    use crate::syscall::Syscall;
+
+    fn parse(hex: &str) -> Result<Syscall, crate::error::ParseError> {
+        let data = hex::decode(hex).unwrap();
+        Syscall::from_buffer(&mut Reader::new(&data))
+    }
+
+    #[test]
+    fn fee_reserve() {
+        let amount = format!("{:064x}", 100);
+        match parse(&format!("00{}", amount)).unwrap() {
+            Syscall::FeeReservePut(amount) => assert_eq!(amount.raw, 100.into()),
+            s => panic!("Unexpected syscall: {:?}", s),
+        }
+        match parse(&format!("01{}", amount)).unwrap() {
+            Syscall::FeeReserveTake(amount) => assert_eq!(amount.raw, 100.into()),
+            s => panic!("Unexpected syscall: {:?}", s),
+        }
+
+        let err = parse(&format!("00{}00", amount)).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidCallDataLength(34));
+        let err = parse("0100").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidCallDataLength(2));
+    }
+
+    #[test]
+    fn readdr_claim() {
+        match parse("02676f6c64").unwrap() {
+            Syscall::ReaddrClaim(symbol) => assert_eq!(symbol.data, b"gold"),
+            s => panic!("Unexpected syscall: {:?}", s),
+        }
+
+        let err = parse("02").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidCallDataLength(1));
+        let err = parse(&format!("02{}", "61".repeat(33))).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidCallDataLength(34));
+    }
+
+    #[test]
+    fn invalid_function() {
+        let err = parse("03").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidSyscall(3));
+        let err = parse("").unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::Underflow { .. }));
+    }
+}
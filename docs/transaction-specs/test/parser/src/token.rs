@@ -0,0 +1,205 @@
+use crate::error::TokenCreationError;
+use crate::substates::SubstateData;
+use crate::substates::SubstateTypeId;
+use crate::syscall::Syscall;
+use crate::transaction::Instruction;
+use crate::transaction::Transaction;
+use crate::types::Address;
+use crate::types::PublicKey;
+use crate::types::VirtualSubstate;
+
+/// A `READDR_CLAIM` awaiting its token substates, within the current group
+struct Claim {
+    symbol: String,
+    address: Address,
+    has_resource: bool,
+    has_metadata: bool,
+}
+
+impl Claim {
+    fn check_address(&self, actual: &Address) -> Result<(), TokenCreationError> {
+        if *actual != self.address {
+            return Err(TokenCreationError::AddressMismatch {
+                expected: self.address,
+                actual: *actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Transaction {
+    /// Checks that each `READDR_CLAIM` creates a token whose address derives from the signer's
+    /// key and the claimed symbol, with a `TokenResource` and a `TokenResourceMetadata` of the
+    /// same symbol in the same group, terminated by `END`.
+    pub fn check_token_creations(&self, signer: &PublicKey) -> Result<(), TokenCreationError> {
+        let mut claim: Option<Claim> = None;
+        for instruction in self.instructions() {
+            match (instruction, &mut claim) {
+                (Instruction::SYSCALL(Syscall::ReaddrClaim(symbol)), _) => {
+                    let symbol = String::from_utf8_lossy(&symbol.data).into_owned();
+                    claim = Some(Claim {
+                        address: Address::resource_from(signer, &symbol),
+                        symbol,
+                        has_resource: false,
+                        has_metadata: false,
+                    });
+                }
+                (Instruction::VDOWN(id), Some(claim)) => {
+                    // The claimed address is spun down from the `UNCLAIMED_READDR` parent
+                    let unclaimed =
+                        VirtualSubstate::from_key(SubstateTypeId::UnclaimedREAddr, &id.key)
+                            .map_err(TokenCreationError::InvalidClaimedAddress)?;
+                    if let Some(address) = unclaimed.key.as_address() {
+                        claim.check_address(address)?;
+                    }
+                }
+                (Instruction::UP(SubstateData::TokenResource(resource)), Some(claim)) => {
+                    claim.check_address(&resource.resource)?;
+                    claim.has_resource = true;
+                }
+                (Instruction::UP(SubstateData::TokenResourceMetadata(metadata)), Some(claim)) => {
+                    claim.check_address(&metadata.resource)?;
+                    if metadata.symbol.data != claim.symbol {
+                        return Err(TokenCreationError::SymbolMismatch {
+                            expected: claim.symbol.clone(),
+                            actual: metadata.symbol.data.clone(),
+                        });
+                    }
+                    claim.has_metadata = true;
+                }
+                (Instruction::END, Some(_)) => {
+                    let claim = claim.take().unwrap();
+                    if !claim.has_resource {
+                        return Err(TokenCreationError::MissingTokenResource);
+                    }
+                    if !claim.has_metadata {
+                        return Err(TokenCreationError::MissingMetadata);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if claim.is_some() {
+            return Err(TokenCreationError::MissingEnd);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::error::ParseErrorKind;
+    use crate::error::TokenCreationError;
+    use crate::substates::SubstateData;
+    use crate::transaction::Instruction;
+    use crate::transaction::Transaction;
+    use crate::types::Address;
+    use crate::types::PublicKey;
+
+    fn load(name: &str) -> Transaction {
+        let contents = fs::read_to_string(format!("../samples/{}.txt", name)).unwrap();
+        Transaction::from_bytes(hex::decode(contents).unwrap()).unwrap()
+    }
+
+    fn key(s: &str) -> PublicKey {
+        let mut raw = [0u8; 33];
+        raw.copy_from_slice(&hex::decode(s).unwrap());
+        PublicKey { raw }
+    }
+
+    #[test]
+    fn derives_resource_addresses() {
+        let signer = key("02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5");
+        let mut expected = [0u8; 26];
+        expected.copy_from_slice(
+            &hex::decode("35befa8e00250b5f02b15da6c8e6d4a17329a7ddf2c68cfdc388").unwrap(),
+        );
+        assert_eq!(
+            Address::resource_from(&signer, "symbol"),
+            Address::HashedKeyNonce(expected)
+        );
+
+        // Vector from ResourceAddressingTest, for private key 1
+        let signer = key("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        let mut expected = Vec::new();
+        Address::resource_from(&signer, "foo").write_to(&mut expected);
+        assert_eq!(
+            expected,
+            hex::decode("030b9cd2550726548c1256f08f177d002949d75ca1033549e82e22").unwrap()
+        );
+    }
+
+    #[test]
+    fn checks_token_create() {
+        let tx = load("token_create");
+        let signer = tx.recover_signer().unwrap();
+        assert_eq!(tx.check_token_creations(&signer), Ok(()));
+
+        let other = key("02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9");
+        assert!(matches!(
+            tx.check_token_creations(&other),
+            Err(TokenCreationError::AddressMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_mismatched_metadata() {
+        let tx = load("token_create");
+        let signer = tx.recover_signer().unwrap();
+        let mut instructions = tx.into_instructions();
+        for instruction in &mut instructions {
+            if let Instruction::UP(SubstateData::TokenResourceMetadata(metadata)) = instruction {
+                metadata.symbol.data = "other".to_string();
+            }
+        }
+        assert_eq!(
+            Transaction::new(instructions).check_token_creations(&signer),
+            Err(TokenCreationError::SymbolMismatch {
+                expected: "symbol".to_string(),
+                actual: "other".to_string(),
+            })
+        );
+
+        let tx = load("token_create");
+        let instructions = tx
+            .into_instructions()
+            .into_iter()
+            .filter(|i| !matches!(i, Instruction::UP(SubstateData::TokenResourceMetadata(_))))
+            .collect();
+        assert_eq!(
+            Transaction::new(instructions).check_token_creations(&signer),
+            Err(TokenCreationError::MissingMetadata)
+        );
+    }
+
+    #[test]
+    fn rejects_incomplete_claims() {
+        let tx = load("token_create");
+        let signer = tx.recover_signer().unwrap();
+        let mut instructions = tx.into_instructions();
+        for instruction in &mut instructions {
+            if let Instruction::VDOWN(id) = instruction {
+                id.key.data.truncate(1);
+            }
+        }
+        assert!(matches!(
+            Transaction::new(instructions).check_token_creations(&signer),
+            Err(TokenCreationError::InvalidClaimedAddress(e))
+                if matches!(e.kind, ParseErrorKind::Underflow { .. })
+        ));
+
+        let mut instructions = load("token_create").into_instructions();
+        let end = instructions
+            .iter()
+            .rposition(|i| matches!(i, Instruction::END))
+            .unwrap();
+        instructions.truncate(end);
+        assert_eq!(
+            Transaction::new(instructions).check_token_creations(&signer),
+            Err(TokenCreationError::MissingEnd)
+        );
+    }
+}
@@ -1,22 +1,41 @@
-extern crate bytebuffer;
-
-use bytebuffer::ByteBuffer;
 use std::fmt;
+use std::ops::Range;
+use std::sync::OnceLock;
 
+use crate::error::ParseError;
+use crate::error::ParseErrorKind;
+use crate::reader::ParseOptions;
+use crate::reader::Reader;
 use crate::substates::*;
+use crate::syscall::Syscall;
 use crate::types::*;
 
+/// Instructions up to and including an `END`, i.e. one action of a transaction
+#[derive(Debug)]
+pub struct InstructionGroup<'a> {
+    /// Indices of the instructions within the transaction
+    pub range: Range<usize>,
+    pub instructions: &'a [Instruction],
+}
+
+/// Decoded transaction
+///
+/// The instructions are only reachable through accessors, so that the cached bytes and ID
+/// never go stale.
 pub struct Transaction {
-    pub instructions: Vec<Instruction>,
+    instructions: Vec<Instruction>,
+    /// The bytes the transaction was parsed from, if any
+    bytes: Option<Vec<u8>>,
+    id: OnceLock<Hash>,
 }
 
 #[derive(Debug)]
 pub enum Instruction {
     END,
 
-    SYSCALL(Bytes),
+    SYSCALL(Syscall),
 
-    UP(Box<dyn Substate>),
+    UP(SubstateData),
 
     READ(SubstateId),
 
@@ -38,90 +57,300 @@ pub enum Instruction {
 
     MSG(Bytes),
 
-    HEADER(u8, u8),
+    HEADER(Header),
 
-    READINDEX(Bytes),
+    READINDEX(IndexPrefix),
 
-    DOWNINDEX(Bytes),
+    DOWNINDEX(IndexPrefix),
 }
 
 impl Transaction {
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Self {
+            instructions,
+            bytes: None,
+            id: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn with_bytes(instructions: Vec<Instruction>, bytes: Vec<u8>) -> Self {
+        Self {
+            instructions,
+            bytes: Some(bytes),
+            id: OnceLock::new(),
+        }
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, ParseError> {
+        Self::from_bytes_with_options(bytes, ParseOptions::default())
+    }
+
+    pub fn from_bytes_with_options(
+        bytes: Vec<u8>,
+        options: ParseOptions,
+    ) -> Result<Self, ParseError> {
         let mut instructions = Vec::new();
-        let mut buffer = ByteBuffer::from_bytes(&bytes[..]);
-        while buffer.get_rpos() < buffer.get_wpos() {
-            let inst = Instruction::from_buffer(&mut buffer);
-            // println!("{:?}", inst);
+        let mut reader = Reader::new(&bytes).with_options(options);
+        while !reader.is_empty() {
+            let inst = Instruction::from_buffer(&mut reader)
+                .map_err(|e| e.at_instruction(instructions.len()))?;
             instructions.push(inst);
         }
-        Self { instructions }
+        Ok(Self::with_bytes(instructions, bytes))
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Returns the instructions for modification, dropping the original bytes and the cached
+    /// ID, which are recomputed from the serialization.
+    pub fn instructions_mut(&mut self) -> &mut Vec<Instruction> {
+        self.bytes = None;
+        self.id = OnceLock::new();
+        &mut self.instructions
+    }
+
+    pub fn into_instructions(self) -> Vec<Instruction> {
+        self.instructions
+    }
+
+    /// Returns the bytes the transaction was parsed from, or `None` if it was built.
+    pub fn bytes(&self) -> Option<&[u8]> {
+        self.bytes.as_deref()
+    }
+
+    /// Returns the transaction ID, i.e. the hash of the bytes the transaction was parsed from,
+    /// or of its serialization if it was built.
+    pub fn id(&self) -> &Hash {
+        self.id.get_or_init(|| match &self.bytes {
+            Some(bytes) => Hash::sha256_twice(bytes),
+            None => Hash::sha256_twice(&self.to_bytes()),
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        self.instructions.iter().for_each(|i| i.write_to(out));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(&mut out);
+        out
+    }
+
+    /// Returns the signature of the `SIG` instruction, if any.
+    pub fn signature(&self) -> Option<&Signature> {
+        self.instructions.iter().find_map(|i| match i {
+            Instruction::SIG(signature) => Some(signature),
+            _ => None,
+        })
+    }
+
+    /// Returns the hash signed by the `SIG` instruction, i.e. the hash of all bytes preceding it,
+    /// or `None` if there is no signature.
+    pub fn signed_payload_hash(&self) -> Option<Hash> {
+        let position = self
+            .instructions
+            .iter()
+            .position(|i| matches!(i, Instruction::SIG(_)))?;
+        let mut payload = Vec::new();
+        self.instructions[..position]
+            .iter()
+            .for_each(|i| i.write_to(&mut payload));
+        match &self.bytes {
+            Some(bytes) => Some(Hash::sha256_twice(&bytes[..payload.len()])),
+            None => Some(Hash::sha256_twice(&payload)),
+        }
+    }
+
+    /// Returns the substates booted up by this transaction, along with their IDs.
+    pub fn up_substates(&self) -> impl Iterator<Item = (SubstateId, &SubstateData)> {
+        let hash = *self.id();
+        self.instructions
+            .iter()
+            .filter_map(|i| match i {
+                Instruction::UP(substate) => Some(substate),
+                _ => None,
+            })
+            .enumerate()
+            .map(move |(index, substate)| {
+                let id = SubstateId {
+                    hash,
+                    index: index as u32,
+                };
+                (id, substate)
+            })
+    }
+
+    /// Returns the substate booted up by the `UP` a local substate index refers to.
+    pub fn local_substate(&self, index: SubstateIndex) -> Option<(SubstateId, &SubstateData)> {
+        self.up_substates().nth(index as usize)
+    }
+
+    /// Splits the instructions at `END`, each group ending with its `END`.
+    ///
+    /// Instructions after the last `END`, such as `MSG` and `SIG`, belong to no group.
+    pub fn groups(&self) -> Vec<InstructionGroup<'_>> {
+        let mut groups = Vec::new();
+        let mut start = 0;
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            if let Instruction::END = instruction {
+                groups.push(InstructionGroup {
+                    range: start..index + 1,
+                    instructions: &self.instructions[start..index + 1],
+                });
+                start = index + 1;
+            }
+        }
+        groups
+    }
+
+    /// Returns the transaction header, if any.
+    pub fn header(&self) -> Option<&Header> {
+        self.instructions.iter().find_map(|i| match i {
+            Instruction::HEADER(header) => Some(header),
+            _ => None,
+        })
     }
 }
 
 impl fmt::Debug for Transaction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Instructions:\n").unwrap();
+        writeln!(f, "Instructions:").unwrap();
         self.instructions
             .iter()
-            .for_each(|i| write!(f, "|- {:?}\n", i).unwrap());
+            .for_each(|i| writeln!(f, "|- {:?}", i).unwrap());
         fmt::Result::Ok(())
     }
 }
 
 impl Instruction {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let t = buffer.read_u8();
-        match t {
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let t = reader.read_u8("opcode")?;
+        let inst = match t {
             0x00 => Self::END,
-            0x01 => Self::SYSCALL(Bytes::from_buffer(buffer)),
-            0x02 => Self::UP(Self::read_substate(buffer)),
-            0x03 => Self::READ(SubstateId::from_buffer(buffer)),
-            0x04 => Self::LREAD(buffer.read_u16()),
-            0x05 => Self::VREAD(VirtualSubstateID::from_buffer(buffer)),
-            0x06 => Self::LVREAD(LocalVirtualSubstateID::from_buffer(buffer)),
-            0x07 => Self::DOWN(SubstateId::from_buffer(buffer)),
-            0x08 => Self::LDOWN(buffer.read_u16()),
-            0x09 => Self::VDOWN(VirtualSubstateID::from_buffer(buffer)),
-            0x0A => Self::LVDOWN(LocalVirtualSubstateID::from_buffer(buffer)),
-            0x0B => Self::SIG(Signature::from_buffer(buffer)),
-            0x0C => Self::MSG(Bytes::from_buffer(buffer)),
-            0x0D => Self::HEADER(buffer.read_u8(), buffer.read_u8()),
-            0x0E => Self::READINDEX(Bytes::from_buffer(buffer)),
-            0x0F => Self::DOWNINDEX(Bytes::from_buffer(buffer)),
-            _ => panic!("Unexpected opcode: {:#04X}", t),
+            0x01 => Self::SYSCALL(Self::read_call_data(reader)?),
+            0x02 => Self::UP(Self::read_substate(reader)?),
+            0x03 => Self::READ(SubstateId::from_buffer(reader)?),
+            0x04 => Self::LREAD(reader.read_u16("substate_index")?),
+            0x05 => Self::VREAD(VirtualSubstateID::from_buffer(reader)?),
+            0x06 => Self::LVREAD(LocalVirtualSubstateID::from_buffer(reader)?),
+            0x07 => Self::DOWN(SubstateId::from_buffer(reader)?),
+            0x08 => Self::LDOWN(reader.read_u16("substate_index")?),
+            0x09 => Self::VDOWN(VirtualSubstateID::from_buffer(reader)?),
+            0x0A => Self::LVDOWN(LocalVirtualSubstateID::from_buffer(reader)?),
+            0x0B => Self::SIG(Signature::from_buffer(reader)?),
+            0x0C => Self::MSG(Bytes::from_buffer(reader)?),
+            0x0D => Self::HEADER(Header::from_buffer(reader)?),
+            0x0E => Self::READINDEX(IndexPrefix::from_buffer(reader)?),
+            0x0F => Self::DOWNINDEX(IndexPrefix::from_buffer(reader)?),
+            _ => return Err(ParseError::new(ParseErrorKind::InvalidOpcode(t), offset)),
+        };
+        Ok(inst)
+    }
+
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Self::END => 0x00,
+            Self::SYSCALL(_) => 0x01,
+            Self::UP(_) => 0x02,
+            Self::READ(_) => 0x03,
+            Self::LREAD(_) => 0x04,
+            Self::VREAD(_) => 0x05,
+            Self::LVREAD(_) => 0x06,
+            Self::DOWN(_) => 0x07,
+            Self::LDOWN(_) => 0x08,
+            Self::VDOWN(_) => 0x09,
+            Self::LVDOWN(_) => 0x0A,
+            Self::SIG(_) => 0x0B,
+            Self::MSG(_) => 0x0C,
+            Self::HEADER(_) => 0x0D,
+            Self::READINDEX(_) => 0x0E,
+            Self::DOWNINDEX(_) => 0x0F,
         }
     }
 
-    fn read_substate(buffer: &mut ByteBuffer) -> Box<dyn Substate> {
-        let _size = buffer.read_u16();
-        let t = buffer.read_u8();
-        match t {
-            0x04 => Box::new(TokenResource::from_buffer(buffer)),
-            0x05 => Box::new(TokenResourceMetadata::from_buffer(buffer)),
-            0x06 => Box::new(Tokens::from_buffer(buffer)),
-            0x07 => Box::new(PreparedStake::from_buffer(buffer)),
-            0x08 => Box::new(StakeOwnership::from_buffer(buffer)),
-            0x09 => Box::new(PreparedUnstake::from_buffer(buffer)),
-            0x0A => Box::new(ExitingStake::from_buffer(buffer)),
-            0x0E => Box::new(ValidatorAllowDelegationFlag::from_buffer(buffer)),
-            0x0F => Box::new(ValidatorRegisteredFlagCopy::from_buffer(buffer)),
-            0x10 => Box::new(ValidatorRakeCopy::from_buffer(buffer)),
-            0x11 => Box::new(ValidatorOwnerCopy::from_buffer(buffer)),
-            _ => panic!("Unsupported substate type: {:#04X}", t),
+    /// Returns the local substate index referred to by `LREAD`, `LDOWN`, `LVREAD` and `LVDOWN`.
+    pub fn local_index(&self) -> Option<SubstateIndex> {
+        match self {
+            Self::LREAD(index) | Self::LDOWN(index) => Some(*index),
+            Self::LVREAD(id) | Self::LVDOWN(id) => Some(id.parent),
+            _ => None,
         }
     }
+
+    /// Returns whether the instruction updates a substate, i.e. belongs to an instruction group.
+    pub fn is_substate_update(&self) -> bool {
+        matches!(
+            self,
+            Self::UP(_)
+                | Self::DOWN(_)
+                | Self::LDOWN(_)
+                | Self::VDOWN(_)
+                | Self::LVDOWN(_)
+                | Self::DOWNINDEX(_)
+        )
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.opcode());
+        match self {
+            Self::END => {}
+            Self::SYSCALL(syscall) => write_size_prefixed(out, |out| syscall.write_to(out)),
+            Self::UP(substate) => write_size_prefixed(out, |out| substate.write_to(out)),
+            Self::READ(id) | Self::DOWN(id) => id.write_to(out),
+            Self::LREAD(index) | Self::LDOWN(index) => out.extend_from_slice(&index.to_be_bytes()),
+            Self::VREAD(id) | Self::VDOWN(id) => id.write_to(out),
+            Self::LVREAD(id) | Self::LVDOWN(id) => id.write_to(out),
+            Self::SIG(signature) => signature.write_to(out),
+            Self::MSG(msg) => msg.write_to(out),
+            Self::HEADER(header) => header.write_to(out),
+            Self::READINDEX(prefix) | Self::DOWNINDEX(prefix) => prefix.write_to(out),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(&mut out);
+        out
+    }
+
+    fn read_call_data(reader: &mut Reader) -> Result<Syscall, ParseError> {
+        let length = reader.read_u16("call_data.length")?;
+        let mut call_data_reader = reader.read_sub_reader(length as usize, "call_data")?;
+        Syscall::from_buffer(&mut call_data_reader)
+    }
+
+    fn read_substate(reader: &mut Reader) -> Result<SubstateData, ParseError> {
+        let size = reader.read_u16("substate.size")?;
+        let mut substate_reader = reader.read_sub_reader(size as usize, "substate")?;
+        SubstateData::from_buffer_exact(&mut substate_reader)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::ParseErrorKind;
+    use crate::network::Network;
+    use crate::reader::ParseOptions;
+    use crate::samples::samples;
+    use crate::substates::SubstateTypeId;
+    use crate::syscall::Syscall;
+    use crate::transaction::Instruction;
     use crate::transaction::Transaction;
+    use crate::types::Bytes;
+    use crate::types::VirtualSubstate;
     use std::fs;
+    use std::sync::Arc;
+    use std::thread;
 
     #[test]
     fn token_create() {
         let contents = fs::read_to_string("../samples/token_create.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -129,7 +358,7 @@ mod tests {
     fn token_mint() {
         let contents = fs::read_to_string("../samples/token_mint.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -137,7 +366,7 @@ mod tests {
     fn token_transfer() {
         let contents = fs::read_to_string("../samples/token_transfer.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -145,7 +374,7 @@ mod tests {
     fn token_burn() {
         let contents = fs::read_to_string("../samples/token_burn.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -153,15 +382,37 @@ mod tests {
     fn xrd_transfer() {
         let contents = fs::read_to_string("../samples/xrd_transfer.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
+    #[test]
+    fn token_create_syscalls() {
+        let contents = fs::read_to_string("../samples/token_create.txt").unwrap();
+        let raw = hex::decode(contents).unwrap();
+        let tx = Transaction::from_bytes(raw).unwrap();
+        let syscalls: Vec<_> = tx
+            .instructions
+            .iter()
+            .filter_map(|i| match i {
+                Instruction::SYSCALL(syscall) => Some(syscall),
+                _ => None,
+            })
+            .collect();
+        match syscalls[..] {
+            [Syscall::FeeReservePut(fee), Syscall::ReaddrClaim(symbol)] => {
+                assert_eq!(fee.raw, 1000100400000000000000u128.into());
+                assert_eq!(symbol.data, b"symbol");
+            }
+            _ => panic!("Unexpected syscalls: {:?}", syscalls),
+        }
+    }
+
     #[test]
     fn xrd_transfer_with_msg() {
         let contents = fs::read_to_string("../samples/xrd_transfer_with_msg.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -169,16 +420,90 @@ mod tests {
     fn xrd_stake() {
         let contents = fs::read_to_string("../samples/xrd_stake.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
+    #[test]
+    fn xrd_stake_substates() {
+        let contents = fs::read_to_string("../samples/xrd_stake.txt").unwrap();
+        let raw = hex::decode(contents).unwrap();
+        let tx = Transaction::from_bytes(raw).unwrap();
+        let ups: Vec<_> = tx
+            .instructions
+            .iter()
+            .filter_map(|i| match i {
+                Instruction::UP(substate) => Some(substate),
+                _ => None,
+            })
+            .collect();
+        let types: Vec<_> = ups.iter().map(|s| s.type_id().unwrap()).collect();
+        assert_eq!(
+            types,
+            vec![
+                SubstateTypeId::Tokens,
+                SubstateTypeId::Tokens,
+                SubstateTypeId::PreparedStake
+            ]
+        );
+        assert!(ups[0].as_tokens().is_some());
+        assert!(ups[0].validator_key().is_none());
+        let stake = ups[2].as_prepared_stake().unwrap();
+        assert_eq!(ups[2].validator_key().unwrap().raw, stake.validator.raw);
+    }
+
+    #[test]
+    fn groups() {
+        let contents = fs::read_to_string("../samples/xrd_stake.txt").unwrap();
+        let raw = hex::decode(contents).unwrap();
+        let tx = Transaction::from_bytes(raw).unwrap();
+        let groups = tx.groups();
+        let end = groups.last().unwrap().range.end;
+        assert!(groups.len() > 1);
+        assert_eq!(groups[0].range.start, 0);
+        assert!(matches!(tx.instructions[end..], [Instruction::SIG(_)]));
+        for (group, next) in groups.iter().zip(groups.iter().skip(1)) {
+            assert_eq!(group.range.end, next.range.start);
+        }
+        for group in &groups {
+            assert_eq!(group.instructions.len(), group.range.len());
+            assert!(matches!(group.instructions.last(), Some(Instruction::END)));
+        }
+        assert!(Transaction::new(vec![]).groups().is_empty());
+    }
+
+    #[test]
+    fn strict_validator_key() {
+        let contents = fs::read_to_string("../samples/xrd_stake.txt").unwrap();
+        let mut raw = hex::decode(contents).unwrap();
+        let tx = Transaction::from_bytes(raw.clone()).unwrap();
+        let validator = tx
+            .up_substates()
+            .find_map(|(_, s)| s.validator_key())
+            .unwrap()
+            .raw;
+        // The key follows the type and reserved bytes of the stake, unlike in account addresses
+        let mut pattern = vec![SubstateTypeId::PreparedStake.to_u8(), 0x00];
+        pattern.extend_from_slice(&validator);
+        let offset = raw.windows(35).position(|w| w == pattern).unwrap() + 2;
+        raw[offset] = 0x05;
+
+        assert!(Transaction::from_bytes(raw.clone()).is_ok());
+        let options = ParseOptions {
+            strict_crypto: true,
+            ..Default::default()
+        };
+        let err = Transaction::from_bytes_with_options(raw, options).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidPublicKey);
+        assert_eq!(err.offset, offset);
+    }
+
     #[test]
     fn xrd_unstake() {
         for n in 1..3 {
             let contents = fs::read_to_string(format!("../samples/xrd_unstake{}.txt", n)).unwrap();
             let raw = hex::decode(contents).unwrap();
-            let tx = Transaction::from_bytes(raw);
+            let tx = Transaction::from_bytes(raw).unwrap();
             println!("{:?}", tx)
         }
     }
@@ -187,7 +512,7 @@ mod tests {
     fn validator_register() {
         let contents = fs::read_to_string("../samples/validator_register.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -195,7 +520,7 @@ mod tests {
     fn validator_unregister() {
         let contents = fs::read_to_string("../samples/validator_unregister.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -203,7 +528,7 @@ mod tests {
     fn validator_re_register() {
         let contents = fs::read_to_string("../samples/validator_re_register.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -211,7 +536,7 @@ mod tests {
     fn validator_allow_delegation() {
         let contents = fs::read_to_string("../samples/validator_allow_delegation.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -219,7 +544,7 @@ mod tests {
     fn other_transfer_to_self() {
         let contents = fs::read_to_string("../samples/other_transfer_to_self.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -227,7 +552,7 @@ mod tests {
     fn other_transfer_mixed_tokens() {
         let contents = fs::read_to_string("../samples/other_transfer_mixed_tokens.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -235,7 +560,7 @@ mod tests {
     fn other_stake_from_validator_1() {
         let contents = fs::read_to_string("../samples/other_stake_from_validator_1.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -243,7 +568,7 @@ mod tests {
     fn other_stake_from_validator_2() {
         let contents = fs::read_to_string("../samples/other_stake_from_validator_2.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
 
@@ -251,7 +576,333 @@ mod tests {
     fn other_complex_fee() {
         let contents = fs::read_to_string("../samples/other_complex_fee.txt").unwrap();
         let raw = hex::decode(contents).unwrap();
-        let tx = Transaction::from_bytes(raw);
+        let tx = Transaction::from_bytes(raw).unwrap();
         println!("{:?}", tx)
     }
+
+    #[test]
+    fn invalid_opcode() {
+        let err = Transaction::from_bytes(vec![0x0D, 0x00, 0x01, 0x42]).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidOpcode(0x42));
+        assert_eq!(err.offset, 3);
+        assert_eq!(err.instruction_index, Some(1));
+    }
+
+    #[test]
+    fn underflow() {
+        let err = Transaction::from_bytes(vec![0x00, 0x0C, 0x00, 0x05, 0x61]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Underflow {
+                field: "bytes.data",
+                needed: 5,
+                available: 1
+            }
+        );
+        assert_eq!(err.offset, 4);
+        assert_eq!(err.instruction_index, Some(1));
+    }
+
+    #[test]
+    fn invalid_substate_content() {
+        // UP <Tokens> with a non-zero reserved byte
+        let err = Transaction::from_bytes(hex::decode("020002060100").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::NonZeroReservedByte(1));
+        assert_eq!(err.offset, 4);
+
+        // UP <Tokens> with an unknown owner address type
+        let err = Transaction::from_bytes(hex::decode("02000306000200").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidAddressType(2));
+        assert_eq!(err.offset, 5);
+
+        // UP <ValidatorAllowDelegationFlag> with an invalid boolean
+        let mut raw = hex::decode("020024").unwrap();
+        raw.extend([0x0E, 0x00].iter());
+        raw.extend([0x02; 33].iter());
+        raw.push(0x02);
+        let err = Transaction::from_bytes(raw).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidBoolean(2));
+        assert_eq!(err.offset, 38);
+        assert_eq!(err.instruction_index, Some(0));
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        // UP <TokenResourceMetadata> with a non UTF-8 symbol
+        let err =
+            Transaction::from_bytes(hex::decode("020007050001000261ff").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidUtf8);
+        assert_eq!(err.offset, 9);
+    }
+
+    #[test]
+    fn invalid_substate_type() {
+        let err = Transaction::from_bytes(hex::decode("02000142").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidSubstateType(0x42));
+        assert_eq!(err.offset, 3);
+    }
+
+    #[test]
+    fn round_trip_samples() {
+        for (name, raw, tx) in samples() {
+            assert_eq!(tx.to_bytes(), raw, "{}", name);
+        }
+    }
+
+    #[test]
+    fn round_trip_instructions() {
+        let raw = hex::decode(concat!(
+            "0e00030c0001",
+            "0a00230001",
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+            "0f000106",
+            "04000200"
+        ))
+        .unwrap();
+        let tx = Transaction::from_bytes(raw.clone()).unwrap();
+        assert_eq!(tx.instructions.len(), 5);
+        assert_eq!(tx.to_bytes(), raw);
+
+        let options = ParseOptions {
+            keep_unknown_substates: true,
+            ..Default::default()
+        };
+        let raw = hex::decode("02000342abcd00").unwrap();
+        let tx = Transaction::from_bytes_with_options(raw.clone(), options).unwrap();
+        assert_eq!(tx.to_bytes(), raw);
+    }
+
+    #[test]
+    fn transaction_id() {
+        let contents = fs::read_to_string("../samples/xrd_transfer.txt").unwrap();
+        let raw = hex::decode(contents).unwrap();
+        let tx = Transaction::from_bytes(raw.clone()).unwrap();
+        assert_eq!(
+            hex::encode(tx.id().raw),
+            "c42a57082dc349653a405eda1c774271935e6a07abc9b662e92fcf0ce0e73de5"
+        );
+        assert_eq!(tx.bytes(), Some(&raw[..]));
+
+        let mut built = Transaction::new(tx.into_instructions());
+        assert!(built.bytes().is_none());
+        assert_eq!(
+            hex::encode(built.id().raw),
+            "c42a57082dc349653a405eda1c774271935e6a07abc9b662e92fcf0ce0e73de5"
+        );
+
+        // Modifying the instructions invalidates the cached ID
+        let mut tx = Transaction::from_bytes(raw).unwrap();
+        let id = *tx.id();
+        tx.instructions_mut()
+            .push(Instruction::MSG(Bytes::from_slice(b"hi")));
+        assert!(tx.bytes().is_none());
+        assert_ne!(*tx.id(), id);
+        built
+            .instructions_mut()
+            .push(Instruction::MSG(Bytes::from_slice(b"hi")));
+        assert_eq!(built.id(), tx.id());
+
+        // The ID can be computed from any thread sharing the transaction
+        let shared = Arc::new(built);
+        let handle = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || *shared.id())
+        };
+        assert_eq!(handle.join().unwrap(), *tx.id());
+    }
+
+    #[test]
+    fn up_substate_ids() {
+        let contents = fs::read_to_string("../samples/xrd_transfer.txt").unwrap();
+        let tx = Transaction::from_bytes(hex::decode(contents).unwrap()).unwrap();
+        let ups: Vec<_> = tx.up_substates().collect();
+        assert_eq!(ups.len(), 3);
+        for (index, (id, substate)) in ups.iter().enumerate() {
+            assert_eq!(id.hash, *tx.id());
+            assert_eq!(id.index, index as u32);
+            assert!(substate.as_tokens().is_some());
+        }
+
+        let ldown = tx
+            .instructions
+            .iter()
+            .find_map(|i| match i {
+                Instruction::LDOWN(_) => i.local_index(),
+                _ => None,
+            })
+            .unwrap();
+        let (id, substate) = tx.local_substate(ldown).unwrap();
+        assert_eq!(id.index, 0);
+        assert_eq!(
+            format!("{:?}", substate),
+            format!("{:?}", tx.up_substates().next().unwrap().1)
+        );
+        assert!(tx.local_substate(3).is_none());
+    }
+
+    #[test]
+    fn header() {
+        let contents = fs::read_to_string("../samples/xrd_transfer.txt").unwrap();
+        let tx = Transaction::from_bytes(hex::decode(contents).unwrap()).unwrap();
+        let header = tx.header().unwrap();
+        assert_eq!(header.version, 0);
+        assert!(header.flags.mint_burn_disabled());
+
+        let contents = fs::read_to_string("../samples/token_mint.txt").unwrap();
+        let tx = Transaction::from_bytes(hex::decode(contents).unwrap()).unwrap();
+        assert!(tx.header().is_none());
+
+        let tx = Transaction::from_bytes(hex::decode("0d000000").unwrap()).unwrap();
+        assert!(!tx.header().unwrap().flags.mint_burn_disabled());
+    }
+
+    #[test]
+    fn invalid_header() {
+        let err = Transaction::from_bytes(hex::decode("0d0100").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidHeaderVersion(1));
+        assert_eq!(err.offset, 1);
+
+        let err = Transaction::from_bytes(hex::decode("0d0002").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidHeaderFlags(2));
+        assert_eq!(err.offset, 2);
+    }
+
+    #[test]
+    fn virtual_substate_keys() {
+        let contents = fs::read_to_string("../samples/token_create.txt").unwrap();
+        let tx = Transaction::from_bytes(hex::decode(contents).unwrap()).unwrap();
+        let id = tx
+            .instructions
+            .iter()
+            .find_map(|i| match i {
+                Instruction::VDOWN(id) => Some(id),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(id.parent.index, 0);
+        assert_eq!(
+            format!("{:?}", id.key),
+            "0x0335befa8e00250b5f02b15da6c8e6d4a17329a7ddf2c68cfdc388"
+        );
+        let substate = VirtualSubstate::from_key(SubstateTypeId::UnclaimedREAddr, &id.key).unwrap();
+        assert_eq!(
+            format!("{:?}", substate),
+            "unclaimed-readdr(0x0335befa8e00250b5f02b15da6c8e6d4a17329a7ddf2c68cfdc388)"
+        );
+
+        let contents = fs::read_to_string("../samples/validator_register.txt").unwrap();
+        let tx = Transaction::from_bytes(hex::decode(contents).unwrap()).unwrap();
+        let id = tx
+            .instructions
+            .iter()
+            .find_map(|i| match i {
+                Instruction::VDOWN(id) => Some(id),
+                _ => None,
+            })
+            .unwrap();
+        let substate =
+            VirtualSubstate::from_key(SubstateTypeId::ValidatorMetadata, &id.key).unwrap();
+        assert!(substate.key.as_validator().is_some());
+        assert!(format!("{:?}", substate).starts_with("validator-metadata(0x02"));
+        assert!(substate
+            .to_bech32_string(Network::Mainnet)
+            .starts_with("validator-metadata(rv1"));
+
+        // The same key is not an address, nor are there virtual token substates
+        assert_eq!(
+            VirtualSubstate::from_key(SubstateTypeId::UnclaimedREAddr, &id.key)
+                .unwrap_err()
+                .kind,
+            ParseErrorKind::InvalidAddressType(0x02)
+        );
+        assert_eq!(
+            VirtualSubstate::from_key(SubstateTypeId::Tokens, &id.key)
+                .unwrap_err()
+                .kind,
+            ParseErrorKind::InvalidVirtualSubstateType(0x06)
+        );
+
+        // LVDOWN <index 1> without a key
+        let err = Transaction::from_bytes(hex::decode("0a00020001").unwrap()).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Underflow {
+                field: "virtual_key",
+                needed: 1,
+                available: 0
+            }
+        );
+    }
+
+    #[test]
+    fn index_prefix() {
+        let tx = Transaction::from_bytes(hex::decode("0e00030c0001").unwrap()).unwrap();
+        match &tx.instructions[0] {
+            Instruction::READINDEX(prefix) => {
+                assert_eq!(prefix.type_id, SubstateTypeId::ValidatorStakeData);
+                assert_eq!(prefix.prefix.data, vec![0, 1]);
+            }
+            i => panic!("Unexpected instruction: {:?}", i),
+        }
+
+        let err = Transaction::from_bytes(hex::decode("0f000142").unwrap()).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidSubstateType(0x42));
+        assert_eq!(err.offset, 3);
+    }
+
+    #[test]
+    fn substate_size_mismatch() {
+        // UP <EpochData> declaring one byte more than its fields, then END
+        let raw = hex::decode("02000b0300000000000000000700ff00").unwrap();
+        let err = Transaction::from_bytes(raw).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::SubstateSizeMismatch {
+                declared: 11,
+                consumed: 10
+            }
+        );
+        assert_eq!(err.offset, 13);
+        assert_eq!(err.instruction_index, Some(0));
+    }
+
+    #[test]
+    fn keep_unknown_substates() {
+        let raw = hex::decode("02000342abcd00").unwrap();
+        let options = ParseOptions {
+            keep_unknown_substates: true,
+            ..Default::default()
+        };
+        let tx = Transaction::from_bytes_with_options(raw, options).unwrap();
+        assert_eq!(tx.instructions.len(), 2);
+        let substate = match &tx.instructions[0] {
+            Instruction::UP(substate) => substate,
+            _ => panic!("Expected UP"),
+        };
+        assert_eq!(substate.type_id(), None);
+        assert_eq!(substate.type_code(), 0x42);
+        assert_eq!(substate.as_unknown().unwrap().data.data, vec![0xab, 0xcd]);
+    }
+
+    #[test]
+    fn truncated_sample() {
+        let contents = fs::read_to_string("../samples/xrd_transfer.txt").unwrap();
+        let raw = hex::decode(contents).unwrap();
+        for len in 1..raw.len() {
+            // Truncating at an instruction boundary still yields a parsable prefix
+            let err = match Transaction::from_bytes(raw[..len].to_vec()) {
+                Ok(_) => continue,
+                Err(err) => err,
+            };
+            match err.kind {
+                ParseErrorKind::Underflow {
+                    needed, available, ..
+                } => {
+                    assert!(needed > available);
+                    assert_eq!(err.offset + available, len);
+                }
+                _ => panic!("Unexpected error: {}", err),
+            }
+        }
+    }
 }
@@ -1,11 +1,21 @@
-extern crate bytebuffer;
 extern crate hex;
 extern crate primitive_types;
 
-use bytebuffer::ByteBuffer;
+use std::convert::TryFrom;
+use std::convert::TryInto;
 use std::fmt;
 use std::str;
 
+use k256::ecdsa;
+use k256::ecdsa::VerifyingKey;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::error::ParseError;
+use crate::error::ParseErrorKind;
+use crate::reader::Reader;
+use crate::substates::SubstateTypeId;
+
 /// Boolean
 #[derive(Debug)]
 pub struct Boolean {
@@ -34,11 +44,13 @@ pub struct UTF8 {
 }
 
 /// Hash value
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Hash {
     pub raw: [u8; 32],
 }
 
-/// ECDSA public key
+/// ECDSA public key, ordered by its compressed bytes
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PublicKey {
     pub raw: [u8; 33],
 }
@@ -51,7 +63,7 @@ pub struct Signature {
 }
 
 /// Substate ID
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct SubstateId {
     pub hash: Hash,
     pub index: u32,
@@ -59,19 +71,60 @@ pub struct SubstateId {
 
 pub type SubstateIndex = u16;
 
-/// Virtual Substate ID
+/// Virtual Substate ID, i.e. the ID of a virtual parent substate followed by a raw key
+///
+/// The key is decoded with `VirtualSubstate::from_key` given the child type of the parent.
+#[derive(Debug)]
 pub struct VirtualSubstateID {
-    pub length: Size,
-    pub data: Vec<u8>,
+    pub parent: SubstateId,
+    pub key: Bytes,
 }
 
-/// Virtual Substate ID
+/// Local Virtual Substate ID, i.e. the index of a virtual parent substate (booted up in the
+/// same transaction) followed by a raw key
+#[derive(Debug)]
 pub struct LocalVirtualSubstateID {
-    pub length: Size,
-    pub data: Vec<u8>,
+    pub parent: SubstateIndex,
+    pub key: Bytes,
 }
 
-/// Radix Engine address
+/// Key of a virtual substate, decoded according to the child type of its virtual parent
+pub enum VirtualKey {
+    /// Key of the validator substates
+    Validator(PublicKey),
+
+    /// Key of `UNCLAIMED_READDR`
+    Address(Address),
+}
+
+/// Virtual substate, i.e. its type (the child type of its virtual parent) and its key
+pub struct VirtualSubstate {
+    pub type_id: SubstateTypeId,
+    pub key: VirtualKey,
+}
+
+/// Index prefix of `READINDEX` and `DOWNINDEX`, i.e. a substate type followed by a prefix of
+/// the substate fields
+pub struct IndexPrefix {
+    pub type_id: SubstateTypeId,
+    pub prefix: Bytes,
+}
+
+/// Transaction header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub flags: TxFlags,
+}
+
+/// Transaction feature flags, as carried by the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TxFlags {
+    bits: u8,
+}
+
+/// Radix Engine address, ordered by its serialized bytes
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Address {
     System,
 
@@ -82,117 +135,443 @@ pub enum Address {
     PublicKey([u8; 33]),
 }
 
-#[macro_export]
-macro_rules! read_bytes {
-    ($buffer: expr, $size: expr) => {{
-        let mut bytes = [0u8; $size];
-        let temp = $buffer.read_bytes(bytes.len());
-        bytes.copy_from_slice(&temp[..]);
-        bytes
-    }};
-}
-
 impl Boolean {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let raw = buffer.read_u8();
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let raw = reader.read_u8("boolean")?;
         if raw != 0 && raw != 1 {
-            panic!("Invalid boolean value: {}", raw);
+            return Err(ParseError::new(ParseErrorKind::InvalidBoolean(raw), offset));
         }
-        Self { raw }
+        Ok(Self { raw })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.raw);
     }
 }
 
 impl U256 {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        Self {
-            raw: primitive_types::U256::from_big_endian(&buffer.read_bytes(32)),
-        }
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        Ok(Self {
+            raw: primitive_types::U256::from_big_endian(reader.read_slice(32, "u256")?),
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        let mut raw = [0u8; 32];
+        self.raw.to_big_endian(&mut raw);
+        out.extend_from_slice(&raw);
     }
 }
 
 impl Bytes {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let length = buffer.read_u16();
-        let data = buffer.read_bytes(length as usize);
-        Self { length, data }
+    /// Creates bytes from a copy of `data`.
+    ///
+    /// Panics if `data` is longer than `Size::MAX` bytes.
+    pub fn from_slice(data: &[u8]) -> Self {
+        Self::from_vec(data.to_vec())
     }
-}
 
-impl UTF8 {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let length = buffer.read_u16();
-        let data = buffer.read_bytes(length as usize);
+    /// Creates bytes from `data`.
+    ///
+    /// Panics if `data` is longer than `Size::MAX` bytes.
+    pub fn from_vec(data: Vec<u8>) -> Self {
         Self {
-            length,
-            data: str::from_utf8(&data).unwrap().to_owned(),
+            length: to_size(data.len()),
+            data,
         }
     }
+
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let length = reader.read_u16("bytes.length")?;
+        let data = reader.read_slice(length as usize, "bytes.data")?.to_vec();
+        Ok(Self { length, data })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&to_size(self.data.len()).to_be_bytes());
+        out.extend_from_slice(&self.data);
+    }
+}
+
+impl UTF8 {
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let length = reader.read_u16("string.length")?;
+        let offset = reader.position();
+        let data = reader.read_slice(length as usize, "string.data")?;
+        let data = str::from_utf8(data)
+            .map_err(|e| ParseError::new(ParseErrorKind::InvalidUtf8, offset + e.valid_up_to()))?
+            .to_owned();
+        Ok(Self { length, data })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&to_size(self.data.len()).to_be_bytes());
+        out.extend_from_slice(self.data.as_bytes());
+    }
 }
 
 impl Hash {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
+    /// Computes `sha_256_twice(data)`, the hash used throughout the engine.
+    pub fn sha256_twice(data: &[u8]) -> Self {
         Self {
-            raw: read_bytes!(buffer, 32),
+            raw: Sha256::digest(Sha256::digest(data)).into(),
         }
     }
+
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        Ok(Self {
+            raw: reader.read_array("hash")?,
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.raw);
+    }
 }
 
 impl PublicKey {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        Self {
-            raw: read_bytes!(buffer, 33),
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let raw = reader.read_array("public_key")?;
+        if reader.options().strict_crypto && !is_compressed_point(&raw) {
+            return Err(ParseError::new(ParseErrorKind::InvalidPublicKey, offset));
         }
+        Ok(Self { raw })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.raw);
     }
 }
 
 impl Signature {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        Self {
-            v: buffer.read_u8(),
-            r: read_bytes!(buffer, 32),
-            s: read_bytes!(buffer, 32),
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let signature = Self {
+            v: reader.read_u8("signature.v")?,
+            r: reader.read_array("signature.r")?,
+            s: reader.read_array("signature.s")?,
+        };
+        if reader.options().strict_crypto {
+            signature
+                .check_canonical()
+                .map_err(|kind| ParseError::new(kind, offset))?;
+        }
+        Ok(signature)
+    }
+
+    /// Checks the recovery byte and that `(r, s)` is in range with a low `s`, as produced by
+    /// the engine.
+    pub fn check_canonical(&self) -> Result<(), ParseErrorKind> {
+        if self.v > 3 {
+            return Err(ParseErrorKind::InvalidRecoveryId(self.v));
+        }
+        let signature = ecdsa::Signature::from_scalars(self.r, self.s)
+            .map_err(|_| ParseErrorKind::InvalidSignature)?;
+        if signature.normalize_s().is_some() {
+            return Err(ParseErrorKind::HighSignatureS);
         }
+        Ok(())
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.v);
+        out.extend_from_slice(&self.r);
+        out.extend_from_slice(&self.s);
     }
 }
 
-impl SubstateId {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        Self {
-            hash: Hash::from_buffer(buffer),
-            index: buffer.read_u32(),
+impl Header {
+    pub const VERSION: u8 = 0x00;
+
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let version = reader.read_u8("header.version")?;
+        if version != Self::VERSION {
+            return Err(ParseError::new(
+                ParseErrorKind::InvalidHeaderVersion(version),
+                offset,
+            ));
         }
+        let offset = reader.position();
+        let bits = reader.read_u8("header.flags")?;
+        let flags = TxFlags::from_bits(bits)
+            .ok_or_else(|| ParseError::new(ParseErrorKind::InvalidHeaderFlags(bits), offset))?;
+        Ok(Self { version, flags })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(self.version);
+        out.push(self.flags.bits());
+    }
+}
+
+impl TxFlags {
+    /// Resource allocation and deallocation (i.e. mint and burn) is disabled
+    pub const MINT_BURN_DISABLED: u8 = 0x01;
+
+    pub fn empty() -> Self {
+        Self { bits: 0 }
+    }
+
+    /// Returns the flags, or `None` if any unknown flag is set.
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits & !Self::MINT_BURN_DISABLED != 0 {
+            return None;
+        }
+        Some(Self { bits })
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub fn mint_burn_disabled(&self) -> bool {
+        self.bits & Self::MINT_BURN_DISABLED != 0
+    }
+}
+
+impl SubstateId {
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        Ok(Self {
+            hash: Hash::from_buffer(reader)?,
+            index: reader.read_u32("substate_id.index")?,
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        self.hash.write_to(out);
+        out.extend_from_slice(&self.index.to_be_bytes());
     }
 }
 
 impl VirtualSubstateID {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let length = buffer.read_u16();
-        let data = buffer.read_bytes(length as usize);
-        Self { length, data }
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let length = reader.read_u16("virtual_substate_id.length")?;
+        Self::read_fields(&mut reader.read_sub_reader(length as usize, "virtual_substate_id")?)
+    }
+
+    /// Reads the ID without its size prefix, spanning all remaining bytes of the reader.
+    pub(crate) fn read_fields(reader: &mut Reader) -> Result<Self, ParseError> {
+        Ok(Self {
+            parent: SubstateId::from_buffer(reader)?,
+            key: read_virtual_key(reader)?,
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        write_size_prefixed(out, |out| {
+            self.parent.write_to(out);
+            out.extend_from_slice(&self.key.data);
+        });
     }
 }
 
 impl LocalVirtualSubstateID {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let length = buffer.read_u16();
-        let data = buffer.read_bytes(length as usize);
-        Self { length, data }
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let length = reader.read_u16("virtual_substate_id.length")?;
+        Self::read_fields(&mut reader.read_sub_reader(length as usize, "virtual_substate_id")?)
+    }
+
+    /// Reads the ID without its size prefix, spanning all remaining bytes of the reader.
+    pub(crate) fn read_fields(reader: &mut Reader) -> Result<Self, ParseError> {
+        Ok(Self {
+            parent: reader.read_u16("virtual_substate_id.parent")?,
+            key: read_virtual_key(reader)?,
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        write_size_prefixed(out, |out| {
+            out.extend_from_slice(&self.parent.to_be_bytes());
+            out.extend_from_slice(&self.key.data);
+        });
+    }
+}
+
+/// Reads a raw virtual key, spanning all remaining bytes of the reader.
+fn read_virtual_key(reader: &mut Reader) -> Result<Bytes, ParseError> {
+    let offset = reader.position();
+    let data = reader.read_slice(reader.remaining(), "virtual_key")?;
+    if data.is_empty() {
+        return Err(ParseError::new(
+            ParseErrorKind::Underflow {
+                field: "virtual_key",
+                needed: 1,
+                available: 0,
+            },
+            offset,
+        ));
+    }
+    Ok(Bytes::from_slice(data))
+}
+
+impl VirtualKey {
+    /// Reads the key of a virtual substate of a given type, spanning all remaining bytes of the
+    /// reader.
+    pub fn from_buffer(type_id: SubstateTypeId, reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let length = reader.remaining();
+        let key = match type_id {
+            SubstateTypeId::UnclaimedREAddr => Self::Address(Address::from_buffer(reader)?),
+            SubstateTypeId::ValidatorMetadata
+            | SubstateTypeId::ValidatorStakeData
+            | SubstateTypeId::ValidatorAllowDelegationFlag
+            | SubstateTypeId::ValidatorRegisteredFlagCopy
+            | SubstateTypeId::ValidatorRakeCopy
+            | SubstateTypeId::ValidatorOwnerCopy
+            | SubstateTypeId::ValidatorSystemMetadata => {
+                Self::Validator(PublicKey::from_buffer(reader)?)
+            }
+            _ => {
+                return Err(ParseError::new(
+                    ParseErrorKind::InvalidVirtualSubstateType(type_id.to_u8()),
+                    offset,
+                ))
+            }
+        };
+        if !reader.is_empty() {
+            return Err(ParseError::new(
+                ParseErrorKind::InvalidVirtualKeyLength {
+                    type_id: type_id.to_u8(),
+                    length,
+                },
+                offset,
+            ));
+        }
+        Ok(key)
+    }
+
+    pub fn as_validator(&self) -> Option<&PublicKey> {
+        match self {
+            Self::Validator(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_address(&self) -> Option<&Address> {
+        match self {
+            Self::Address(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Validator(key) => key.write_to(out),
+            Self::Address(address) => address.write_to(out),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Bytes {
+        let mut data = Vec::new();
+        self.write_to(&mut data);
+        Bytes::from_vec(data)
+    }
+}
+
+impl VirtualSubstate {
+    /// Decodes the raw key of a virtual substate ID, given the child type of its parent.
+    pub fn from_key(type_id: SubstateTypeId, key: &Bytes) -> Result<Self, ParseError> {
+        Ok(Self {
+            type_id,
+            key: VirtualKey::from_buffer(type_id, &mut Reader::new(&key.data))?,
+        })
+    }
+}
+
+impl IndexPrefix {
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let length = reader.read_u16("index_prefix.length")?;
+        Self::read_fields(&mut reader.read_sub_reader(length as usize, "index_prefix")?)
+    }
+
+    /// Reads the prefix without its size prefix, spanning all remaining bytes of the reader.
+    pub(crate) fn read_fields(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let t = reader.read_u8("index_prefix.type")?;
+        let type_id = SubstateTypeId::from_u8(t)
+            .ok_or_else(|| ParseError::new(ParseErrorKind::InvalidSubstateType(t), offset))?;
+        let prefix = reader.read_slice(reader.remaining(), "index_prefix.prefix")?;
+        Ok(Self {
+            type_id,
+            prefix: Bytes::from_slice(prefix),
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        write_size_prefixed(out, |out| {
+            out.push(self.type_id.to_u8());
+            out.extend_from_slice(&self.prefix.data);
+        });
     }
 }
 
 impl Address {
-    pub fn from_buffer(buffer: &mut ByteBuffer) -> Self {
-        let t = buffer.read_u8();
+    pub fn from_buffer(reader: &mut Reader) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let t = reader.read_u8("address.type")?;
         match t {
-            0x00 => Self::System,
-            0x01 => Self::RadixNativeToken,
-            0x03 => Address::HashedKeyNonce(read_bytes!(buffer, 26)),
-            0x04 => Address::PublicKey(read_bytes!(buffer, 33)),
-            _ => panic!("Invalid address type: {}", t),
+            0x00 => Ok(Self::System),
+            0x01 => Ok(Self::RadixNativeToken),
+            0x03 => Ok(Address::HashedKeyNonce(reader.read_array("address.hash")?)),
+            0x04 => Ok(Address::PublicKey(reader.read_array("address.public_key")?)),
+            _ => Err(ParseError::new(
+                ParseErrorKind::InvalidAddressType(t),
+                offset,
+            )),
+        }
+    }
+
+    /// Derives the resource address claimed by `key` for `symbol`, i.e. the lower 26 bytes of
+    /// `sha256(sha256(public_key + symbol))`.
+    pub fn resource_from(key: &PublicKey, symbol: &str) -> Self {
+        let mut data = key.raw.to_vec();
+        data.extend_from_slice(symbol.as_bytes());
+        let hash = Hash::sha256_twice(&data);
+        Self::HashedKeyNonce(hash.raw[32 - 26..].try_into().unwrap())
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::System => out.push(0x00),
+            Self::RadixNativeToken => out.push(0x01),
+            Self::HashedKeyNonce(hash) => {
+                out.push(0x03);
+                out.extend_from_slice(hash);
+            }
+            Self::PublicKey(key) => {
+                out.push(0x04);
+                out.extend_from_slice(key);
+            }
         }
     }
 }
 
+/// Checks that a key is a compressed point on the curve, as SEC1 also allows compact points.
+pub(crate) fn is_compressed_point(raw: &[u8]) -> bool {
+    raw.len() == 33
+        && (raw[0] == 0x02 || raw[0] == 0x03)
+        && VerifyingKey::from_sec1_bytes(raw).is_ok()
+}
+
+/// Converts a length to a `u16` size prefix.
+///
+/// Panics if the length exceeds `Size::MAX`, which no field of a transaction can hold.
+pub(crate) fn to_size(length: usize) -> Size {
+    Size::try_from(length).expect("Length exceeds the u16 size prefix")
+}
+
+/// Writes a `u16` size prefix followed by the bytes written by `write`.
+pub(crate) fn write_size_prefixed(out: &mut Vec<u8>, write: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0, 0]);
+    write(out);
+    let size = to_size(out.len() - start - 2);
+    out[start..start + 2].copy_from_slice(&size.to_be_bytes());
+}
+
 impl fmt::Debug for Address {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -234,14 +613,129 @@ impl fmt::Debug for Signature {
     }
 }
 
-impl fmt::Debug for VirtualSubstateID {
+impl fmt::Debug for VirtualKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "0x{}", hex::encode(&self.data))
+        match self {
+            Self::Validator(key) => write!(f, "{:?}", key),
+            Self::Address(address) => write!(f, "{:?}", address),
+        }
     }
 }
 
-impl fmt::Debug for LocalVirtualSubstateID {
+impl fmt::Debug for VirtualSubstate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "0x{}", hex::encode(&self.data))
+        write!(f, "{}({:?})", self.type_id.name(), self.key)
+    }
+}
+
+impl fmt::Debug for IndexPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}({:?})", self.type_id, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ParseErrorKind;
+    use crate::reader::ParseOptions;
+    use crate::reader::Reader;
+    use crate::samples::samples;
+    use crate::transaction::Transaction;
+    use crate::types::Bytes;
+    use crate::types::Hash;
+    use crate::types::PublicKey;
+    use crate::types::Signature;
+
+    const STRICT: ParseOptions = ParseOptions {
+        keep_unknown_substates: false,
+        strict_crypto: true,
+    };
+
+    #[test]
+    fn sha256_twice() {
+        assert_eq!(
+            hex::encode(Hash::sha256_twice(b"").raw),
+            "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456"
+        );
+    }
+
+    #[test]
+    fn strict_public_keys() {
+        let g = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap();
+        assert!(PublicKey::from_buffer(&mut Reader::new(&g).with_options(STRICT)).is_ok());
+
+        // 0x05 is the SEC1 tag of compact points
+        let mut compact = g.clone();
+        compact[0] = 0x05;
+        let mut invalid_prefix = g.clone();
+        invalid_prefix[0] = 0x04;
+        let mut not_on_curve = g;
+        not_on_curve[1..].copy_from_slice(&[0xff; 32]);
+        for raw in [compact, invalid_prefix, not_on_curve] {
+            assert!(PublicKey::from_buffer(&mut Reader::new(&raw)).is_ok());
+            let err =
+                PublicKey::from_buffer(&mut Reader::with_offset(&raw, 5).with_options(STRICT))
+                    .unwrap_err();
+            assert_eq!(err.kind, ParseErrorKind::InvalidPublicKey);
+            assert_eq!(err.offset, 5);
+        }
+    }
+
+    #[test]
+    fn strict_signatures() {
+        let order = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+        let half = "7fffffffffffffffffffffffffffffff5d576e7357a4501ddfe92f46681b20a0";
+        let low = "7fffffffffffffffffffffffffffffff5d576e7357a4501ddfe92f46681b209f";
+        for (v, r, s, expected) in [
+            (0, low, half, Ok(())),
+            (3, low, low, Ok(())),
+            (4, low, low, Err(ParseErrorKind::InvalidRecoveryId(4))),
+            (
+                0,
+                &"00".repeat(32),
+                low,
+                Err(ParseErrorKind::InvalidSignature),
+            ),
+            (0, order, low, Err(ParseErrorKind::InvalidSignature)),
+            (
+                0,
+                low,
+                &"00".repeat(32),
+                Err(ParseErrorKind::InvalidSignature),
+            ),
+            (
+                1,
+                low,
+                &format!("{}1", &half[..63]),
+                Err(ParseErrorKind::HighSignatureS),
+            ),
+        ] {
+            let raw = hex::decode(format!("{:02x}{}{}", v, r, s)).unwrap();
+            assert!(Signature::from_buffer(&mut Reader::new(&raw)).is_ok());
+            let result = Signature::from_buffer(&mut Reader::new(&raw).with_options(STRICT));
+            assert_eq!(result.map(|_| ()).map_err(|e| e.kind), expected);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Length exceeds the u16 size prefix")]
+    fn oversized_bytes() {
+        let bytes = Bytes {
+            length: 0,
+            data: vec![0; 0x10000],
+        };
+        bytes.write_to(&mut Vec::new());
+    }
+
+    #[test]
+    fn strict_samples() {
+        for (name, raw, _) in samples() {
+            assert!(
+                Transaction::from_bytes_with_options(raw, STRICT).is_ok(),
+                "{}",
+                name
+            );
+        }
     }
 }
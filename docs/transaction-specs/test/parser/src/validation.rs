@@ -0,0 +1,359 @@
+use crate::error::ValidationError;
+use crate::error::ValidationErrorKind;
+use crate::syscall::Syscall;
+use crate::syscall::MAX_SYMBOL_LENGTH;
+use crate::transaction::Instruction;
+use crate::transaction::Transaction;
+use crate::types::Bytes;
+use crate::types::Header;
+use crate::types::Signature;
+use crate::types::TxFlags;
+
+/// Maximum length of an index prefix, including its substate type
+pub const MAX_INDEX_PREFIX_LENGTH: usize = 9;
+
+/// A transaction which passed stateless validation
+#[derive(Debug)]
+pub struct ValidatedTransaction<'a> {
+    pub instructions: &'a [Instruction],
+    pub message: Option<&'a Bytes>,
+    pub signature: Option<&'a Signature>,
+    pub flags: TxFlags,
+}
+
+/// Checks the instruction rules, the substate static rules and the instruction group rules of the
+/// "Stateless Validation" section of the spec.
+pub fn validate_stateless(tx: &Transaction) -> Result<ValidatedTransaction<'_>, ValidationError> {
+    let mut validated = ValidatedTransaction {
+        instructions: tx.instructions(),
+        message: None,
+        signature: None,
+        flags: TxFlags::empty(),
+    };
+    let mut up_count = 0;
+    for (index, instruction) in tx.instructions().iter().enumerate() {
+        let error = |kind| ValidationError {
+            kind,
+            instruction_index: index,
+        };
+        if validated.signature.is_some() {
+            return Err(error(ValidationErrorKind::MisplacedSignature));
+        }
+        if let Some(local) = instruction.local_index() {
+            if local as usize >= up_count {
+                return Err(error(ValidationErrorKind::InvalidLocalIndex {
+                    index: local,
+                    up_count,
+                }));
+            }
+        }
+        match instruction {
+            Instruction::HEADER(header) => {
+                if index != 0 {
+                    return Err(error(ValidationErrorKind::MisplacedHeader));
+                }
+                if header.version != Header::VERSION {
+                    return Err(error(ValidationErrorKind::InvalidHeaderVersion(
+                        header.version,
+                    )));
+                }
+                validated.flags = header.flags;
+            }
+            Instruction::SIG(signature) => validated.signature = Some(signature),
+            Instruction::MSG(_) if validated.message.is_some() => {
+                return Err(error(ValidationErrorKind::DuplicateMessage));
+            }
+            Instruction::MSG(message) => validated.message = Some(message),
+            Instruction::UP(substate) => {
+                substate
+                    .static_check()
+                    .map_err(|e| error(ValidationErrorKind::InvalidSubstate(e)))?;
+                up_count += 1;
+            }
+            Instruction::READINDEX(prefix) | Instruction::DOWNINDEX(prefix) => {
+                let length = 1 + prefix.prefix.data.len();
+                if length > MAX_INDEX_PREFIX_LENGTH {
+                    return Err(error(ValidationErrorKind::InvalidIndexPrefixLength(length)));
+                }
+            }
+            Instruction::SYSCALL(Syscall::ReaddrClaim(symbol))
+                if !(1..=MAX_SYMBOL_LENGTH).contains(&symbol.data.len()) =>
+            {
+                return Err(error(ValidationErrorKind::InvalidCallDataLength(
+                    1 + symbol.data.len(),
+                )));
+            }
+            _ => {}
+        }
+    }
+    check_groups(tx)?;
+    Ok(validated)
+}
+
+/// Checks that every group has a state update, that state updates are followed by an `END` and
+/// that there is at least one group.
+fn check_groups(tx: &Transaction) -> Result<(), ValidationError> {
+    let groups = tx.groups();
+    for group in &groups {
+        if !group
+            .instructions
+            .iter()
+            .any(Instruction::is_substate_update)
+        {
+            return Err(ValidationError {
+                kind: ValidationErrorKind::EmptyGroup,
+                instruction_index: group.range.end - 1,
+            });
+        }
+    }
+    let rest = groups.last().map_or(0, |group| group.range.end);
+    if let Some(index) = tx.instructions()[rest..]
+        .iter()
+        .position(Instruction::is_substate_update)
+    {
+        return Err(ValidationError {
+            kind: ValidationErrorKind::MissingEnd,
+            instruction_index: rest + index,
+        });
+    }
+    if groups.is_empty() {
+        return Err(ValidationError {
+            kind: ValidationErrorKind::NoStateUpdates,
+            instruction_index: tx.instructions().len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::error::StaticCheckError;
+    use crate::error::StaticCheckErrorKind;
+    use crate::error::ValidationErrorKind;
+    use crate::reader::Reader;
+    use crate::samples::samples;
+    use crate::substates::SubstateData;
+    use crate::substates::SubstateTypeId;
+    use crate::syscall::Syscall;
+    use crate::transaction::Instruction;
+    use crate::transaction::Transaction;
+    use crate::types::*;
+    use crate::validation::validate_stateless;
+
+    fn tokens_of(amount: u64) -> SubstateData {
+        let raw = hex::decode(format!(
+            "0600{}01{:064x}",
+            "0402c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5", amount
+        ))
+        .unwrap();
+        SubstateData::from_buffer(&mut Reader::new(&raw)).unwrap()
+    }
+
+    fn tokens() -> SubstateData {
+        tokens_of(1000)
+    }
+
+    fn header(version: u8) -> Instruction {
+        Instruction::HEADER(Header {
+            version,
+            flags: TxFlags::empty(),
+        })
+    }
+
+    fn message(data: &[u8]) -> Instruction {
+        Instruction::MSG(Bytes::from_slice(data))
+    }
+
+    fn signature() -> Instruction {
+        Instruction::SIG(Signature {
+            v: 0,
+            r: [1; 32],
+            s: [1; 32],
+        })
+    }
+
+    fn index_prefix(length: usize) -> IndexPrefix {
+        IndexPrefix {
+            type_id: SubstateTypeId::PreparedStake,
+            prefix: Bytes::from_vec(vec![0; length]),
+        }
+    }
+
+    fn validate(instructions: Vec<Instruction>) -> Result<(), (ValidationErrorKind, usize)> {
+        validate_stateless(&Transaction::new(instructions))
+            .map(|_| ())
+            .map_err(|e| (e.kind, e.instruction_index))
+    }
+
+    #[test]
+    fn validates_samples() {
+        for (name, _, tx) in samples() {
+            let validated = validate_stateless(&tx).unwrap();
+            assert_eq!(validated.instructions.len(), tx.instructions().len());
+            let flags = tx.header().map(|h| h.flags).unwrap_or_default();
+            assert_eq!(validated.flags, flags);
+            assert!(validated.signature.is_some(), "{}", name);
+        }
+
+        let contents = fs::read_to_string("../samples/xrd_transfer_with_msg.txt").unwrap();
+        let tx = Transaction::from_bytes(hex::decode(contents).unwrap()).unwrap();
+        assert!(validate_stateless(&tx).unwrap().message.is_some());
+    }
+
+    #[test]
+    fn header_and_signature_placement() {
+        assert_eq!(
+            validate(vec![Instruction::END, header(0)]),
+            Err((ValidationErrorKind::MisplacedHeader, 1))
+        );
+        assert_eq!(
+            validate(vec![header(0), header(0)]),
+            Err((ValidationErrorKind::MisplacedHeader, 1))
+        );
+        assert_eq!(
+            validate(vec![header(1)]),
+            Err((ValidationErrorKind::InvalidHeaderVersion(1), 0))
+        );
+        assert_eq!(
+            validate(vec![header(0), signature(), Instruction::END]),
+            Err((ValidationErrorKind::MisplacedSignature, 2))
+        );
+        assert_eq!(
+            validate(vec![signature(), signature()]),
+            Err((ValidationErrorKind::MisplacedSignature, 1))
+        );
+        assert_eq!(
+            validate(vec![message(b"a"), message(b"b")]),
+            Err((ValidationErrorKind::DuplicateMessage, 1))
+        );
+        assert_eq!(
+            validate(vec![
+                header(0),
+                Instruction::UP(tokens()),
+                Instruction::END,
+                message(b"a"),
+                signature()
+            ]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn local_indices() {
+        assert_eq!(
+            validate(vec![Instruction::LDOWN(0)]),
+            Err((
+                ValidationErrorKind::InvalidLocalIndex {
+                    index: 0,
+                    up_count: 0
+                },
+                0
+            ))
+        );
+        assert_eq!(
+            validate(vec![
+                Instruction::UP(tokens()),
+                Instruction::LREAD(0),
+                Instruction::LDOWN(1)
+            ]),
+            Err((
+                ValidationErrorKind::InvalidLocalIndex {
+                    index: 1,
+                    up_count: 1
+                },
+                2
+            ))
+        );
+        assert_eq!(
+            validate(vec![
+                Instruction::UP(tokens()),
+                Instruction::UP(tokens()),
+                Instruction::LDOWN(1),
+                Instruction::END
+            ]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn index_prefixes_and_syscalls() {
+        assert_eq!(
+            validate(vec![
+                Instruction::READINDEX(index_prefix(8)),
+                Instruction::UP(tokens()),
+                Instruction::END
+            ]),
+            Ok(())
+        );
+        assert_eq!(
+            validate(vec![Instruction::DOWNINDEX(index_prefix(9))]),
+            Err((ValidationErrorKind::InvalidIndexPrefixLength(10), 0))
+        );
+        let symbol = |length: usize| {
+            Instruction::SYSCALL(Syscall::ReaddrClaim(Bytes::from_vec(vec![b'a'; length])))
+        };
+        assert_eq!(
+            validate(vec![
+                symbol(32),
+                Instruction::UP(tokens()),
+                Instruction::END
+            ]),
+            Ok(())
+        );
+        assert_eq!(
+            validate(vec![symbol(0)]),
+            Err((ValidationErrorKind::InvalidCallDataLength(1), 0))
+        );
+        assert_eq!(
+            validate(vec![symbol(33)]),
+            Err((ValidationErrorKind::InvalidCallDataLength(34), 0))
+        );
+    }
+
+    #[test]
+    fn static_checks() {
+        assert_eq!(
+            validate(vec![
+                header(0),
+                Instruction::UP(tokens()),
+                Instruction::UP(tokens_of(0))
+            ]),
+            Err((
+                ValidationErrorKind::InvalidSubstate(StaticCheckError::new(
+                    "amount",
+                    StaticCheckErrorKind::Zero
+                )),
+                2
+            ))
+        );
+    }
+
+    #[test]
+    fn group_rules() {
+        let down = || {
+            Instruction::DOWN(SubstateId {
+                hash: Hash { raw: [7; 32] },
+                index: 0,
+            })
+        };
+        assert_eq!(
+            validate(vec![header(0), down(), Instruction::END, Instruction::END]),
+            Err((ValidationErrorKind::EmptyGroup, 3))
+        );
+        assert_eq!(
+            validate(vec![
+                down(),
+                Instruction::END,
+                message(b"a"),
+                Instruction::UP(tokens())
+            ]),
+            Err((ValidationErrorKind::MissingEnd, 3))
+        );
+        assert_eq!(
+            validate(vec![header(0), message(b"a")]),
+            Err((ValidationErrorKind::NoStateUpdates, 2))
+        );
+    }
+}
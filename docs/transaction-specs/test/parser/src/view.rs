@@ -0,0 +1,397 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::error::ParseError;
+use crate::error::ParseErrorKind;
+use crate::reader::ParseOptions;
+use crate::reader::Reader;
+use crate::substates::SubstateData;
+use crate::syscall::Syscall;
+use crate::transaction::Instruction;
+use crate::transaction::Transaction;
+use crate::types::*;
+
+/// Borrowed view of a transaction
+///
+/// Instructions are framed on construction, but operands are kept as slices of the input
+/// and only decoded on demand.
+pub struct TransactionRef<'a> {
+    bytes: &'a [u8],
+    instruction_count: usize,
+    options: ParseOptions,
+}
+
+/// Borrowed view of an instruction, with operands pointing into the transaction bytes
+#[derive(Debug, Clone, Copy)]
+pub enum InstructionRef<'a> {
+    END,
+
+    SYSCALL(OperandRef<'a>),
+
+    UP(SubstateRef<'a>),
+
+    READ(OperandRef<'a>),
+
+    LREAD(SubstateIndex),
+
+    VREAD(OperandRef<'a>),
+
+    LVREAD(OperandRef<'a>),
+
+    DOWN(OperandRef<'a>),
+
+    LDOWN(SubstateIndex),
+
+    VDOWN(OperandRef<'a>),
+
+    LVDOWN(OperandRef<'a>),
+
+    SIG(OperandRef<'a>),
+
+    MSG(OperandRef<'a>),
+
+    HEADER(Header),
+
+    READINDEX(OperandRef<'a>),
+
+    DOWNINDEX(OperandRef<'a>),
+}
+
+/// Borrowed view of a size-prefixed or fixed-size operand
+#[derive(Clone, Copy)]
+pub struct OperandRef<'a> {
+    /// Offset of the operand (after its size prefix, if any) in the transaction bytes
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+/// Borrowed view of a serialized substate
+#[derive(Clone, Copy)]
+pub struct SubstateRef<'a> {
+    /// Offset of the substate (type byte) in the transaction bytes
+    pub offset: usize,
+    pub type_id: u8,
+    /// The serialized substate, including the type byte
+    pub data: &'a [u8],
+}
+
+/// Iterator over the instructions of a `TransactionRef`
+///
+/// A malformed instruction is yielded as an error, which ends the iteration.
+pub struct InstructionRefs<'a> {
+    reader: Reader<'a>,
+    index: usize,
+    failed: bool,
+}
+
+const SUBSTATE_ID_LENGTH: usize = 32 + 4;
+const SIGNATURE_LENGTH: usize = 1 + 32 + 32;
+
+impl<'a> TransactionRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        Self::from_bytes_with_options(bytes, ParseOptions::default())
+    }
+
+    /// Frames the instructions of `bytes`, keeping `options` to decode operands with.
+    pub fn from_bytes_with_options(
+        bytes: &'a [u8],
+        options: ParseOptions,
+    ) -> Result<Self, ParseError> {
+        let mut instruction_count = 0;
+        let mut reader = Reader::new(bytes);
+        while !reader.is_empty() {
+            InstructionRef::from_buffer(&mut reader)
+                .map_err(|e| e.at_instruction(instruction_count))?;
+            instruction_count += 1;
+        }
+        Ok(Self {
+            bytes,
+            instruction_count,
+            options,
+        })
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn instruction_count(&self) -> usize {
+        self.instruction_count
+    }
+
+    pub fn options(&self) -> ParseOptions {
+        self.options
+    }
+
+    pub fn instructions(&self) -> InstructionRefs<'a> {
+        InstructionRefs {
+            reader: Reader::new(self.bytes),
+            index: 0,
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for InstructionRefs<'a> {
+    type Item = Result<InstructionRef<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let inst = InstructionRef::from_buffer(&mut self.reader).map_err(|e| {
+            self.failed = true;
+            e.at_instruction(self.index)
+        });
+        self.index += 1;
+        Some(inst)
+    }
+}
+
+impl<'a> InstructionRef<'a> {
+    pub fn from_buffer(reader: &mut Reader<'a>) -> Result<Self, ParseError> {
+        let offset = reader.position();
+        let t = reader.read_u8("opcode")?;
+        let inst = match t {
+            0x00 => Self::END,
+            0x01 => Self::SYSCALL(read_variable(reader, "call_data")?),
+            0x02 => Self::UP(SubstateRef::from_buffer(reader)?),
+            0x03 => Self::READ(read_fixed(reader, SUBSTATE_ID_LENGTH, "substate_id")?),
+            0x04 => Self::LREAD(reader.read_u16("substate_index")?),
+            0x05 => Self::VREAD(read_variable(reader, "virtual_substate_id")?),
+            0x06 => Self::LVREAD(read_variable(reader, "local_virtual_substate_id")?),
+            0x07 => Self::DOWN(read_fixed(reader, SUBSTATE_ID_LENGTH, "substate_id")?),
+            0x08 => Self::LDOWN(reader.read_u16("substate_index")?),
+            0x09 => Self::VDOWN(read_variable(reader, "virtual_substate_id")?),
+            0x0A => Self::LVDOWN(read_variable(reader, "local_virtual_substate_id")?),
+            0x0B => Self::SIG(read_fixed(reader, SIGNATURE_LENGTH, "signature")?),
+            0x0C => Self::MSG(read_variable(reader, "msg")?),
+            0x0D => Self::HEADER(Header::from_buffer(reader)?),
+            0x0E => Self::READINDEX(read_variable(reader, "prefix")?),
+            0x0F => Self::DOWNINDEX(read_variable(reader, "prefix")?),
+            _ => return Err(ParseError::new(ParseErrorKind::InvalidOpcode(t), offset)),
+        };
+        Ok(inst)
+    }
+
+    pub fn decode(&self) -> Result<Instruction, ParseError> {
+        self.decode_with_options(ParseOptions::default())
+    }
+
+    /// Decodes the operands, reporting offsets relative to the transaction bytes.
+    pub fn decode_with_options(&self, options: ParseOptions) -> Result<Instruction, ParseError> {
+        let inst = match *self {
+            Self::END => Instruction::END,
+            Self::SYSCALL(data) => {
+                Instruction::SYSCALL(Syscall::from_buffer(&mut data.reader(options))?)
+            }
+            Self::UP(substate) => Instruction::UP(substate.decode_with_options(options)?),
+            Self::READ(data) => {
+                Instruction::READ(SubstateId::from_buffer(&mut data.reader(options))?)
+            }
+            Self::LREAD(index) => Instruction::LREAD(index),
+            Self::VREAD(data) => {
+                Instruction::VREAD(VirtualSubstateID::read_fields(&mut data.reader(options))?)
+            }
+            Self::LVREAD(data) => Instruction::LVREAD(LocalVirtualSubstateID::read_fields(
+                &mut data.reader(options),
+            )?),
+            Self::DOWN(data) => {
+                Instruction::DOWN(SubstateId::from_buffer(&mut data.reader(options))?)
+            }
+            Self::LDOWN(index) => Instruction::LDOWN(index),
+            Self::VDOWN(data) => {
+                Instruction::VDOWN(VirtualSubstateID::read_fields(&mut data.reader(options))?)
+            }
+            Self::LVDOWN(data) => Instruction::LVDOWN(LocalVirtualSubstateID::read_fields(
+                &mut data.reader(options),
+            )?),
+            Self::SIG(data) => Instruction::SIG(Signature::from_buffer(&mut data.reader(options))?),
+            Self::MSG(data) => Instruction::MSG(Bytes::from_slice(data.data)),
+            Self::HEADER(header) => Instruction::HEADER(header),
+            Self::READINDEX(data) => {
+                Instruction::READINDEX(IndexPrefix::read_fields(&mut data.reader(options))?)
+            }
+            Self::DOWNINDEX(data) => {
+                Instruction::DOWNINDEX(IndexPrefix::read_fields(&mut data.reader(options))?)
+            }
+        };
+        Ok(inst)
+    }
+}
+
+impl<'a> OperandRef<'a> {
+    fn reader(&self, options: ParseOptions) -> Reader<'a> {
+        Reader::with_offset(self.data, self.offset).with_options(options)
+    }
+}
+
+impl<'a> SubstateRef<'a> {
+    /// Frames a substate by its size prefix, without decoding its fields.
+    pub fn from_buffer(reader: &mut Reader<'a>) -> Result<Self, ParseError> {
+        let size = reader.read_u16("substate.size")?;
+        let offset = reader.position();
+        let data = reader.read_slice(size as usize, "substate")?;
+        let type_id = Reader::with_offset(data, offset).read_u8("substate.type")?;
+        Ok(Self {
+            offset,
+            type_id,
+            data,
+        })
+    }
+
+    pub fn decode(&self) -> Result<SubstateData, ParseError> {
+        self.decode_with_options(ParseOptions::default())
+    }
+
+    pub fn decode_with_options(&self, options: ParseOptions) -> Result<SubstateData, ParseError> {
+        let mut reader = Reader::with_offset(self.data, self.offset).with_options(options);
+        SubstateData::from_buffer_exact(&mut reader)
+    }
+}
+
+/// Reads a size-prefixed operand, without the size prefix.
+fn read_variable<'a>(
+    reader: &mut Reader<'a>,
+    field: &'static str,
+) -> Result<OperandRef<'a>, ParseError> {
+    let length = reader.read_u16(field)?;
+    read_fixed(reader, length as usize, field)
+}
+
+fn read_fixed<'a>(
+    reader: &mut Reader<'a>,
+    length: usize,
+    field: &'static str,
+) -> Result<OperandRef<'a>, ParseError> {
+    let offset = reader.position();
+    let data = reader.read_slice(length, field)?;
+    Ok(OperandRef { offset, data })
+}
+
+impl<'a> TryFrom<InstructionRef<'a>> for Instruction {
+    type Error = ParseError;
+
+    fn try_from(inst: InstructionRef<'a>) -> Result<Self, Self::Error> {
+        inst.decode()
+    }
+}
+
+impl<'a> TryFrom<&TransactionRef<'a>> for Transaction {
+    type Error = ParseError;
+
+    fn try_from(tx: &TransactionRef<'a>) -> Result<Self, Self::Error> {
+        let instructions = tx
+            .instructions()
+            .enumerate()
+            .map(|(i, inst)| {
+                inst?
+                    .decode_with_options(tx.options)
+                    .map_err(|e| e.at_instruction(i))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::with_bytes(instructions, tx.bytes.to_vec()))
+    }
+}
+
+impl<'a> fmt::Debug for OperandRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.data))
+    }
+}
+
+impl<'a> fmt::Debug for SubstateRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::fs;
+
+    use crate::error::ParseErrorKind;
+    use crate::reader::ParseOptions;
+    use crate::samples::samples;
+    use crate::transaction::Transaction;
+    use crate::view::InstructionRef;
+    use crate::view::TransactionRef;
+
+    #[test]
+    fn borrows_samples() {
+        for (_, raw, tx) in samples() {
+            let tx_ref = TransactionRef::from_bytes(&raw).unwrap();
+            assert_eq!(tx_ref.instruction_count(), tx.instructions().len());
+            assert_eq!(
+                tx_ref.instructions().filter(Result::is_ok).count(),
+                tx.instructions().len()
+            );
+
+            let converted = Transaction::try_from(&tx_ref).unwrap();
+            assert_eq!(format!("{:?}", converted), format!("{:?}", tx));
+        }
+    }
+
+    #[test]
+    fn operands_point_into_input() {
+        let contents = fs::read_to_string("../samples/xrd_transfer_with_msg.txt").unwrap();
+        let raw = hex::decode(contents).unwrap();
+        let tx_ref = TransactionRef::from_bytes(&raw).unwrap();
+        let range = raw.as_ptr_range();
+        for inst in tx_ref.instructions() {
+            match inst.unwrap() {
+                InstructionRef::MSG(msg) => {
+                    assert!(range.contains(&msg.data.as_ptr()));
+                    assert_eq!(&raw[msg.offset..msg.offset + msg.data.len()], msg.data);
+                }
+                InstructionRef::UP(substate) => {
+                    assert!(range.contains(&substate.data.as_ptr()));
+                    assert_eq!(raw[substate.offset], substate.type_id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn lazy_decode_reports_absolute_offset() {
+        // HEADER, then UP <Tokens> with a non-zero reserved byte
+        let raw = hex::decode("0d0001020002060100").unwrap();
+        let tx_ref = TransactionRef::from_bytes(&raw).unwrap();
+        let up = tx_ref.instructions().nth(1).unwrap().unwrap();
+        let err = match up {
+            InstructionRef::UP(substate) => substate.decode().unwrap_err(),
+            _ => panic!("Expected UP"),
+        };
+        assert_eq!(err.kind, ParseErrorKind::NonZeroReservedByte(1));
+        assert_eq!(err.offset, 7);
+
+        let err = Transaction::try_from(&tx_ref).unwrap_err();
+        assert_eq!(err.instruction_index, Some(1));
+
+        // HEADER, then SYSCALL with an unknown function code
+        let raw = hex::decode("0d00010100017f").unwrap();
+        let tx_ref = TransactionRef::from_bytes(&raw).unwrap();
+        let err = Transaction::try_from(&tx_ref).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidSyscall(0x7f));
+        assert_eq!(err.offset, 6);
+        assert_eq!(err.instruction_index, Some(1));
+    }
+
+    #[test]
+    fn lazy_decode_applies_options() {
+        // UP of an unknown substate type
+        let raw = hex::decode("020002ee00").unwrap();
+        let tx_ref = TransactionRef::from_bytes(&raw).unwrap();
+        assert!(Transaction::try_from(&tx_ref).is_err());
+
+        let options = ParseOptions {
+            keep_unknown_substates: true,
+            ..ParseOptions::default()
+        };
+        let tx_ref = TransactionRef::from_bytes_with_options(&raw, options).unwrap();
+        let tx = Transaction::try_from(&tx_ref).unwrap();
+        assert_eq!(tx.to_bytes(), raw);
+    }
+}